@@ -1622,7 +1622,7 @@ fn _get_timestamp(output_option: &OutputOption, time: &DateTime<Utc>) -> i64 {
     }
 }
 
-/// json出力の際に配列として対応させるdetails,MitreTactics,MitreTags,OtherTagsに該当する場合に配列を返す関数
+/// json出力の際に配列として対応させるdetails,MitreTactics,MitreTags,OtherTags,MatchReasonに該当する場合に配列を返す関数
 fn _get_json_vec(profile: &Profile, target_data: &String) -> Vec<String> {
     match profile {
         Profile::MitreTactics(_) | Profile::MitreTags(_) | Profile::OtherTags(_) => {
@@ -1636,6 +1636,9 @@ fn _get_json_vec(profile: &Profile, target_data: &String) -> Vec<String> {
                 ret
             }
         }
+        Profile::MatchReason(_) if target_data != "-" => {
+            target_data.split(" ¦ ").map(|x| x.to_string()).collect()
+        }
         _ => vec![],
     }
 }
@@ -2038,6 +2041,26 @@ pub fn output_json_str(
                         4,
                     ));
                 }
+                Profile::MatchReason(_) => {
+                    let key = _convert_valid_json_str(&[key.as_str()], false);
+                    // ルールの値にはバックスラッシュやダブルクォートが含まれるので、JSONの文字列としてエスケープする
+                    let values = vec_data
+                        .iter()
+                        .map(|x| serde_json::to_string(x).unwrap_or_default())
+                        .collect::<Vec<_>>();
+                    let fmted_val = if jsonl_output_flag {
+                        format!("[{}]", values.join(", "))
+                    } else {
+                        format!("[\n        {}\n    ]", values.join(",\n        "))
+                    };
+                    target.push(_create_json_output_format(
+                        &key,
+                        &fmted_val,
+                        key.starts_with('\"'),
+                        true,
+                        4,
+                    ));
+                }
                 _ => {}
             }
         }
@@ -2243,6 +2266,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -2282,6 +2306,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -2295,6 +2320,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("./test_emit_csv.csv").to_path_buf()),
@@ -2334,6 +2365,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -2373,6 +2405,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -2386,6 +2419,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             };
             let ch = mock_ch_filter
                 .get(&CompactString::from("security"))
@@ -2579,6 +2618,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: Some("verbose-2".to_string()),
                 enable_deprecated_rules: false,
@@ -2618,6 +2658,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -2631,6 +2672,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("./test_emit_csv_multiline.csv").to_path_buf()),
@@ -2680,6 +2727,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: Some("verbose-2".to_string()),
                 enable_deprecated_rules: false,
@@ -2719,6 +2767,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -2732,6 +2781,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             };
             let ch = mock_ch_filter
                 .get(&CompactString::from("security"))
@@ -2909,6 +2964,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -2948,6 +3004,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -2961,6 +3018,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("./test_emit_csv_remove_duplicate.csv").to_path_buf()),
@@ -3000,6 +3063,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -3039,6 +3103,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -3052,6 +3117,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             };
             let ch = mock_ch_filter
                 .get(&CompactString::from("security"))
@@ -3240,6 +3311,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -3279,6 +3351,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -3292,6 +3365,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("./test_emit_csv_remove_duplicate.json").to_path_buf()),
@@ -3331,6 +3410,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -3370,6 +3450,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -3383,6 +3464,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             };
             let ch = mock_ch_filter
                 .get(&CompactString::from("security"))
@@ -3644,6 +3731,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -3683,6 +3771,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -3696,6 +3785,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("./test_multiple_data_in_details.json").to_path_buf()),
@@ -3736,6 +3831,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -3775,6 +3871,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -3788,6 +3885,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             };
             let ch = mock_ch_filter
                 .get(&CompactString::from("security"))
@@ -3994,6 +4097,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -4033,6 +4137,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -4046,6 +4151,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("./test_emit_csv_json.json").to_path_buf()),
@@ -4086,6 +4197,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -4125,6 +4237,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -4138,6 +4251,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             };
             let ch = mock_ch_filter
                 .get(&CompactString::from("security"))
@@ -4269,6 +4388,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -4308,6 +4428,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -4321,6 +4442,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("./test_emit_csv_jsonl.jsonl").to_path_buf()),
@@ -4361,6 +4488,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -4400,6 +4528,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -4413,6 +4542,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             };
             let ch = mock_ch_filter
                 .get(&CompactString::from("security"))
@@ -0,0 +1,389 @@
+use crate::detections::utils::is_stdin_path;
+use flate2::read::MultiGzDecoder;
+use hashbrown::HashSet;
+use lazy_static::lazy_static;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tar::{Archive, Entry, EntryType};
+use zip::ZipArchive;
+
+/// アーカイブのパスとアーカイブ内のメンバーのパスの区切り文字(例: archive.zip!/path/Security.evtx)
+pub const ARCHIVE_SEPARATOR: &str = "!/";
+
+/// アーカイブ内のメンバーの展開後のサイズの上限のデフォルト値(4GB)
+pub const DEFAULT_MAX_MEMBER_SIZE: u64 = 4 * (1 << 30);
+
+static MAX_MEMBER_SIZE: AtomicU64 = AtomicU64::new(DEFAULT_MAX_MEMBER_SIZE);
+
+lazy_static! {
+    // tar.gzはシークできないので、メンバーを順番に読み込む場合に先頭から展開し直さないように、前回読み込んだメンバーの次の位置のストリームを保持する
+    static ref TAR_STREAM: Mutex<Option<(PathBuf, CountingReader)>> = Mutex::new(None);
+}
+
+/// EvtxParserに渡すための、ファイルとアーカイブのメンバーの両方を扱えるtrait
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+#[derive(Debug, PartialEq)]
+enum ArchiveType {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn get_archive_type(path: &Path) -> Option<ArchiveType> {
+    let filename = path.file_name()?.to_str()?.to_lowercase();
+    if filename.ends_with(".zip") {
+        Some(ArchiveType::Zip)
+    } else if filename.ends_with(".tar") {
+        Some(ArchiveType::Tar)
+    } else if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+        Some(ArchiveType::TarGz)
+    } else {
+        None
+    }
+}
+
+/// 拡張子が.zip, .tar, .tar.gz(.tgz)のファイルの場合にtrueを返す
+pub fn is_archive(path: &Path) -> bool {
+    get_archive_type(path).is_some()
+}
+
+/// アーカイブ内のメンバーの展開後のサイズの上限を設定する関数
+pub fn set_max_member_size(size: u64) {
+    MAX_MEMBER_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// archive.zip!/path/Security.evtxのようなアーカイブのメンバーのパスを、アーカイブのパスとメンバーのパスに分割する。
+/// アーカイブのメンバーのパスではない場合はNoneを返す
+pub fn split_member_path(path: &Path) -> Option<(PathBuf, String)> {
+    let path_str = path.to_str()?;
+    path_str
+        .match_indices(ARCHIVE_SEPARATOR)
+        .map(|(idx, _)| path_str.split_at(idx))
+        .find(|(archive, _)| is_archive(Path::new(archive)))
+        .map(|(archive, member)| {
+            (
+                PathBuf::from(archive),
+                member[ARCHIVE_SEPARATOR.len()..].to_string(),
+            )
+        })
+}
+
+/// アーカイブ内の、target_extensionsで指定された拡張子を持つメンバーのパスの一覧を返す。隠しファイルは対象外とする
+pub fn collect_archive_members(
+    archive_path: &Path,
+    target_extensions: &HashSet<String>,
+) -> io::Result<Vec<PathBuf>> {
+    let names = match get_archive_type(archive_path) {
+        Some(ArchiveType::Zip) => {
+            let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+            let mut names = vec![];
+            for idx in 0..archive.len() {
+                let member = archive.by_index_raw(idx)?;
+                if member.is_file() {
+                    names.push(member.name().to_string());
+                }
+            }
+            names
+        }
+        Some(_) => {
+            let mut names = vec![];
+            let mut archive = Archive::new(open_tar(archive_path)?);
+            for entry in archive.entries()? {
+                let entry = entry?;
+                if is_tar_file(&entry) {
+                    names.push(tar_entry_name(&entry)?);
+                }
+            }
+            names
+        }
+        None => vec![],
+    };
+    let ret = names
+        .into_iter()
+        .filter(|name| {
+            let member = Path::new(name);
+            target_extensions.contains(
+                member
+                    .extension()
+                    .unwrap_or_else(|| OsStr::new(""))
+                    .to_str()
+                    .unwrap_or_default(),
+            ) && !member
+                .file_stem()
+                .unwrap_or_else(|| OsStr::new("."))
+                .to_str()
+                .unwrap_or_default()
+                .starts_with('.')
+        })
+        .map(|name| {
+            PathBuf::from(format!(
+                "{}{ARCHIVE_SEPARATOR}{name}",
+                archive_path.display()
+            ))
+        })
+        .collect();
+    Ok(ret)
+}
+
+/// ファイルを開く。アーカイブのメンバーのパスの場合は、メンバーを一時ファイルに展開して読み込む
+pub fn open_file(path: &Path) -> io::Result<Box<dyn ReadSeek + Send>> {
+    // 標準入力はシークできないので、メモリ上に全て読み込む(evtxファイルを-f -で読み込む場合)
+    if is_stdin_path(path) {
+        let mut data = vec![];
+        io::stdin().lock().read_to_end(&mut data)?;
+        return Ok(Box::new(Cursor::new(data)));
+    }
+    match split_member_path(path) {
+        Some((archive_path, member)) => {
+            let file = if get_archive_type(&archive_path) == Some(ArchiveType::Zip) {
+                let mut archive = ZipArchive::new(File::open(&archive_path)?)?;
+                let mut member_file = archive
+                    .by_name(&member)
+                    .map_err(|_| not_found_error(&archive_path, &member))?;
+                extract_to_tempfile(&mut member_file, &member, max_member_size())?
+            } else {
+                extract_tar_member(&archive_path, &member)?
+            };
+            Ok(Box::new(file))
+        }
+        None => Ok(Box::new(File::open(path)?)),
+    }
+}
+
+/// ファイルのサイズを返す。アーカイブのメンバーの場合はアーカイブのファイルのサイズを返す
+pub fn file_size(path: &Path) -> io::Result<u64> {
+    // 標準入力はファイルサイズを事前に取得できないため0とする
+    if is_stdin_path(path) {
+        return Ok(0);
+    }
+    match split_member_path(path) {
+        Some((archive_path, _)) => Ok(archive_path.metadata()?.len()),
+        None => Ok(path.metadata()?.len()),
+    }
+}
+
+fn max_member_size() -> u64 {
+    MAX_MEMBER_SIZE.load(Ordering::Relaxed)
+}
+
+/// メンバーを一時ファイルに展開する。evtxファイルの読み込みにはシークが必要なため、メモリ上ではなく一時ファイルに書き出す。
+/// zip bomb等でディスクを使い果たさないように、展開後のサイズが上限を超えた場合はエラーを返す
+fn extract_to_tempfile(reader: &mut impl Read, member: &str, max_size: u64) -> io::Result<File> {
+    let mut file = tempfile::tempfile()?;
+    let size = io::copy(&mut reader.take(max_size.saturating_add(1)), &mut file)?;
+    if size > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{member} exceeds the maximum member size of {max_size} bytes."),
+        ));
+    }
+    file.rewind()?;
+    Ok(file)
+}
+
+fn extract_tar_member(archive_path: &Path, member: &str) -> io::Result<File> {
+    let mut tar_stream = TAR_STREAM.lock().unwrap();
+    // 前回読み込んだメンバーの続きから探し、見つからない場合は先頭から探し直す
+    for is_reopen in [false, true] {
+        let is_cached = matches!(tar_stream.as_ref(), Some((path, _)) if path == archive_path);
+        if !is_cached || is_reopen {
+            *tar_stream = Some((archive_path.to_path_buf(), open_tar(archive_path)?));
+        }
+        let reader = &mut tar_stream.as_mut().unwrap().1;
+        match find_tar_member(reader, member) {
+            Ok(Some(file)) => return Ok(file),
+            Ok(None) if is_cached && !is_reopen => continue,
+            Ok(None) => break,
+            Err(e) => {
+                *tar_stream = None;
+                return Err(e);
+            }
+        }
+    }
+    *tar_stream = None;
+    Err(not_found_error(archive_path, member))
+}
+
+/// ストリームの現在の位置からメンバーを探して一時ファイルに展開する。
+/// 次の呼び出しで続きのメンバーから探せるように、展開後は次のヘッダーの位置まで読み進める
+fn find_tar_member(reader: &mut CountingReader, member: &str) -> io::Result<Option<File>> {
+    let mut found = None;
+    for entry in Archive::new(&mut *reader).entries()? {
+        let mut entry = entry?;
+        if is_tar_file(&entry) && tar_entry_name(&entry)? == member {
+            found = Some(extract_to_tempfile(&mut entry, member, max_member_size())?);
+            break;
+        }
+    }
+    // メンバーのデータは512バイト単位になるように末尾が0で埋められている
+    let padding = (512 - reader.count % 512) % 512;
+    io::copy(&mut reader.take(padding), &mut io::sink())?;
+    Ok(found)
+}
+
+fn open_tar(path: &Path) -> io::Result<CountingReader> {
+    let file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read + Send> = if get_archive_type(path) == Some(ArchiveType::TarGz) {
+        Box::new(MultiGzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    Ok(CountingReader { reader, count: 0 })
+}
+
+/// ディレクトリやリンク等のファイル以外のメンバーは対象外とする
+fn is_tar_file<R: Read>(entry: &Entry<R>) -> bool {
+    matches!(
+        entry.header().entry_type(),
+        EntryType::Regular | EntryType::Continuous
+    )
+}
+
+/// GNU形式やpax形式の長いパスも含めたメンバーのパスを返す
+fn tar_entry_name<R: Read>(entry: &Entry<R>) -> io::Result<String> {
+    Ok(entry
+        .path()?
+        .to_string_lossy()
+        .trim_start_matches("./")
+        .to_string())
+}
+
+/// tarのストリームの先頭から読み込んだバイト数を数えるリーダー
+struct CountingReader {
+    reader: Box<dyn Read + Send>,
+    count: u64,
+}
+
+impl Read for CountingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+}
+
+fn not_found_error(archive_path: &Path, member: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "{member} was not found in the archive. [archive:{}]",
+            archive_path.display()
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archive::{
+        collect_archive_members, extract_to_tempfile, file_size, is_archive, open_file,
+        split_member_path,
+    };
+    use hashbrown::HashSet;
+    use std::fs;
+    use std::io::{Cursor, Read};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_split_member_path() {
+        assert!(is_archive(Path::new("test_files/archive/logs.tar.gz")));
+        assert!(!is_archive(Path::new("test_files/evtx/test.jsonl")));
+        assert_eq!(
+            split_member_path(Path::new("dir!/logs.zip!/logs/Security.evtx")),
+            Some((
+                PathBuf::from("dir!/logs.zip"),
+                "logs/Security.evtx".to_string()
+            ))
+        );
+        assert_eq!(
+            split_member_path(Path::new("test_files/evtx/test.jsonl")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_collect_archive_members() {
+        let target_extensions = HashSet::from(["jsonl".to_string()]);
+        let members =
+            collect_archive_members(Path::new("test_files/archive/logs.zip"), &target_extensions)
+                .unwrap();
+        assert_eq!(
+            members,
+            vec![PathBuf::from(
+                "test_files/archive/logs.zip!/logs/test.jsonl"
+            )]
+        );
+
+        let members = collect_archive_members(
+            Path::new("test_files/archive/logs.tar.gz"),
+            &target_extensions,
+        )
+        .unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(
+            members[1],
+            PathBuf::from(format!(
+                "test_files/archive/logs.tar.gz!/logs/{}/test.jsonl",
+                "a".repeat(120)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_open_file() {
+        let expect = fs::read("test_files/evtx/test.jsonl").unwrap();
+        for path in [
+            "test_files/archive/logs.zip!/logs/test.jsonl",
+            "test_files/archive/logs.tar.gz!/logs/test.jsonl",
+        ] {
+            let mut data = vec![];
+            open_file(Path::new(path))
+                .unwrap()
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(data, expect);
+        }
+        let mut data = String::default();
+        open_file(Path::new("test_files/archive/logs.tar.gz!/logs/readme.txt"))
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "not a log");
+        // 前回読み込んだメンバーより後ろのメンバーは、続きから読み込めること
+        let mut data = vec![];
+        open_file(Path::new(&format!(
+            "test_files/archive/logs.tar.gz!/logs/{}/test.jsonl",
+            "a".repeat(120)
+        )))
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+        assert_eq!(data, expect);
+        assert!(open_file(Path::new("test_files/archive/logs.zip!/notfound.evtx")).is_err());
+        assert_eq!(
+            file_size(Path::new("test_files/archive/logs.zip!/logs/test.jsonl")).unwrap(),
+            fs::metadata("test_files/archive/logs.zip").unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_extract_to_tempfile() {
+        let mut data = vec![];
+        extract_to_tempfile(&mut Cursor::new(b"0123456789"), "test.jsonl", 10)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, b"0123456789");
+        // 展開後のサイズが上限を超える場合はErr
+        let r = extract_to_tempfile(&mut Cursor::new(b"0123456789"), "test.jsonl", 9);
+        assert_eq!(
+            r.unwrap_err().to_string(),
+            "test.jsonl exceeds the maximum member size of 9 bytes."
+        );
+    }
+}
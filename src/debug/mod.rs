@@ -1 +1,2 @@
 pub mod checkpoint_process_timer;
+pub mod rule_profiler;
@@ -0,0 +1,191 @@
+use std::cell::Cell;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    pub static ref RULE_PROFILER: Mutex<RuleProfiler> = Mutex::new(RuleProfiler::default());
+}
+
+// 計測していない場合に時間計測のオーバーヘッドが発生しないように、計測の有無をフラグで管理する
+static IS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // 1つのルールの判定は1つのスレッド内で行われるので、正規表現の判定時間はスレッド毎に集計する
+    static REGEX_ELAPSED: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// ルール毎の計測を有効にする関数
+pub fn enable() {
+    IS_ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// ルール毎の計測が有効かを返す関数
+pub fn is_enabled() -> bool {
+    IS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// 現在のスレッドで正規表現の判定にかかった時間を加算する関数
+pub fn add_regex_time(elapsed: Duration) {
+    REGEX_ELAPSED.with(|e| e.set(e.get() + elapsed));
+}
+
+/// 現在のスレッドで集計した正規表現の判定時間を取得し、集計をリセットする関数
+pub fn take_regex_time() -> Duration {
+    REGEX_ELAPSED.with(|e| e.replace(Duration::ZERO))
+}
+
+/// 1つのルールの計測結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleProfile {
+    pub title: String,
+    pub eval_time: Duration,
+    pub record_cnt: u64,
+    pub match_cnt: u64,
+    pub regex_time: Duration,
+}
+
+/// ルールのパス毎に判定時間、判定したレコード数、検知数、正規表現の判定時間を集計する構造体
+#[derive(Debug, Default)]
+pub struct RuleProfiler {
+    profiles: HashMap<String, RuleProfile>,
+}
+
+impl RuleProfiler {
+    /// ルールの計測結果を加算する関数
+    pub fn add(&mut self, rulepath: &str, title: &str, profile: &RuleProfile) {
+        let total = self
+            .profiles
+            .entry(rulepath.to_string())
+            .or_insert_with(|| RuleProfile {
+                title: title.to_string(),
+                ..Default::default()
+            });
+        total.eval_time += profile.eval_time;
+        total.record_cnt += profile.record_cnt;
+        total.match_cnt += profile.match_cnt;
+        total.regex_time += profile.regex_time;
+    }
+
+    /// 判定時間が長い順にソートした計測結果を返す関数
+    pub fn get_sorted_profiles(&self) -> Vec<(&String, &RuleProfile)> {
+        let mut ret: Vec<_> = self.profiles.iter().collect();
+        ret.sort_by(|(a_path, a), (b_path, b)| {
+            b.eval_time
+                .cmp(&a.eval_time)
+                .then_with(|| a_path.cmp(b_path))
+        });
+        ret
+    }
+
+    /// 判定時間が長い上位top_n件のルールを出力する関数
+    pub fn output_top(&self, top_n: usize) {
+        println!("Top {top_n} slowest rules:");
+        for (i, (rulepath, profile)) in self.get_sorted_profiles().iter().take(top_n).enumerate() {
+            println!(
+                "{}. {} ({}) - Time: {} / Records: {} / Matches: {} / Regex time: {}",
+                i + 1,
+                profile.title,
+                rulepath,
+                format_millis(profile.eval_time),
+                profile.record_cnt,
+                profile.match_cnt,
+                format_millis(profile.regex_time)
+            );
+        }
+        println!();
+    }
+
+    /// 全てのルールの計測結果をCSV形式で保存する関数
+    pub fn write_csv(&self, path: &Path) -> Result<(), String> {
+        let mut wtr = csv::Writer::from_path(path).map_err(|e| e.to_string())?;
+        wtr.write_record([
+            "Title",
+            "RulePath",
+            "EvalTime(ms)",
+            "Records",
+            "Matches",
+            "RegexTime(ms)",
+        ])
+        .map_err(|e| e.to_string())?;
+        for (rulepath, profile) in self.get_sorted_profiles() {
+            wtr.write_record([
+                profile.title.as_str(),
+                rulepath.as_str(),
+                &as_millis_str(profile.eval_time),
+                &profile.record_cnt.to_string(),
+                &profile.match_cnt.to_string(),
+                &as_millis_str(profile.regex_time),
+            ])
+            .map_err(|e| e.to_string())?;
+        }
+        wtr.flush().map_err(|e| e.to_string())
+    }
+}
+
+fn as_millis_str(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+fn format_millis(duration: Duration) -> String {
+    format!("{}ms", as_millis_str(duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{read_to_string, remove_file};
+    use std::path::Path;
+    use std::time::Duration;
+
+    use crate::debug::rule_profiler::{add_regex_time, take_regex_time, RuleProfile, RuleProfiler};
+
+    #[test]
+    fn test_take_regex_time() {
+        take_regex_time();
+        add_regex_time(Duration::from_millis(3));
+        add_regex_time(Duration::from_millis(4));
+        assert_eq!(take_regex_time(), Duration::from_millis(7));
+        assert_eq!(take_regex_time(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_rule_profiler() {
+        let mut profiler = RuleProfiler::default();
+        let profile = |eval_ms, record_cnt, match_cnt| RuleProfile {
+            title: String::default(),
+            eval_time: Duration::from_millis(eval_ms),
+            record_cnt,
+            match_cnt,
+            regex_time: Duration::from_millis(1),
+        };
+        profiler.add("fast.yml", "fast rule", &profile(2, 10, 0));
+        profiler.add("slow.yml", "slow rule", &profile(5, 10, 1));
+        profiler.add("fast.yml", "fast rule", &profile(2, 5, 2));
+
+        let sorted = profiler.get_sorted_profiles();
+        assert_eq!(sorted.len(), 2);
+        assert_eq!(sorted[0].0, "slow.yml");
+        assert_eq!(
+            sorted[1].1,
+            &RuleProfile {
+                title: "fast rule".to_string(),
+                eval_time: Duration::from_millis(4),
+                record_cnt: 15,
+                match_cnt: 2,
+                regex_time: Duration::from_millis(2),
+            }
+        );
+
+        let csv_path = Path::new("./test_rule_profiling.csv");
+        profiler.write_csv(csv_path).unwrap();
+        assert_eq!(
+            read_to_string(csv_path).unwrap(),
+            "Title,RulePath,EvalTime(ms),Records,Matches,RegexTime(ms)\nslow rule,slow.yml,5.000,10,1,1.000\nfast rule,fast.yml,4.000,15,2,2.000\n"
+        );
+        remove_file(csv_path).ok();
+    }
+}
@@ -1,5 +1,9 @@
 use crate::detections::field_data_map::{create_field_data_map, FieldDataMap};
 use crate::detections::message::AlertMessage;
+use crate::detections::pipeline::{
+    create_logsource_mappings, create_pipelines, LogsourceMapping, Pipeline,
+};
+use crate::detections::placeholder::{create_placeholder_map, PlaceholderMap};
 use crate::detections::utils;
 use crate::options::geoip_search::GeoIPSearch;
 use crate::options::htmlreport;
@@ -87,10 +91,15 @@ pub struct StoredStatic {
     pub exclude_eid: HashSet<CompactString>,
     pub include_status: HashSet<CompactString>, // 読み込み対象ルールのステータスのセット。*はすべてのステータスを読み込む
     pub field_data_map: Option<FieldDataMap>,
+    pub placeholders: PlaceholderMap,
+    pub pipelines: Vec<Pipeline>,
+    pub logsource_mappings: Vec<LogsourceMapping>,
     pub no_pwsh_field_extraction: bool,
     pub enable_recover_records: bool,
     pub timeline_offset: Option<String>,
+    pub max_archive_member_size: Option<u64>,
     pub is_low_memory: bool,
+    pub follow_flag: bool,
 }
 impl StoredStatic {
     /// main.rsでパースした情報からデータを格納する関数
@@ -120,6 +129,12 @@ impl StoredStatic {
             Some(Action::UpdateRules(opt)) => opt.common_options,
             Some(Action::Search(opt)) => opt.common_options,
             Some(Action::ComputerMetrics(opt)) => opt.common_options,
+            // JSON形式で出力する場合はロゴ等を出力しないようにする
+            Some(Action::ValidateRules(opt)) => CommonOptions {
+                quiet: opt.common_options.quiet || opt.json_output,
+                ..opt.common_options
+            },
+            Some(Action::TestRules(opt)) => opt.common_options,
             None => CommonOptions {
                 no_color: false,
                 quiet: false,
@@ -135,6 +150,8 @@ impl StoredStatic {
             Some(Action::PivotKeywordsList(opt)) => &opt.detect_common_options.config,
             Some(Action::Search(opt)) => &opt.config,
             Some(Action::ComputerMetrics(opt)) => &opt.config,
+            Some(Action::ValidateRules(opt)) => &opt.config,
+            Some(Action::TestRules(opt)) => &opt.config,
             _ => &binding,
         };
         let verbose_flag = match &input_config.as_ref().unwrap().action {
@@ -504,6 +521,45 @@ impl StoredStatic {
             ))
         };
 
+        let placeholders = create_placeholder_map(
+            check_setting_path(config_path, "placeholders", false)
+                .unwrap_or_else(|| {
+                    check_setting_path(
+                        &CURRENT_EXE_PATH.to_path_buf(),
+                        "rules/config/placeholders",
+                        true,
+                    )
+                    .unwrap()
+                })
+                .as_path(),
+        );
+
+        let pipelines = create_pipelines(
+            check_setting_path(config_path, "pipelines", false)
+                .unwrap_or_else(|| {
+                    check_setting_path(
+                        &CURRENT_EXE_PATH.to_path_buf(),
+                        "rules/config/pipelines",
+                        true,
+                    )
+                    .unwrap()
+                })
+                .as_path(),
+        );
+
+        let logsource_mappings = create_logsource_mappings(
+            check_setting_path(config_path, "logsource_mapping.yaml", false)
+                .unwrap_or_else(|| {
+                    check_setting_path(
+                        &CURRENT_EXE_PATH.to_path_buf(),
+                        "rules/config/logsource_mapping.yaml",
+                        true,
+                    )
+                    .unwrap()
+                })
+                .as_path(),
+        );
+
         let no_pwsh_field_extraction_flag = match &input_config.as_ref().unwrap().action {
             Some(Action::CsvTimeline(opt)) => opt.output_options.no_pwsh_field_extraction,
             Some(Action::JsonTimeline(opt)) => opt.output_options.no_pwsh_field_extraction,
@@ -531,6 +587,18 @@ impl StoredStatic {
             Some(Action::ComputerMetrics(opt)) => opt.input_args.timeline_offset.clone(),
             _ => None,
         };
+        let max_archive_member_size = match &input_config.as_ref().unwrap().action {
+            Some(Action::CsvTimeline(opt)) => opt.output_options.input_args.max_archive_member_size,
+            Some(Action::JsonTimeline(opt)) => {
+                opt.output_options.input_args.max_archive_member_size
+            }
+            Some(Action::EidMetrics(opt)) => opt.input_args.max_archive_member_size,
+            Some(Action::LogonSummary(opt)) => opt.input_args.max_archive_member_size,
+            Some(Action::PivotKeywordsList(opt)) => opt.input_args.max_archive_member_size,
+            Some(Action::Search(opt)) => opt.input_args.max_archive_member_size,
+            Some(Action::ComputerMetrics(opt)) => opt.input_args.max_archive_member_size,
+            _ => None,
+        };
         let include_status: HashSet<CompactString> = match &input_config.as_ref().unwrap().action {
             Some(Action::CsvTimeline(opt)) => opt
                 .output_options
@@ -557,11 +625,17 @@ impl StoredStatic {
                 .collect(),
             _ => HashSet::default(),
         };
+        let follow_flag = match &input_config.as_ref().unwrap().action {
+            Some(Action::CsvTimeline(opt)) => opt.output_options.follow,
+            Some(Action::JsonTimeline(opt)) => opt.output_options.follow,
+            _ => false,
+        };
+        // --followの場合は検知結果を随時出力するため、低メモリモードと同様にソートせずに出力する
         let is_low_memory = match &input_config.as_ref().unwrap().action {
             Some(Action::CsvTimeline(opt)) => opt.output_options.low_memory_mode,
             Some(Action::JsonTimeline(opt)) => opt.output_options.low_memory_mode,
             _ => false,
-        };
+        } || follow_flag;
         let mut ret = StoredStatic {
             config: input_config.as_ref().unwrap().to_owned(),
             config_path: config_path.to_path_buf(),
@@ -673,11 +747,16 @@ impl StoredStatic {
             include_eid,
             exclude_eid,
             field_data_map,
+            placeholders,
+            pipelines,
+            logsource_mappings,
             no_pwsh_field_extraction: no_pwsh_field_extraction_flag,
             enable_recover_records,
             timeline_offset,
+            max_archive_member_size,
             include_status,
             is_low_memory,
+            follow_flag,
         };
         ret.profiles = load_profile(
             check_setting_path(
@@ -879,6 +958,26 @@ pub enum Action {
     )]
     /// Print computer name metrics
     ComputerMetrics(ComputerMetricsOption),
+
+    #[clap(
+        author = "Yamato Security (https://github.com/Yamato-Security/hayabusa - @SecurityYamato)",
+        help_template = "\nHayabusa v2.15.0 - Sonic Release\n{author-with-newline}\n{usage-heading}\n  {usage}\n\n{all-args}",
+        term_width = 400,
+        display_order = 471,
+        disable_help_flag = true
+    )]
+    /// Validate rules and report problems (default: ./rules)
+    ValidateRules(ValidateRulesOption),
+
+    #[clap(
+        author = "Yamato Security (https://github.com/Yamato-Security/hayabusa - @SecurityYamato)",
+        help_template = "\nHayabusa v2.15.0 - Sonic Release\n{author-with-newline}\n{usage-heading}\n  {usage}\n\n{all-args}",
+        term_width = 400,
+        display_order = 472,
+        disable_help_flag = true
+    )]
+    /// Run the test cases written in the tests field of rules (default: ./rules)
+    TestRules(TestRulesOption),
 }
 
 impl Action {
@@ -897,6 +996,8 @@ impl Action {
                 Action::ListProfiles(_) => 9,
                 Action::Search(_) => 10,
                 Action::ComputerMetrics(_) => 11,
+                Action::ValidateRules(_) => 12,
+                Action::TestRules(_) => 13,
             }
         } else {
             100
@@ -917,6 +1018,8 @@ impl Action {
                 Action::ListProfiles(_) => "list-profiles",
                 Action::Search(_) => "search",
                 Action::ComputerMetrics(_) => "computer-metrics",
+                Action::ValidateRules(_) => "validate-rules",
+                Action::TestRules(_) => "test-rules",
             }
         } else {
             ""
@@ -1153,6 +1256,70 @@ pub struct UpdateOption {
     pub rules: PathBuf,
 }
 
+#[derive(Args, Clone, Debug)]
+pub struct ValidateRulesOption {
+    #[clap(flatten)]
+    pub common_options: CommonOptions,
+
+    /// Specify a custom rule directory or file (default: ./rules)
+    #[arg(
+        help_heading = Some("General Options"),
+        short = 'r',
+        long,
+        default_value = "./rules",
+        hide_default_value = true,
+        value_name = "DIR/FILE",
+        display_order = 441
+    )]
+    pub rules: PathBuf,
+
+    /// Specify custom rule config directory (default: ./rules/config)
+    #[arg(
+        help_heading = Some("General Options"),
+        short = 'c',
+        long = "rules-config",
+        default_value = "./rules/config",
+        hide_default_value = true,
+        value_name = "DIR",
+        display_order = 442
+    )]
+    pub config: PathBuf,
+
+    /// Output the results in JSON format
+    #[arg(help_heading = Some("Output"), short = 'J', long = "JSON-output", display_order = 100)]
+    pub json_output: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct TestRulesOption {
+    #[clap(flatten)]
+    pub common_options: CommonOptions,
+
+    /// Specify a custom rule directory or file (default: ./rules)
+    #[arg(
+        help_heading = Some("General Options"),
+        short = 'r',
+        long,
+        default_value = "./rules",
+        hide_default_value = true,
+        value_name = "DIR/FILE",
+        display_order = 441
+    )]
+    pub rules: PathBuf,
+
+    /// Specify custom rule config directory (default: ./rules/config)
+    #[arg(
+        help_heading = Some("General Options"),
+        short = 'c',
+        long = "rules-config",
+        default_value = "./rules/config",
+        hide_default_value = true,
+        value_name = "DIR",
+        display_order = 442
+    )]
+    pub config: PathBuf,
+}
+
 #[derive(Args, Clone, Debug)]
 pub struct LevelTuningOption {
     #[clap(flatten)]
@@ -1522,6 +1689,10 @@ pub struct OutputOption {
     )]
     pub rules: PathBuf,
 
+    /// Apply sigma filter rules in a directory or file to exclude detections (ex: ./filters)
+    #[arg(help_heading = Some("Filtering"), long = "filter-rules", value_name = "DIR/FILE", display_order = 317)]
+    pub filter_rules: Option<PathBuf>,
+
     /// Save Results Summary details to an HTML report (ex: results.html)
     #[arg(help_heading = Some("Output"), short = 'H', long="HTML-report", conflicts_with = "no_summary", value_name = "FILE", display_order = 80, requires = "output")]
     pub html_report: Option<PathBuf>,
@@ -1563,6 +1734,30 @@ pub struct OutputOption {
     /// Scan with the minimal amount of memory by not sorting events
     #[arg(help_heading = Some("General Options"), short='s', long = "low-memory-mode", display_order = 380)]
     pub low_memory_mode: bool,
+
+    /// Print the N slowest rules with their evaluation time and match counts (default: 10)
+    #[arg(help_heading = Some("Display Settings"), long = "rule-profiling", value_name = "NUMBER", num_args = 0..=1, default_missing_value = "10", display_order = 470)]
+    pub rule_profiling: Option<usize>,
+
+    /// Save the rule profiling results in CSV format (ex: rule-profiling.csv)
+    #[arg(help_heading = Some("Output"), long = "rule-profiling-output", value_name = "FILE", requires = "rule_profiling", display_order = 450)]
+    pub rule_profiling_output: Option<PathBuf>,
+
+    /// Maximum compiled size of each regex in rules in bytes (default: 10485760)
+    #[arg(help_heading = Some("General Options"), long = "regex-size-limit", value_name = "BYTES", display_order = 410)]
+    pub regex_size_limit: Option<usize>,
+
+    /// Maximum DFA cache size of each regex in rules in bytes (default: 2097152)
+    #[arg(help_heading = Some("General Options"), long = "regex-dfa-size-limit", value_name = "BYTES", display_order = 411)]
+    pub regex_dfa_size_limit: Option<usize>,
+
+    /// Keep watching the directory and scan new or appended records
+    #[arg(help_heading = Some("General Options"), long = "follow", requires = "directory", conflicts_with_all = ["remove_duplicate_data", "remove_duplicate_detections"], display_order = 330)]
+    pub follow: bool,
+
+    /// Cache the parsed rules in ./cache/rules.json to speed up startup
+    #[arg(help_heading = Some("General Options"), long = "rule-cache", display_order = 412)]
+    pub rule_cache: bool,
 }
 
 #[derive(Copy, Args, Clone, Debug)]
@@ -1587,7 +1782,7 @@ pub struct InputOption {
     #[arg(help_heading = Some("Input"), short = 'd', long, value_name = "DIR", conflicts_with_all = ["filepath", "live_analysis"], display_order = 300)]
     pub directory: Option<PathBuf>,
 
-    /// File path to one .evtx file
+    /// File path to one .evtx file (use - to read from standard input)
     #[arg(help_heading = Some("Input"), short = 'f', long = "file", value_name = "FILE", conflicts_with_all = ["directory", "live_analysis"], display_order = 320)]
     pub filepath: Option<PathBuf>,
 
@@ -1602,6 +1797,10 @@ pub struct InputOption {
     /// Scan recent events based on an offset (ex: 1y, 3M, 30d, 24h, 30m)
     #[arg(help_heading = Some("Filtering"), long = "timeline-offset", value_name = "OFFSET", conflicts_with = "start_timeline", display_order = 460)]
     pub timeline_offset: Option<String>,
+
+    /// Maximum uncompressed size of each file in .zip/.tar archives in bytes (default: 4294967296)
+    #[arg(help_heading = Some("General Options"), long = "max-archive-member-size", value_name = "BYTES", display_order = 385)]
+    pub max_archive_member_size: Option<u64>,
 }
 
 #[derive(Args, Clone, Debug)]
@@ -1768,6 +1967,10 @@ impl TargetIds {
         }
         self.ids.contains(id)
     }
+
+    pub fn get_ids(&self) -> &HashSet<String> {
+        &self.ids
+    }
 }
 
 fn load_target_ids(path: &str) -> TargetIds {
@@ -2235,6 +2438,7 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             enable_unsupported_rules: option.enable_unsupported_rules,
             clobber: option.clobber,
             proven_rules: false,
+            filter_rules: None,
             include_tag: option.include_tag.clone(),
             exclude_tag: option.exclude_tag.clone(),
             include_category: None,
@@ -2248,6 +2452,12 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             no_wizard: option.no_wizard,
             include_status: option.include_status.clone(),
             low_memory_mode: false,
+            rule_profiling: None,
+            rule_profiling_output: None,
+            regex_size_limit: None,
+            regex_dfa_size_limit: None,
+            follow: false,
+            rule_cache: false,
         }),
         Action::EidMetrics(option) => Some(OutputOption {
             input_args: option.input_args.clone(),
@@ -2276,6 +2486,7 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             enable_unsupported_rules: false,
             clobber: option.clobber,
             proven_rules: false,
+            filter_rules: None,
             include_tag: None,
             exclude_tag: None,
             include_category: None,
@@ -2289,6 +2500,12 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             no_wizard: true,
             include_status: None,
             low_memory_mode: false,
+            rule_profiling: None,
+            rule_profiling_output: None,
+            regex_size_limit: None,
+            regex_dfa_size_limit: None,
+            follow: false,
+            rule_cache: false,
         }),
         Action::LogonSummary(option) => Some(OutputOption {
             input_args: option.input_args.clone(),
@@ -2317,6 +2534,7 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             enable_unsupported_rules: false,
             clobber: option.clobber,
             proven_rules: false,
+            filter_rules: None,
             include_tag: None,
             exclude_tag: None,
             include_category: None,
@@ -2330,6 +2548,12 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             no_wizard: true,
             include_status: None,
             low_memory_mode: false,
+            rule_profiling: None,
+            rule_profiling_output: None,
+            regex_size_limit: None,
+            regex_dfa_size_limit: None,
+            follow: false,
+            rule_cache: false,
         }),
         Action::ComputerMetrics(option) => Some(OutputOption {
             input_args: option.input_args.clone(),
@@ -2348,6 +2572,7 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             start_timeline: None,
             eid_filter: false,
             proven_rules: false,
+            filter_rules: None,
             exclude_tag: None,
             detect_common_options: DetectCommonOption {
                 json_input: option.json_input,
@@ -2380,6 +2605,12 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             no_wizard: true,
             include_status: None,
             low_memory_mode: false,
+            rule_profiling: None,
+            rule_profiling_output: None,
+            regex_size_limit: None,
+            regex_dfa_size_limit: None,
+            follow: false,
+            rule_cache: false,
         }),
         Action::Search(option) => Some(OutputOption {
             input_args: option.input_args.clone(),
@@ -2417,6 +2648,7 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             enable_unsupported_rules: false,
             clobber: option.clobber,
             proven_rules: false,
+            filter_rules: None,
             include_tag: None,
             exclude_tag: None,
             include_category: None,
@@ -2430,6 +2662,12 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             no_wizard: true,
             include_status: None,
             low_memory_mode: false,
+            rule_profiling: None,
+            rule_profiling_output: None,
+            regex_size_limit: None,
+            regex_dfa_size_limit: None,
+            follow: false,
+            rule_cache: false,
         }),
         Action::SetDefaultProfile(option) => Some(OutputOption {
             input_args: InputOption {
@@ -2438,6 +2676,7 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
                 live_analysis: false,
                 recover_records: false,
                 timeline_offset: None,
+                max_archive_member_size: None,
             },
             enable_deprecated_rules: false,
             enable_noisy_rules: false,
@@ -2473,6 +2712,7 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             enable_unsupported_rules: false,
             clobber: false,
             proven_rules: false,
+            filter_rules: None,
             include_tag: None,
             exclude_tag: None,
             include_category: None,
@@ -2486,6 +2726,12 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             no_wizard: true,
             include_status: None,
             low_memory_mode: false,
+            rule_profiling: None,
+            rule_profiling_output: None,
+            regex_size_limit: None,
+            regex_dfa_size_limit: None,
+            follow: false,
+            rule_cache: false,
         }),
         Action::UpdateRules(option) => Some(OutputOption {
             input_args: InputOption {
@@ -2494,6 +2740,7 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
                 live_analysis: false,
                 recover_records: false,
                 timeline_offset: None,
+                max_archive_member_size: None,
             },
             enable_deprecated_rules: true,
             enable_noisy_rules: true,
@@ -2529,6 +2776,7 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             enable_unsupported_rules: true,
             clobber: false,
             proven_rules: false,
+            filter_rules: None,
             include_tag: None,
             exclude_tag: None,
             include_category: None,
@@ -2542,6 +2790,12 @@ fn extract_output_options(config: &Config) -> Option<OutputOption> {
             no_wizard: true,
             include_status: None,
             low_memory_mode: false,
+            rule_profiling: None,
+            rule_profiling_output: None,
+            regex_size_limit: None,
+            regex_dfa_size_limit: None,
+            follow: false,
+            rule_cache: false,
         }),
         _ => None,
     }
@@ -2743,6 +2997,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: Some("1d".to_string()),
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -2782,6 +3037,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -2795,6 +3051,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -2819,6 +3081,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: Some("1y".to_string()),
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -2858,6 +3121,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -2871,6 +3135,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -2900,6 +3170,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: Some("1h".to_string()),
+                    max_archive_member_size: None,
                 },
                 keywords: Some(vec!["mimikatz".to_string()]),
                 regex: None,
@@ -2947,6 +3218,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: Some("1h1m".to_string()),
+                    max_archive_member_size: None,
                 },
                 clobber: true,
                 european_time: false,
@@ -2991,6 +3263,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: Some("1y1d1h".to_string()),
+                    max_archive_member_size: None,
                 },
                 clobber: true,
                 european_time: false,
@@ -3041,6 +3314,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: Some("1y1M1s".to_string()),
+                    max_archive_member_size: None,
                 },
                 clobber: true,
                 detect_common_options: DetectCommonOption {
@@ -5,10 +5,10 @@ use crate::detections::utils::{
     create_recordinfos, format_time, output_profile_name, write_color_buffer,
 };
 use crate::options::profile::Profile::{
-    self, Channel, Computer, EventID, EvtxFile, Level, MitreTactics, MitreTags, OtherTags,
-    Provider, RecordID, RecoveredRecord, RenderedMessage, RuleAuthor, RuleCreationDate, RuleFile,
-    RuleID, RuleModifiedDate, RuleTitle, SrcASN, SrcCity, SrcCountry, Status, TgtASN, TgtCity,
-    TgtCountry, Timestamp,
+    self, Channel, Computer, EventID, EvtxFile, Level, MatchReason, MitreTactics, MitreTags,
+    OtherTags, Provider, RecordID, RecoveredRecord, RenderedMessage, RuleAuthor, RuleCreationDate,
+    RuleFile, RuleID, RuleModifiedDate, RuleTitle, SrcASN, SrcCity, SrcCountry, Status, TgtASN,
+    TgtCity, TgtCountry, Timestamp,
 };
 use chrono::{TimeZone, Utc};
 use compact_str::CompactString;
@@ -19,14 +19,17 @@ use std::default::Default;
 use termcolor::{BufferWriter, Color, ColorChoice};
 use yaml_rust::Yaml;
 
+use crate::debug::rule_profiler::{self, RuleProfile, RULE_PROFILER};
 use crate::detections::message::{AlertMessage, DetectInfo, ERROR_LOG_STACK, TAGS_CONFIG};
+use crate::detections::rule::index::RuleIndex;
 use crate::detections::rule::{self, AggResult, RuleNode};
+use crate::detections::rule_cache;
 use crate::detections::utils::{get_serde_number_to_string, make_ascii_titlecase};
 use crate::filter;
 use crate::options::htmlreport;
 use crate::options::pivot::insert_pivot_keyword;
 use crate::yaml::ParseYaml;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use serde_json::Value;
 use std::fmt::Write;
 use std::path::Path;
@@ -34,6 +37,7 @@ use std::path::Path;
 use crate::detections::configs::STORED_EKEY_ALIAS;
 use crate::detections::field_data_map::FieldDataMapKey;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::{runtime::Runtime, spawn, task::JoinHandle};
 
 use super::configs::{
@@ -60,11 +64,16 @@ impl EvtxRecordInfo {
 #[derive(Debug)]
 pub struct Detection {
     rules: Vec<RuleNode>,
+    rule_index: RuleIndex,
 }
 
 impl Detection {
     pub fn new(rule_nodes: Vec<RuleNode>) -> Detection {
-        Detection { rules: rule_nodes }
+        let rule_index = RuleIndex::new(&rule_nodes);
+        Detection {
+            rules: rule_nodes,
+            rule_index,
+        }
     }
 
     pub fn start(self, rt: &Runtime, records: Vec<EvtxRecordInfo>) -> (Self, Vec<DetectInfo>) {
@@ -81,13 +90,29 @@ impl Detection {
     ) -> Vec<RuleNode> {
         // ルールファイルのパースを実行
         let mut rulefile_loader = ParseYaml::new(stored_static);
-        let result_readdir = rulefile_loader.read_dir(
-            rulespath,
-            min_level,
-            target_level,
-            exclude_ids,
-            stored_static,
-        );
+        // --rule-cacheが指定された場合のみ、キャッシュファイルを読み書きする
+        let result_readdir = if stored_static
+            .output_option
+            .as_ref()
+            .is_some_and(|o| o.rule_cache)
+        {
+            rulefile_loader.read_dir_with_cache(
+                rulespath,
+                min_level,
+                target_level,
+                exclude_ids,
+                stored_static,
+                &rule_cache::default_cache_path(),
+            )
+        } else {
+            rulefile_loader.read_dir(
+                rulespath,
+                min_level,
+                target_level,
+                exclude_ids,
+                stored_static,
+            )
+        };
         if result_readdir.is_err() {
             let errmsg = format!("{}", result_readdir.unwrap_err());
             if stored_static.verbose_flag {
@@ -102,16 +127,50 @@ impl Detection {
             return vec![];
         }
         let mut parseerror_count = rulefile_loader.errorrule_count;
-        let return_if_success = |mut rule: RuleNode| {
-            let err_msgs_result = rule.init(stored_static);
+        let filter_rules = match stored_static
+            .output_option
+            .as_ref()
+            .and_then(|o| o.filter_rules.as_ref())
+        {
+            Some(filter_rules_path) => filter::load_filter_rules(filter_rules_path, stored_static),
+            None => vec![],
+        };
+        let mut return_if_success = |mut rule: RuleNode| {
+            let mut err_msgs_result = rule.init(stored_static);
+            if err_msgs_result.is_ok() {
+                // 対象のフィルタールールのfilterを適用する
+                let target_filter_rules: Vec<&filter::FilterRule> = filter_rules
+                    .iter()
+                    .filter(|f| f.is_target(&rule.yaml))
+                    .collect();
+                for filter_rule in target_filter_rules {
+                    if let Err(err_msgs) =
+                        rule.apply_filter(&filter_rule.yaml["filter"], stored_static)
+                    {
+                        err_msgs_result = Err(err_msgs
+                            .into_iter()
+                            .map(|err_msg| {
+                                format!("{err_msg} (FilterRulePath : {})", filter_rule.filepath)
+                            })
+                            .collect());
+                        break;
+                    }
+                }
+            }
             if err_msgs_result.is_ok() {
                 return Some(rule);
             }
 
             // ruleファイルのパースに失敗した場合はエラー出力
             err_msgs_result.err().iter().for_each(|err_msgs| {
-                let errmsg_body =
-                    format!("Failed to parse rule file. (FilePath : {})", rule.rulepath);
+                // 正規表現のサイズの上限を超えたルール等を特定できるように、ルールのIDも出力する
+                let errmsg_body = match rule.yaml["id"].as_str() {
+                    Some(id) => format!(
+                        "Failed to parse rule file. (FilePath : {}, RuleID : {id})",
+                        rule.rulepath
+                    ),
+                    None => format!("Failed to parse rule file. (FilePath : {})", rule.rulepath),
+                };
                 if stored_static.verbose_flag {
                     AlertMessage::warn(&errmsg_body).ok();
                     err_msgs.iter().for_each(|err_msg| {
@@ -135,12 +194,43 @@ impl Detection {
             });
             None
         };
-        // parse rule files
-        let ret = rulefile_loader
+        // 相関ルールとそれ以外のルールに分ける
+        let (correlation_files, mut rule_files): (Vec<_>, Vec<_>) = rulefile_loader
             .files
             .into_iter()
+            .partition(|(_, yaml)| rule::is_correlation_rule(yaml));
+        // 相関ルールから参照されているルールは、レベルやステータス等の読み込み条件で除外されたルールからも探す。
+        // 除外されたルールは参照先を探すためだけに一時的に追加し、単体のルールとしては読み込まない
+        let rule_files_cnt = rule_files.len();
+        rule_files.extend(rulefile_loader.filtered_files);
+        let correlation_rules: Vec<RuleNode> = correlation_files
+            .into_iter()
+            .map(|(rulepath, yaml)| rule::create_correlation_rule(rulepath, yaml, &rule_files))
+            .filter_map(&mut return_if_success)
+            .collect();
+        rule_files.truncate(rule_files_cnt);
+        // generate: trueが指定されていない相関ルールから参照されているルールは、単体では検知結果を出力しない。
+        // パースに失敗した相関ルールから参照されているルールは、単体のルールとして検知結果を出力する
+        let hidden_rule_refs: HashSet<String> = correlation_rules
+            .iter()
+            .filter(|rule| {
+                !rule.yaml["correlation"]["generate"]
+                    .as_bool()
+                    .unwrap_or(false)
+            })
+            .flat_map(|rule| rule::get_correlation_rule_refs(&rule.yaml))
+            .collect();
+        // parse rule files
+        let ret = rule_files
+            .into_iter()
+            .filter(|(_, yaml)| {
+                !["id", "name"]
+                    .iter()
+                    .any(|key| hidden_rule_refs.contains(yaml[*key].as_str().unwrap_or_default()))
+            })
             .map(|rule_file_tuple| rule::create_rule(rule_file_tuple.0, rule_file_tuple.1))
             .filter_map(return_if_success)
+            .chain(correlation_rules)
             .collect();
         if !(stored_static.logon_summary_flag
             || stored_static.search_flag
@@ -160,16 +250,22 @@ impl Detection {
 
     // 複数のイベントレコードに対して、複数のルールを1個実行します。
     async fn execute_rules(mut self, records: Vec<EvtxRecordInfo>) -> (Self, Vec<DetectInfo>) {
+        // ChannelとEventIDのインデックスで、ルール毎に判定対象のレコードを絞り込む
+        let target_records = self.rule_index.get_target_records(&records);
         let records_arc = Arc::new(records);
         // // 各rule毎にスレッドを作成して、スレッドを起動する。
         let rules = self.rules;
-        let handles: Vec<JoinHandle<(RuleNode, Vec<DetectInfo>)>> = rules
-            .into_iter()
-            .map(|rule| {
-                let records_cloned = Arc::clone(&records_arc);
-                spawn(async move { Detection::execute_rule(rule, records_cloned) })
-            })
-            .collect();
+        let handles: Vec<JoinHandle<(RuleNode, Vec<DetectInfo>)>> =
+            rules
+                .into_iter()
+                .zip(target_records)
+                .map(|(rule, target_record_idxes)| {
+                    let records_cloned = Arc::clone(&records_arc);
+                    spawn(async move {
+                        Detection::execute_rule(rule, records_cloned, target_record_idxes)
+                    })
+                })
+                .collect();
 
         // 全スレッドの実行完了を待機
         let mut rules = vec![];
@@ -198,6 +294,23 @@ impl Detection {
         return rt.block_on(self.add_aggcondition_msg(stored_static));
     }
 
+    /// --followでファイルの監視中に、前回の出力以降に条件を満たしたaggregation conditionの検知結果を返す。
+    /// countdataは保持したままなので、複数回のスキャンにまたがったレコードも集計される
+    pub fn add_new_aggcondition_msges(&mut self, stored_static: &StoredStatic) -> Vec<DetectInfo> {
+        let mut ret = vec![];
+        for rule in self.rules.iter_mut() {
+            if !rule.has_agg_condition() {
+                continue;
+            }
+
+            for value in rule.judge_new_aggcondition(stored_static) {
+                ret.push(Detection::create_agg_log_record(rule, value, stored_static));
+            }
+        }
+
+        ret
+    }
+
     async fn add_aggcondition_msg(&self, stored_static: &StoredStatic) -> Vec<DetectInfo> {
         let mut ret = vec![];
         for rule in &self.rules {
@@ -214,15 +327,27 @@ impl Detection {
     }
 
     // 複数のイベントレコードに対して、ルールを1個実行します。
+    // target_record_idxesが指定されている場合は、そのインデックスのレコードのみを判定します。
     fn execute_rule(
         mut rule: RuleNode,
         records: Arc<Vec<EvtxRecordInfo>>,
+        target_record_idxes: Option<Vec<usize>>,
     ) -> (RuleNode, Vec<DetectInfo>) {
         let agg_condition = rule.has_agg_condition();
         let binding = STORED_STATIC.read().unwrap();
         let stored_static = binding.as_ref().unwrap();
         let mut ret = vec![];
-        for record_info in records.as_ref() {
+        let target_records: Box<dyn Iterator<Item = &EvtxRecordInfo>> = match target_record_idxes {
+            Some(idxes) => Box::new(idxes.into_iter().map(|idx| &records[idx])),
+            None => Box::new(records.iter()),
+        };
+        // --rule-profilingが指定された場合のみ、ルール毎の判定時間等を計測する
+        let mut profile = rule_profiler::is_enabled().then(|| {
+            rule_profiler::take_regex_time();
+            RuleProfile::default()
+        });
+        for record_info in target_records {
+            let start = profile.as_ref().map(|_| Instant::now());
             let result = rule.select(
                 record_info,
                 stored_static.verbose_flag,
@@ -230,6 +355,11 @@ impl Detection {
                 stored_static.json_input_flag,
                 &stored_static.eventkey_alias,
             );
+            if let (Some(profile), Some(start)) = (profile.as_mut(), start) {
+                profile.eval_time += start.elapsed();
+                profile.record_cnt += 1;
+                profile.match_cnt += result as u64;
+            }
             if !result {
                 continue;
             }
@@ -248,6 +378,14 @@ impl Detection {
                 ));
             }
         }
+        if let Some(mut profile) = profile {
+            profile.regex_time = rule_profiler::take_regex_time();
+            RULE_PROFILER.lock().unwrap().add(
+                &rule.rulepath,
+                rule.yaml["title"].as_str().unwrap_or_default(),
+                &profile,
+            );
+        }
 
         (rule, ret)
     }
@@ -512,6 +650,16 @@ impl Detection {
                         RuleID(rule.yaml["id"].as_str().unwrap_or("-").to_string().into()),
                     );
                 }
+                MatchReason(_) => {
+                    let reasons =
+                        rule.get_match_reasons(record_info, &stored_static.eventkey_alias);
+                    let reasons = if reasons.is_empty() {
+                        "-".to_string()
+                    } else {
+                        reasons.join(" ¦ ")
+                    };
+                    profile_converter.insert(key.as_str(), MatchReason(reasons.into()));
+                }
                 Provider(_) => {
                     let provider_value = CompactString::from(
                         record_info.record["Event"]["System"]["Provider_attributes"]["Name"]
@@ -766,7 +914,11 @@ impl Detection {
         stored_static: &StoredStatic,
     ) -> DetectInfo {
         let tag_info: &Nested<String> = &Detection::get_tag_info(rule);
-        let output = Detection::create_count_output(rule, &agg_result);
+        let output = if rule.is_correlation() {
+            Detection::create_correlation_output(rule, &agg_result)
+        } else {
+            Detection::create_count_output(rule, &agg_result)
+        };
 
         let mut profile_converter: HashMap<&str, Profile> = HashMap::new();
         let level = rule.yaml["level"].as_str().unwrap_or("-").to_string();
@@ -921,6 +1073,9 @@ impl Detection {
                         RuleID(rule.yaml["id"].as_str().unwrap_or("-").to_owned().into()),
                     );
                 }
+                MatchReason(_) => {
+                    profile_converter.insert(key.as_str(), MatchReason("-".into()));
+                }
                 Provider(_) => {
                     profile_converter.insert(key.as_str(), Provider("-".into()));
                 }
@@ -1023,7 +1178,22 @@ impl Detection {
         }
 
         write!(ret, " [result] count:{}", agg_result.data).ok();
-        if agg_condition._field_name.is_some() {
+        if let Some(agg_value) = agg_result.agg_value {
+            // min, max, avg, sumの場合は集計した値を出力する。整数でない場合は小数点2桁までとする
+            let agg_value = if agg_value.fract() == 0.0 {
+                format!("{agg_value:.0}")
+            } else {
+                format!("{agg_value:.2}")
+            };
+            write!(
+                ret,
+                " {}({}):{}",
+                agg_condition._function.as_str(),
+                agg_condition._field_name.as_ref().unwrap(),
+                agg_value
+            )
+            .ok();
+        } else if agg_condition._field_name.is_some() {
             write!(
                 ret,
                 " {}:{}",
@@ -1055,6 +1225,31 @@ impl Detection {
         CompactString::from(ret)
     }
 
+    ///相関ルールの検知出力文の文字列を返す関数
+    fn create_correlation_output(rule: &RuleNode, agg_result: &AggResult) -> CompactString {
+        let correlation = &rule.yaml["correlation"];
+        let mut ret = format!(
+            "[correlation] {} {} [result] count:{}",
+            correlation["type"].as_str().unwrap_or_default(),
+            agg_result.condition_op_num,
+            agg_result.data
+        );
+        if let Some(field) = correlation["condition"]["field"].as_str() {
+            write!(ret, " {}:{}", field, agg_result.field_values.join("/")).ok();
+        }
+        if !agg_result.key.is_empty() {
+            write!(ret, " {}", agg_result.key).ok();
+        }
+        write!(
+            ret,
+            " timespan:{} [rules] {}",
+            correlation["timespan"].as_str().unwrap_or_default(),
+            agg_result.contributing_rules.join(" ¦ ")
+        )
+        .ok();
+        CompactString::from(ret)
+    }
+
     pub fn print_rule_load_info(
         rc: &HashMap<CompactString, u128>,
         ld_rc: &HashMap<CompactString, u128>,
@@ -1244,6 +1439,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -1283,6 +1479,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -1296,6 +1493,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -1320,6 +1523,101 @@ mod tests {
         assert_eq!(5, cole.len());
     }
 
+    #[test]
+    fn test_parse_rule_files_with_correlation() {
+        let level = "informational";
+        let opt_rule_path = Path::new("./test_files/rules/correlation");
+        let dummy_stored_static = create_dummy_stored_static();
+        let cole = Detection::parse_rule_files(
+            level,
+            "",
+            opt_rule_path,
+            &filter::exclude_ids(&dummy_stored_static),
+            &dummy_stored_static,
+        );
+        // 相関ルールから参照されているルールは単体では読み込まない
+        let mut titles: Vec<&str> = cole
+            .iter()
+            .map(|rule| rule.yaml["title"].as_str().unwrap())
+            .collect();
+        titles.sort();
+        assert_eq!(titles, vec!["Brute Force Logon", "Successful Logon"]);
+        assert!(cole.iter().any(|rule| rule.is_correlation()));
+    }
+
+    #[test]
+    fn test_parse_rule_files_with_correlation_above_min_level() {
+        // 参照されているルールのレベルが-mで指定したレベルより低い場合でも、相関ルールは読み込む
+        let opt_rule_path = Path::new("./test_files/rules/correlation");
+        let dummy_stored_static = create_dummy_stored_static();
+        let cole = Detection::parse_rule_files(
+            "HIGH",
+            "",
+            opt_rule_path,
+            &filter::exclude_ids(&dummy_stored_static),
+            &dummy_stored_static,
+        );
+        let titles: Vec<&str> = cole
+            .iter()
+            .map(|rule| rule.yaml["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles, vec!["Brute Force Logon"]);
+        assert!(cole[0].is_correlation());
+    }
+
+    #[test]
+    fn test_parse_rule_files_with_invalid_correlation() {
+        let level = "informational";
+        let opt_rule_path = Path::new("./test_files/rules/correlation_invalid");
+        let dummy_stored_static = create_dummy_stored_static();
+        let cole = Detection::parse_rule_files(
+            level,
+            "",
+            opt_rule_path,
+            &filter::exclude_ids(&dummy_stored_static),
+            &dummy_stored_static,
+        );
+        // パースに失敗した相関ルールから参照されているルールは単体で読み込む
+        let titles: Vec<&str> = cole
+            .iter()
+            .map(|rule| rule.yaml["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles, vec!["Failed Logon"]);
+    }
+
+    #[test]
+    fn test_output_correlation_output() {
+        let default_time = Utc.with_ymd_and_hms(1977, 1, 1, 0, 0, 0).unwrap();
+        let mut agg_result: AggResult = AggResult::new(
+            3,
+            "IpAddress:10.0.0.1".to_string(),
+            vec!["alice".to_string(), "bob".to_string(), "carol".to_string()],
+            default_time,
+            ">= 3".to_string(),
+        );
+        agg_result.contributing_rules = vec!["Failed Logon".to_string()];
+        let rule_str = r#"
+        title: Password Spraying
+        correlation:
+            type: value_count
+            rules:
+                - failed_logon
+            group-by:
+                - IpAddress
+            timespan: 1h
+            condition:
+                field: TargetUserName
+                gte: 3
+        "#;
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+        let expected_output = "[correlation] value_count >= 3 [result] count:3 TargetUserName:alice/bob/carol IpAddress:10.0.0.1 timespan:1h [rules] Failed Logon";
+        assert_eq!(
+            Detection::create_correlation_output(&rule_node, &agg_result),
+            expected_output
+        );
+    }
+
     #[test]
     fn test_output_aggregation_output_with_output() {
         let default_time = Utc.with_ymd_and_hms(1977, 1, 1, 0, 0, 0).unwrap();
@@ -1463,6 +1761,37 @@ mod tests {
             expected_output
         );
     }
+    #[test]
+    fn test_output_aggregation_output_with_function() {
+        let default_time = Utc.with_ymd_and_hms(1977, 1, 1, 0, 0, 0).unwrap();
+        let mut agg_result: AggResult = AggResult::new(
+            3,
+            "PC01".to_string(),
+            vec![],
+            default_time,
+            "> 1000".to_string(),
+        );
+        agg_result.agg_value = Some(1234.5);
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection1:
+                Channel: 'System'
+            condition: selection1 | avg(Bytes) by Host > 1000
+            timeframe: 10m
+        output: testdata
+        "#;
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let test = rule_yaml.next().unwrap();
+        let mut rule_node = create_rule("testpath".to_string(), test);
+        rule_node.init(&create_dummy_stored_static()).ok();
+        let expected_output = "[condition] avg(Bytes) by Host > 1000 in timeframe [result] count:3 avg(Bytes):1234.50 Host:PC01 timeframe:10m";
+        assert_eq!(
+            Detection::create_count_output(&rule_node, &agg_result),
+            expected_output
+        );
+    }
+
     #[test]
     fn test_output_aggregation_output_with_by() {
         let default_time = Utc.with_ymd_and_hms(1977, 1, 1, 0, 0, 0).unwrap();
@@ -1506,6 +1835,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -1545,6 +1875,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -1558,6 +1889,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: Some(Path::new("test_files/mmdb").to_path_buf()),
             output: Some(Path::new("./test_emit_csv.csv").to_path_buf()),
@@ -1643,6 +1980,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -1682,6 +2020,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -1695,6 +2034,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: Some(Path::new("test_files/mmdb").to_path_buf()),
             output: Some(Path::new("./test_emit_csv.csv").to_path_buf()),
@@ -1775,6 +2120,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -1814,6 +2160,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -1827,6 +2174,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("./test_emit_csv.csv").to_path_buf()),
@@ -1923,6 +2276,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -1962,6 +2316,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -1975,6 +2330,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("./test_emit_csv.csv").to_path_buf()),
@@ -3,5 +3,8 @@ pub mod detection;
 pub mod field_data_map;
 pub mod field_extract;
 pub mod message;
+pub mod pipeline;
+pub mod placeholder;
 pub mod rule;
+pub mod rule_cache;
 pub mod utils;
@@ -0,0 +1,335 @@
+use crate::detections::message::AlertMessage;
+use hashbrown::HashMap;
+use std::fs;
+use std::path::Path;
+use yaml_rust::{yaml::Hash, Yaml, YamlLoader};
+
+/// logsourceのキーの一覧。パイプラインに指定されたキーのみをルールのlogsourceと比較する
+const LOGSOURCE_KEYS: [&str; 3] = ["category", "product", "service"];
+
+/// 1つのlogsourceを実際のChannelとEventIDの条件とフィールド名に変換するパイプライン
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    /// logsourceのキーと値(小文字)の一覧
+    logsource: Vec<(String, String)>,
+    pub variants: Vec<PipelineVariant>,
+}
+
+/// パイプラインを適用したルールが展開される1つの条件
+/// 例えばprocess_creationはSysmonのEventID 1とSecurityのEventID 4688に展開される。
+#[derive(Debug, Clone)]
+pub struct PipelineVariant {
+    /// ChannelやEventID等、ルールのdetectionにandで追加する条件
+    pub conditions: Yaml,
+    /// Sigmaの汎用的なフィールド名と、実際のイベントのフィールド名のマップ
+    pub field_mappings: HashMap<String, String>,
+}
+
+/// ルールのlogsourceに対応するChannelとEventIDの条件
+/// detectionでChannelを指定していないルールに、暗黙のAND条件として追加する。
+#[derive(Debug, Clone)]
+pub struct LogsourceMapping {
+    /// logsourceのキーと値(小文字)の一覧
+    logsource: Vec<(String, String)>,
+    /// ChannelやEventID等の条件。連想配列の配列の場合はOR条件になる
+    pub conditions: Yaml,
+}
+
+/// logsourceに指定されたキーと値(小文字)の一覧を取得する
+fn parse_logsource(yaml: &Yaml) -> Result<Vec<(String, String)>, String> {
+    let logsource: Vec<(String, String)> = LOGSOURCE_KEYS
+        .iter()
+        .filter_map(|key| {
+            yaml["logsource"][*key]
+                .as_str()
+                .map(|val| (key.to_string(), val.to_lowercase()))
+        })
+        .collect();
+    if logsource.is_empty() {
+        return Err("logsource must have category, product or service.".to_string());
+    }
+    Ok(logsource)
+}
+
+/// ルールのlogsourceが、指定された全てのlogsourceのキーと一致する場合にtrueを返す
+fn is_target_logsource(logsource: &[(String, String)], rule_yaml: &Yaml) -> bool {
+    logsource.iter().all(|(key, val)| {
+        rule_yaml["logsource"][key.as_str()]
+            .as_str()
+            .is_some_and(|rule_val| rule_val.eq_ignore_ascii_case(val))
+    })
+}
+
+impl LogsourceMapping {
+    fn new(yaml: &Yaml) -> Result<LogsourceMapping, String> {
+        let logsource = parse_logsource(yaml)?;
+        let conditions = yaml["conditions"].to_owned();
+        let is_valid = match &conditions {
+            Yaml::Hash(_) => true,
+            Yaml::Array(conds) => !conds.is_empty() && conds.iter().all(|c| c.as_hash().is_some()),
+            _ => false,
+        };
+        if !is_valid {
+            return Err("conditions must be a mapping or a list of mappings.".to_string());
+        }
+        Ok(LogsourceMapping {
+            logsource,
+            conditions,
+        })
+    }
+
+    /// ルールのlogsourceがマッピングに指定された全てのlogsourceのキーと一致する場合にtrueを返す
+    pub fn is_target(&self, rule_yaml: &Yaml) -> bool {
+        is_target_logsource(&self.logsource, rule_yaml)
+    }
+}
+
+impl Pipeline {
+    fn new(yaml: &Yaml) -> Result<Pipeline, String> {
+        let logsource = parse_logsource(yaml)?;
+        let variants = match yaml["variants"].as_vec() {
+            Some(variants) if !variants.is_empty() => variants,
+            _ => return Err("There are no variants.".to_string()),
+        };
+        let variants = variants
+            .iter()
+            .map(PipelineVariant::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Pipeline {
+            logsource,
+            variants,
+        })
+    }
+
+    /// ルールのlogsourceがパイプラインに指定された全てのlogsourceのキーと一致する場合にtrueを返す
+    pub fn is_target(&self, rule_yaml: &Yaml) -> bool {
+        is_target_logsource(&self.logsource, rule_yaml)
+    }
+}
+
+impl PipelineVariant {
+    fn new(yaml: &Yaml) -> Result<PipelineVariant, String> {
+        let conditions = yaml["conditions"].to_owned();
+        if conditions.as_hash().is_none() {
+            return Err("conditions of variants must be a mapping.".to_string());
+        }
+        let mut field_mappings = HashMap::new();
+        if let Some(mappings) = yaml["fieldmappings"].as_hash() {
+            for (key, val) in mappings {
+                match (key.as_str(), val.as_str()) {
+                    (Some(key), Some(val)) => {
+                        field_mappings.insert(key.to_string(), val.to_string());
+                    }
+                    _ => return Err("fieldmappings must be a mapping of strings.".to_string()),
+                }
+            }
+        }
+        Ok(PipelineVariant {
+            conditions,
+            field_mappings,
+        })
+    }
+
+    /// selectionのフィールド名をfieldmappingsに従って変換する。modifierはそのまま残す
+    pub fn map_fields(&self, selection: &Yaml) -> Yaml {
+        match selection {
+            Yaml::Hash(hash) => Yaml::Hash(
+                hash.iter()
+                    .map(|(key, val)| {
+                        let key = match key.as_str() {
+                            Some(key) => {
+                                let (field, modifiers) =
+                                    key.find('|').map_or((key, ""), |idx| key.split_at(idx));
+                                let field = self
+                                    .field_mappings
+                                    .get(field)
+                                    .map_or(field, |mapped| mapped.as_str());
+                                Yaml::String(format!("{field}{modifiers}"))
+                            }
+                            None => key.to_owned(),
+                        };
+                        (key, val.to_owned())
+                    })
+                    .collect::<Hash>(),
+            ),
+            // 連想配列の配列はOR条件なので、それぞれの連想配列のフィールド名を変換する
+            Yaml::Array(selections) => {
+                Yaml::Array(selections.iter().map(|s| self.map_fields(s)).collect())
+            }
+            _ => selection.to_owned(),
+        }
+    }
+}
+
+/// ルールに適用するパイプラインのvariantの一覧を返す。
+/// hayabusa-rulesで変換済みのルール(ruletypeが指定されているルール)は既に実際のフィールド名で記載されているので対象外とする。
+pub fn get_target_variants<'a>(
+    pipelines: &'a [Pipeline],
+    rule_yaml: &Yaml,
+) -> Vec<&'a PipelineVariant> {
+    if !rule_yaml["ruletype"].is_badvalue() {
+        return vec![];
+    }
+    pipelines
+        .iter()
+        .filter(|pipeline| pipeline.is_target(rule_yaml))
+        .flat_map(|pipeline| pipeline.variants.iter())
+        .collect()
+}
+
+/// Sigmaのlogsourceを変換するパイプラインをディレクトリ内のyamlファイルから読み込む。
+pub fn create_pipelines(dir_path: &Path) -> Vec<Pipeline> {
+    let mut ret = vec![];
+    // パイプラインは任意で設定するものなので、ディレクトリが存在しない場合は空の配列を返す
+    if !dir_path.is_dir() {
+        return ret;
+    }
+    let entries = match fs::read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let path = dir_path.as_os_str().to_str().unwrap_or_default();
+            AlertMessage::warn(&format!("Failed to open pipeline dir[{path}]. {e}")).ok();
+            return ret;
+        }
+    };
+    let mut paths: Vec<_> = entries.filter_map(|d| d.ok()).map(|d| d.path()).collect();
+    // 読み込み順によって展開されるvariantの順番が変わらないようにする
+    paths.sort();
+    for path in paths {
+        let ext = path
+            .extension()
+            .unwrap_or_default()
+            .to_str()
+            .unwrap_or_default();
+        if ext != "yaml" && ext != "yml" {
+            continue;
+        }
+        let yaml_docs =
+            match YamlLoader::load_from_str(&fs::read_to_string(&path).unwrap_or_default()) {
+                Ok(docs) => docs,
+                Err(_) => {
+                    AlertMessage::warn(&format!(
+                        "Failed to parse pipeline file. [file:{}]",
+                        path.display()
+                    ))
+                    .ok();
+                    continue;
+                }
+            };
+        for doc in yaml_docs {
+            match Pipeline::new(&doc) {
+                Ok(pipeline) => ret.push(pipeline),
+                Err(e) => {
+                    AlertMessage::warn(&format!(
+                        "Failed to load pipeline. {e} [file:{}]",
+                        path.display()
+                    ))
+                    .ok();
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// ルールのlogsourceに対応するChannelとEventIDの条件を返す。複数のマッピングに一致する場合は最初に一致したものを使う
+pub fn get_logsource_conditions<'a>(
+    mappings: &'a [LogsourceMapping],
+    rule_yaml: &Yaml,
+) -> Option<&'a Yaml> {
+    mappings
+        .iter()
+        .find(|mapping| mapping.is_target(rule_yaml))
+        .map(|mapping| &mapping.conditions)
+}
+
+/// logsourceとChannel/EventIDの条件のマッピングをyamlファイルから読み込む。
+/// マッピングはyamlの配列(または複数のドキュメント)で記載する。
+pub fn create_logsource_mappings(file_path: &Path) -> Vec<LogsourceMapping> {
+    let mut ret = vec![];
+    // マッピングは任意で設定するものなので、ファイルが存在しない場合は空の配列を返す
+    if !file_path.is_file() {
+        return ret;
+    }
+    let yaml_docs =
+        match YamlLoader::load_from_str(&fs::read_to_string(file_path).unwrap_or_default()) {
+            Ok(docs) => docs,
+            Err(_) => {
+                AlertMessage::warn(&format!(
+                    "Failed to parse logsource mapping file. [file:{}]",
+                    file_path.display()
+                ))
+                .ok();
+                return ret;
+            }
+        };
+    for doc in yaml_docs {
+        let docs = match doc {
+            Yaml::Array(docs) => docs,
+            doc => vec![doc],
+        };
+        for doc in docs {
+            match LogsourceMapping::new(&doc) {
+                Ok(mapping) => ret.push(mapping),
+                Err(e) => {
+                    AlertMessage::warn(&format!(
+                        "Failed to load logsource mapping. {e} [file:{}]",
+                        file_path.display()
+                    ))
+                    .ok();
+                }
+            }
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::pipeline::{create_pipelines, get_target_variants};
+    use std::path::Path;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn test_create_pipelines_not_exists_dir() {
+        assert!(create_pipelines(Path::new("notexists")).is_empty());
+    }
+
+    #[test]
+    fn test_get_target_variants() {
+        let pipelines = create_pipelines(Path::new("test_files/config/pipelines"));
+        let rule_str = r#"
+        logsource:
+            category: process_creation
+            product: windows
+        detection:
+            selection:
+                Image|endswith: '\rundll32.exe'
+                CommandLine|contains: 'javascript:'
+            condition: selection
+        "#;
+        let rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().remove(0);
+        let variants = get_target_variants(&pipelines, &rule_yaml);
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[1].conditions["Channel"].as_str(), Some("Security"));
+
+        let selection = &rule_yaml["detection"]["selection"];
+        let mapped = variants[1].map_fields(selection);
+        assert_eq!(
+            mapped["NewProcessName|endswith"].as_str(),
+            Some("\\rundll32.exe")
+        );
+        assert_eq!(mapped["CommandLine|contains"].as_str(), Some("javascript:"));
+        assert_eq!(&variants[0].map_fields(selection), selection);
+
+        // hayabusa-rulesで変換済みのルールとlogsourceが一致しないルールは対象外
+        let converted_rule = rule_str.replace("logsource:", "ruletype: Sigma\n        logsource:");
+        let rule_yaml = YamlLoader::load_from_str(&converted_rule)
+            .unwrap()
+            .remove(0);
+        assert!(get_target_variants(&pipelines, &rule_yaml).is_empty());
+        let other_rule = rule_str.replace("process_creation", "network_connection");
+        let rule_yaml = YamlLoader::load_from_str(&other_rule).unwrap().remove(0);
+        assert!(get_target_variants(&pipelines, &rule_yaml).is_empty());
+    }
+}
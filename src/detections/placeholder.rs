@@ -0,0 +1,182 @@
+use crate::detections::message::AlertMessage;
+use crate::detections::utils;
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// placeholder名と、そのplaceholderを展開した値の一覧のマップ
+pub type PlaceholderMap = HashMap<String, Vec<String>>;
+
+lazy_static! {
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"%([^%\s]+)%").unwrap();
+}
+
+/// Sigmaルールの`|expand`で使う`%placeholder%`の値をディレクトリ内のファイルから読み込む。
+/// - `*.txt`: 拡張子を除いたファイル名がplaceholder名で、1行に1つの値を記載する。空行と#から始まる行は無視する。
+/// - `*.yaml`/`*.yml`: placeholder名をキーとして、値の配列(または値)を記載する。
+pub fn create_placeholder_map(dir_path: &Path) -> PlaceholderMap {
+    let mut ret = PlaceholderMap::new();
+    // placeholderは環境毎に任意で設定するものなので、ディレクトリが存在しない場合は空のマップを返す
+    if !dir_path.is_dir() {
+        return ret;
+    }
+    let entries = match fs::read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let path = dir_path.as_os_str().to_str().unwrap_or_default();
+            AlertMessage::warn(&format!("Failed to open placeholder dir[{path}]. {e}")).ok();
+            return ret;
+        }
+    };
+    for path in entries.filter_map(|d| d.ok()).map(|d| d.path()) {
+        let ext = path
+            .extension()
+            .unwrap_or_default()
+            .to_str()
+            .unwrap_or_default();
+        if ext == "txt" {
+            let name = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_str()
+                .unwrap_or_default();
+            match utils::read_txt(path.to_str().unwrap_or_default()) {
+                Ok(lines) => {
+                    let values = lines
+                        .iter()
+                        .map(|line| line.trim())
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(|line| line.to_string());
+                    ret.entry(name.to_string()).or_default().extend(values);
+                }
+                Err(e) => {
+                    AlertMessage::warn(&e).ok();
+                }
+            }
+        } else if ext == "yaml" || ext == "yml" {
+            let yaml_docs =
+                YamlLoader::load_from_str(&fs::read_to_string(&path).unwrap_or_default());
+            if yaml_docs.is_err() {
+                AlertMessage::warn(&format!(
+                    "Failed to parse placeholder file. [file:{}]",
+                    path.display()
+                ))
+                .ok();
+                continue;
+            }
+            for doc in yaml_docs.unwrap() {
+                let hash = doc.as_hash();
+                if hash.is_none() {
+                    continue;
+                }
+                for (key, val) in hash.unwrap() {
+                    let name = key.as_str().unwrap_or_default();
+                    if name.is_empty() {
+                        continue;
+                    }
+                    let values: Vec<String> = match val {
+                        Yaml::Array(vals) => vals.iter().filter_map(yaml_to_string).collect(),
+                        _ => yaml_to_string(val).into_iter().collect(),
+                    };
+                    ret.entry(name.to_string()).or_default().extend(values);
+                }
+            }
+        }
+    }
+    ret
+}
+
+fn yaml_to_string(val: &Yaml) -> Option<String> {
+    match val {
+        Yaml::String(s) => Some(s.to_owned()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Real(r) => Some(r.to_owned()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// 文字列中の`%placeholder%`をPlaceholderMapの値に展開する。
+/// 複数のplaceholderが含まれている場合は全ての組み合わせを返す。
+/// 定義されていないplaceholderが含まれている場合は、そのplaceholder名をErrで返す。
+pub fn expand_placeholders(
+    value: &str,
+    placeholders: &PlaceholderMap,
+) -> Result<Vec<String>, String> {
+    let captures = match PLACEHOLDER_REGEX.captures(value) {
+        Some(captures) => captures,
+        None => return Ok(vec![value.to_string()]),
+    };
+    let placeholder = captures.get(0).unwrap();
+    let name = &captures[1];
+    let expanded_values = match placeholders.get(name) {
+        Some(values) => values,
+        None => return Err(name.to_string()),
+    };
+    let head = &value[..placeholder.start()];
+    // 展開後の値に含まれる%は展開しないように、placeholderより後ろの文字列のみを再帰的に展開する
+    let tails = expand_placeholders(&value[placeholder.end()..], placeholders)?;
+    let mut ret = vec![];
+    for expanded_value in expanded_values {
+        for tail in tails.iter() {
+            ret.push(format!("{head}{expanded_value}{tail}"));
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::placeholder::{
+        create_placeholder_map, expand_placeholders, PlaceholderMap,
+    };
+    use std::path::Path;
+
+    #[test]
+    fn test_create_placeholder_map_not_exists_dir() {
+        assert!(create_placeholder_map(Path::new("notexists")).is_empty());
+    }
+
+    #[test]
+    fn test_create_placeholder_map() {
+        let placeholders = create_placeholder_map(Path::new("test_files/config/placeholders"));
+        assert_eq!(
+            placeholders.get("Admins_Workstations").unwrap(),
+            &vec!["ADMIN-PC01".to_string(), "ADMIN-PC02".to_string()]
+        );
+        assert_eq!(
+            placeholders.get("DomainControllers").unwrap(),
+            &vec!["DC01".to_string(), "DC02".to_string()]
+        );
+        assert_eq!(
+            placeholders.get("JumpHost").unwrap(),
+            &vec!["JUMP01".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let mut placeholders = PlaceholderMap::new();
+        placeholders.insert(
+            "dc".to_string(),
+            vec!["DC01".to_string(), "DC02".to_string()],
+        );
+        placeholders.insert("domain".to_string(), vec!["corp".to_string()]);
+
+        assert_eq!(
+            expand_placeholders("no placeholder", &placeholders),
+            Ok(vec!["no placeholder".to_string()])
+        );
+        assert_eq!(
+            expand_placeholders("%domain%\\%dc%$", &placeholders),
+            Ok(vec![r"corp\DC01$".to_string(), r"corp\DC02$".to_string()])
+        );
+        assert_eq!(
+            expand_placeholders("%notexists%", &placeholders),
+            Err("notexists".to_string())
+        );
+    }
+}
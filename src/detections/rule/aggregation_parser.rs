@@ -5,7 +5,7 @@ lazy_static! {
     // ここで字句解析するときに使う正規表現の一覧を定義する。
     // ここはSigmaのGithubレポジトリにある、toos/sigma/parser/condition.pyのSigmaConditionTokenizerのtokendefsを参考にしています。
     pub static ref AGGREGATION_REGEXMAP: Vec<Regex> = vec![
-        Regex::new(r"^count\( *\w* *\)").unwrap(), // countの式
+        Regex::new(r"^(count|min|max|avg|sum)\( *\w* *\)").unwrap(), // countなどの集計関数の式
         Regex::new(r"^ ").unwrap(),
         Regex::new(r"^by").unwrap(),
         Regex::new(r"^==").unwrap(),
@@ -20,23 +20,47 @@ lazy_static! {
 
 #[derive(Debug)]
 pub struct AggregationParseInfo {
-    pub _field_name: Option<String>,    // countの括弧に囲まれた部分の文字
+    pub _function: AggregationFunction, // (必須)count, min, max, avg, sumのいずれの集計関数か
+    pub _field_name: Option<String>,    // countなどの集計関数の括弧に囲まれた部分の文字
     pub _by_field_name: Option<String>, // count() by の後に指定される文字列
     pub _cmp_op: AggregationConditionToken, // (必須)<とか>とか何が指定されたのか
     pub _cmp_num: i64,                  // (必須)<とか>とかの後にある数値
 }
 
+#[derive(Debug, Clone, PartialEq)]
+/// パイプ以降に指定できる集計関数
+pub enum AggregationFunction {
+    Count,
+    Min,
+    Max,
+    Avg,
+    Sum,
+}
+
+impl AggregationFunction {
+    pub fn as_str(&self) -> &str {
+        match self {
+            AggregationFunction::Count => "count",
+            AggregationFunction::Min => "min",
+            AggregationFunction::Max => "max",
+            AggregationFunction::Avg => "avg",
+            AggregationFunction::Sum => "sum",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum AggregationConditionToken {
-    Count(String),   // count
-    Space,           // 空白
-    BY,              // by
-    EQ,              // ..と等しい
-    LE,              // ..以下
-    LT,              // ..未満
-    GE,              // ..以上
-    GT,              // .よりおおきい
-    Keyword(String), // BYのフィールド名
+    Count(String),                         // count
+    Function(AggregationFunction, String), // min, max, avg, sum
+    Space,                                 // 空白
+    BY,                                    // by
+    EQ,                                    // ..と等しい
+    LE,                                    // ..以下
+    LT,                                    // ..未満
+    GE,                                    // ..以上
+    GT,                                    // .よりおおきい
+    Keyword(String),                       // BYのフィールド名
 }
 
 /// SIGMAルールでいうAggregationConditionを解析する。
@@ -142,21 +166,41 @@ impl AggegationConditionCompiler {
         let token = token_ite.next().unwrap();
 
         let mut count_field_name: Option<String> = Option::None;
-        if let AggregationConditionToken::Count(field_name) = token {
-            if !field_name.is_empty() {
+        let function = match token {
+            AggregationConditionToken::Count(field_name) => {
+                if !field_name.is_empty() {
+                    count_field_name = Option::Some(field_name);
+                }
+                AggregationFunction::Count
+            }
+            AggregationConditionToken::Function(function, field_name) => {
+                // count以外の集計関数は数値のフィールドを集計するので、フィールドの指定が必須
+                if field_name.is_empty() {
+                    return Result::Err(format!(
+                        "The {0} keyword needs a field name like '{0}(Bytes)'",
+                        function.as_str()
+                    ));
+                }
                 count_field_name = Option::Some(field_name);
+                function
             }
-        } else {
-            // いろんなパターンがあるので難しいが、countというキーワードしか使えないことを説明しておく。
-            return Result::Err("The aggregation condition can only use count.".to_string());
-        }
+            _ => {
+                // いろんなパターンがあるので難しいが、使えるキーワードを説明しておく。
+                return Result::Err(
+                    "The aggregation condition can only use count, min, max, avg or sum."
+                        .to_string(),
+                );
+            }
+        };
+        let no_cmp_op_errmsg = format!(
+            "The {} keyword needs a compare operator and number like '> 3'",
+            function.as_str()
+        );
 
         let token = token_ite.next();
         if token.is_none() {
             // 論理演算子がないのはだめ
-            return Result::Err(
-                "The count keyword needs a compare operator and number like '> 3'".to_string(),
-            );
+            return Result::Err(no_cmp_op_errmsg);
         }
 
         // BYはオプションでつけなくても良い
@@ -186,16 +230,12 @@ impl AggegationConditionCompiler {
         // 比較演算子と数値をパース
         if token.is_none() {
             // 論理演算子がないのはだめ
-            return Result::Err(
-                "The count keyword needs a compare operator and number like '> 3'".to_string(),
-            );
+            return Result::Err(no_cmp_op_errmsg);
         }
 
         let cmp_token = token.unwrap();
         if !self.is_cmp_op(&cmp_token) {
-            return Result::Err(
-                "The count keyword needs a compare operator and number like '> 3'".to_string(),
-            );
+            return Result::Err(no_cmp_op_errmsg);
         }
 
         let token = token_ite.next().unwrap_or(AggregationConditionToken::Space);
@@ -217,6 +257,7 @@ impl AggegationConditionCompiler {
         }
 
         let info = AggregationParseInfo {
+            _function: function,
             _field_name: count_field_name,
             _by_field_name: by_field_name,
             _cmp_op: cmp_token,
@@ -233,6 +274,15 @@ impl AggegationConditionCompiler {
                 .replacen(')', "", 1)
                 .replace(' ', "");
             AggregationConditionToken::Count(count_field)
+        } else if let Some((function_name, field)) = token.split_once('(') {
+            let function = match function_name {
+                "min" => AggregationFunction::Min,
+                "max" => AggregationFunction::Max,
+                "avg" => AggregationFunction::Avg,
+                _ => AggregationFunction::Sum,
+            };
+            let field_name = field.replacen(')', "", 1).replace(' ', "");
+            AggregationConditionToken::Function(function, field_name)
         } else if token == " " {
             AggregationConditionToken::Space
         } else if token == "by" {
@@ -256,7 +306,7 @@ impl AggegationConditionCompiler {
 #[cfg(test)]
 mod tests {
     use super::super::aggregation_parser::{
-        AggegationConditionCompiler, AggregationConditionToken,
+        AggegationConditionCompiler, AggregationConditionToken, AggregationFunction,
     };
 
     #[test]
@@ -341,6 +391,36 @@ mod tests {
         assert!(matches!(result._cmp_op, AggregationConditionToken::GT));
     }
 
+    #[test]
+    fn test_aggegation_condition_compiler_functions() {
+        let compiler = AggegationConditionCompiler::new();
+        for (function, expected) in [
+            ("min", AggregationFunction::Min),
+            ("max", AggregationFunction::Max),
+            ("avg", AggregationFunction::Avg),
+            ("sum", AggregationFunction::Sum),
+        ] {
+            let result = compiler
+                .compile(&format!("select1 | {function}( Bytes ) by Host > 1000"))
+                .unwrap()
+                .unwrap();
+            assert_eq!(expected, result._function);
+            assert_eq!("Bytes", result._field_name.unwrap());
+            assert_eq!("Host", result._by_field_name.unwrap());
+            assert_eq!(1000, result._cmp_num);
+            assert!(matches!(result._cmp_op, AggregationConditionToken::GT));
+        }
+    }
+
+    #[test]
+    fn test_aggegation_condition_compiler_function_no_field() {
+        // count以外の集計関数でフィールドが指定されていない
+        let compiler = AggegationConditionCompiler::new();
+        let result = compiler.compile("select1 | sum() by Host > 1000");
+
+        assert_eq!("An aggregation condition parse error has occurred. The sum keyword needs a field name like 'sum(Bytes)'".to_string(),result.unwrap_err());
+    }
+
     #[test]
     fn test_aggegation_condition_compiler_only_pipe() {
         let compiler = AggegationConditionCompiler::new();
@@ -374,7 +454,7 @@ mod tests {
         let result = compiler.compile("select1 or select2 | by count( hogehoge) by snsn > 3");
 
         assert!(result.is_err());
-        assert_eq!("An aggregation condition parse error has occurred. The aggregation condition can only use count.".to_string(),result.unwrap_err());
+        assert_eq!("An aggregation condition parse error has occurred. The aggregation condition can only use count, min, max, avg or sum.".to_string(),result.unwrap_err());
     }
 
     #[test]
@@ -2,7 +2,8 @@ use lazy_static::lazy_static;
 use regex::Regex;
 
 use self::selectionnodes::{
-    AndSelectionNode, NotSelectionNode, OrSelectionNode, RefSelectionNode, SelectionNode,
+    AndSelectionNode, CountSelectionNode, NotSelectionNode, OrSelectionNode, RefSelectionNode,
+    SelectionNode,
 };
 use super::selectionnodes;
 use hashbrown::HashMap;
@@ -11,14 +12,17 @@ use std::{sync::Arc, vec::IntoIter};
 
 lazy_static! {
     pub static ref CONDITION_REGEXMAP: Vec<Regex> = vec![
+        Regex::new(r"^\d+ of \[[\w ]+\]").unwrap(),
         Regex::new(r"^\(").unwrap(),
         Regex::new(r"^\)").unwrap(),
         Regex::new(r"^ ").unwrap(),
         Regex::new(r"^\w+").unwrap(),
     ];
     pub static ref RE_PIPE: Regex = Regex::new(r"\|.*").unwrap();
-    // all of selection* と 1 of selection* にマッチする正規表現
-    pub static ref OF_SELECTION: Regex = Regex::new(r"(all|1) of ([^*]+)\*").unwrap();
+    // all of selection*, 1 of selection*, any of selection*, 2 of selection*, all of them などにマッチする正規表現
+    pub static ref OF_SELECTION: Regex = Regex::new(r"\b(all|any|\d+) of ([\w*]+)").unwrap();
+    // convert_conditionで変換した「N of [selection1 selection2 ...]」にマッチする正規表現
+    static ref COUNT_SELECTION: Regex = Regex::new(r"^(\d+) of \[([\w ]+)\]$").unwrap();
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +35,7 @@ pub enum ConditionToken {
     And,
     Or,
     SelectionReference(String),
+    CountSelection(usize, Vec<String>), // N of selection*のように、N個以上のselectionが一致することを表すトークン
 
     // パースの時に上手く処理するために作った疑似的なトークン
     ParenthesisContainer(Box<ConditionToken>), // 括弧を表すトークン
@@ -51,13 +56,22 @@ impl ConditionToken {
                 if let Some(select_node) = selection_node {
                     let selection_node = select_node;
                     let selection_node = Arc::clone(selection_node);
-                    let ref_node = RefSelectionNode::new(selection_node);
+                    let ref_node = RefSelectionNode::new(selection_name, selection_node);
                     return Result::Ok(Box::new(ref_node));
                 } else {
                     let err_msg = format!("{selection_name} is not defined.");
                     return Result::Err(err_msg);
                 }
             }
+            ConditionToken::CountSelection(count, selection_names) => {
+                let mut select_count_node = CountSelectionNode::new(count);
+                for selection_name in selection_names {
+                    let sub_node = ConditionToken::SelectionReference(selection_name)
+                        .into_selection_node(name_2_node)?;
+                    select_count_node.child_nodes.push(sub_node);
+                }
+                return Result::Ok(Box::new(select_count_node));
+            }
             ConditionToken::ParenthesisContainer(sub_token) => {
                 Result::Ok((*sub_token).into_selection_node(name_2_node)?)
             }
@@ -104,6 +118,11 @@ impl ConditionToken {
             ConditionToken::And
         } else if token == "or" {
             ConditionToken::Or
+        } else if let Some(caps) = COUNT_SELECTION.captures(token) {
+            ConditionToken::CountSelection(
+                caps[1].parse().unwrap_or_default(),
+                caps[2].split(' ').map(|s| s.to_string()).collect(),
+            )
         } else {
             ConditionToken::SelectionReference(token.to_string())
         }
@@ -125,7 +144,8 @@ impl ConditionCompiler {
         name_2_node: &HashMap<String, Arc<Box<dyn SelectionNode>>>,
     ) -> Result<Box<dyn SelectionNode>, String> {
         let node_keys: Vec<String> = name_2_node.keys().cloned().collect();
-        let condition_str = Self::convert_condition(condition_str, &node_keys);
+        let condition_str = Self::convert_condition(condition_str, &node_keys)
+            .map_err(|msg| format!("A condition parse error has occurred. {msg}"))?;
         // パイプはここでは処理しない
         let captured = self::RE_PIPE.captures(condition_str.as_str());
         let replaced_condition = if let Some(cap) = captured {
@@ -143,28 +163,68 @@ impl ConditionCompiler {
         }
     }
 
-    // all of selection* と 1 of selection* を通常のand/orに変換する
-    pub fn convert_condition(condition_str: &str, node_keys: &[String]) -> String {
-        let mut converted_str = condition_str.to_string();
-        for matched in OF_SELECTION.find_iter(condition_str) {
-            let match_str: &str = matched.as_str();
-            let sep = if match_str.starts_with("all") {
-                " and "
-            } else {
-                " or "
-            };
-            let target_node_key_prefix = match_str
-                .replace('*', "")
-                .replace("all of ", "")
-                .replace("1 of ", "");
-            let replaced_condition = node_keys
+    // all of selection*, 1 of selection*, all of them などを通常のand/orに変換する
+    pub fn convert_condition(condition_str: &str, node_keys: &[String]) -> Result<String, String> {
+        let mut err_msg = None;
+        let converted_str = OF_SELECTION.replace_all(condition_str, |caps: &regex::Captures| {
+            match Self::convert_of_selection(&caps[1], &caps[2], node_keys) {
+                Ok(replaced_condition) => replaced_condition,
+                Err(e) => {
+                    err_msg.get_or_insert(e);
+                    String::default()
+                }
+            }
+        });
+        match err_msg {
+            Some(e) => Result::Err(e),
+            None => Result::Ok(converted_str.to_string()),
+        }
+    }
+
+    /// 「all/any/N of 対象」の対象に該当するselectionの名前をand/orでつないだ条件式に変換する
+    fn convert_of_selection(
+        quantifier: &str,
+        target: &str,
+        node_keys: &[String],
+    ) -> Result<String, String> {
+        let target_node_keys: Vec<&String> = if target == "them" {
+            // themは_から始まるselectionを対象にしない
+            node_keys.iter().filter(|x| !x.starts_with('_')).collect()
+        } else {
+            // *は任意の文字列として扱う
+            let target_regex = format!("^{}$", regex::escape(target).replace(r"\*", ".*"));
+            let target_regex = Regex::new(&target_regex).unwrap();
+            node_keys
                 .iter()
-                .filter(|x| x.starts_with(target_node_key_prefix.as_str()))
-                .join(sep);
-            converted_str =
-                converted_str.replace(match_str, format!("({})", replaced_condition).as_str())
+                .filter(|x| target_regex.is_match(x))
+                .collect()
+        };
+        let of_str = format!("{quantifier} of {target}");
+        if target_node_keys.is_empty() {
+            return Result::Err(format!("No selection matched to '{of_str}'."));
+        }
+
+        let cnt = match quantifier {
+            "all" => target_node_keys.len(),
+            "any" => 1,
+            _ => quantifier.parse::<usize>().unwrap_or_default(),
+        };
+        if cnt == 0 || cnt > target_node_keys.len() {
+            return Result::Err(format!(
+                "The number of '{of_str}' must be between 1 and the number of matched selections({}).",
+                target_node_keys.len()
+            ));
+        }
+
+        if cnt == 1 {
+            Result::Ok(format!("({})", target_node_keys.iter().join(" or ")))
+        } else if cnt == target_node_keys.len() {
+            Result::Ok(format!("({})", target_node_keys.iter().join(" and ")))
+        } else {
+            // N of selection* はN個以上のselectionがマッチすることを表す。
+            // 組み合わせをand/orに展開すると条件式が組み合わせの数だけ大きくなるので、各selectionを1回ずつ判定して数を数えるノードに変換する
+            Result::Ok(format!("{cnt} of [{}]", target_node_keys.iter().join(" ")))
         }
-        converted_str
     }
 
     /// 与えたConditionからSelectionNodeを作る
@@ -457,6 +517,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -496,6 +557,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -509,6 +571,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -1404,7 +1472,7 @@ mod tests {
         let condition = "all of selection*";
 
         let keys = vec!["selection1".to_string(), "selection2".to_string()];
-        let result = ConditionCompiler::convert_condition(condition, &keys);
+        let result = ConditionCompiler::convert_condition(condition, &keys).unwrap();
         let expected = "(selection1 and selection2)".to_string();
         assert_eq!(result, expected);
 
@@ -1413,7 +1481,7 @@ mod tests {
             "selection2".to_string(),
             "selection3".to_string(),
         ];
-        let result = ConditionCompiler::convert_condition(condition, &keys);
+        let result = ConditionCompiler::convert_condition(condition, &keys).unwrap();
         let expected = "(selection1 and selection2 and selection3)".to_string();
         assert_eq!(result, expected);
     }
@@ -1428,7 +1496,7 @@ mod tests {
             "filter1".to_string(),
             "filter2".to_string(),
         ];
-        let result = ConditionCompiler::convert_condition(condition, &keys);
+        let result = ConditionCompiler::convert_condition(condition, &keys).unwrap();
         let expected = "(selection1 and selection2) and (filter1 and filter2)".to_string();
         assert_eq!(result, expected);
     }
@@ -1438,7 +1506,7 @@ mod tests {
         let condition = "1 of selection*";
 
         let keys = vec!["selection1".to_string(), "selection2".to_string()];
-        let result = ConditionCompiler::convert_condition(condition, &keys);
+        let result = ConditionCompiler::convert_condition(condition, &keys).unwrap();
         let expected = "(selection1 or selection2)".to_string();
         assert_eq!(result, expected);
 
@@ -1447,7 +1515,7 @@ mod tests {
             "selection2".to_string(),
             "selection3".to_string(),
         ];
-        let result = ConditionCompiler::convert_condition(condition, &keys);
+        let result = ConditionCompiler::convert_condition(condition, &keys).unwrap();
         let expected = "(selection1 or selection2 or selection3)".to_string();
         assert_eq!(result, expected);
     }
@@ -1461,7 +1529,7 @@ mod tests {
             "filter1".to_string(),
             "filter2".to_string(),
         ];
-        let result = ConditionCompiler::convert_condition(condition, &keys);
+        let result = ConditionCompiler::convert_condition(condition, &keys).unwrap();
         let expected = "(selection1 or selection2) and (filter1 or filter2)".to_string();
         assert_eq!(result, expected);
     }
@@ -1476,7 +1544,7 @@ mod tests {
             "filter1".to_string(),
             "filter2".to_string(),
         ];
-        let result = ConditionCompiler::convert_condition(condition, &keys);
+        let result = ConditionCompiler::convert_condition(condition, &keys).unwrap();
         let expected =
             "(selection1 and selection2) and test1 or test2 or (filter1 or filter2)".to_string();
         assert_eq!(result, expected);
@@ -1486,10 +1554,126 @@ mod tests {
     fn test_convert_condition_not_convert() {
         let condition = "selection1 and selection2";
         let keys = vec!["selection1".to_string(), "selection2".to_string()];
-        let result = ConditionCompiler::convert_condition(condition, &keys);
+        let result = ConditionCompiler::convert_condition(condition, &keys).unwrap();
         assert_eq!(result, condition);
     }
 
+    #[test]
+    fn test_convert_condition_of_them() {
+        let keys = vec![
+            "selection1".to_string(),
+            "selection2".to_string(),
+            "_filter".to_string(),
+        ];
+        let result = ConditionCompiler::convert_condition("all of them", &keys).unwrap();
+        assert_eq!(result, "(selection1 and selection2)");
+
+        let result = ConditionCompiler::convert_condition("1 of them", &keys).unwrap();
+        assert_eq!(result, "(selection1 or selection2)");
+
+        let result = ConditionCompiler::convert_condition("any of them", &keys).unwrap();
+        assert_eq!(result, "(selection1 or selection2)");
+    }
+
+    #[test]
+    fn test_convert_condition_n_of_selection() {
+        let keys = vec![
+            "selection_a".to_string(),
+            "selection_b".to_string(),
+            "selection_c".to_string(),
+            "filter".to_string(),
+        ];
+        let result = ConditionCompiler::convert_condition("2 of selection_*", &keys).unwrap();
+        assert_eq!(result, "2 of [selection_a selection_b selection_c]");
+
+        let result =
+            ConditionCompiler::convert_condition("3 of selection_* and not filter", &keys).unwrap();
+        assert_eq!(
+            result,
+            "(selection_a and selection_b and selection_c) and not filter"
+        );
+
+        let result = ConditionCompiler::convert_condition("any of sel*_b", &keys).unwrap();
+        assert_eq!(result, "(selection_b)");
+    }
+
+    #[test]
+    fn test_convert_condition_of_selection_err() {
+        let keys = vec!["selection1".to_string(), "selection2".to_string()];
+        assert_eq!(
+            ConditionCompiler::convert_condition("1 of filter*", &keys),
+            Err("No selection matched to '1 of filter*'.".to_string())
+        );
+        assert_eq!(
+            ConditionCompiler::convert_condition("3 of selection*", &keys),
+            Err("The number of '3 of selection*' must be between 1 and the number of matched selections(2).".to_string())
+        );
+    }
+
+    #[test]
+    fn test_condition_1_of_them_detect() {
+        // conditionに 1 of them を使ったパターンのテスト
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection1:
+                Channel: 'NODETECT'
+            selection2:
+                EventID: 7040
+            condition: 1 of them
+        details: 'Service name : %param1%¥nMessage : Event Log Service Stopped¥nResults: Selective event log manipulation may follow this event.'
+        "#;
+
+        check_select(rule_str, SIMPLE_RECORD_STR, true);
+    }
+
+    #[test]
+    fn test_condition_2_of_select_detect() {
+        // conditionに 2 of selection* を使ったパターンのテスト
+        let rule_str = |channel: &str| {
+            format!(
+                r#"
+        enabled: true
+        detection:
+            selection1:
+                Channel: '{channel}'
+            selection2:
+                EventID: 9999
+            selection3:
+                param1: 'Windows Event Log'
+            condition: 2 of selection*
+        details: 'Service name : %param1%¥nMessage : Event Log Service Stopped¥nResults: Selective event log manipulation may follow this event.'
+        "#
+            )
+        };
+
+        check_select(rule_str("System").as_str(), SIMPLE_RECORD_STR, true);
+        check_select(rule_str("NODETECT").as_str(), SIMPLE_RECORD_STR, false);
+    }
+
+    #[test]
+    fn test_condition_n_of_many_selections_detect() {
+        // 組み合わせの数が膨大になる 15 of selection* でも、一致したselectionの数で判定できること
+        let rule_str = |matched_cnt: usize| {
+            let selections: String = (1..=30)
+                .map(|i| {
+                    let value = if i <= matched_cnt {
+                        "Windows Event Log"
+                    } else {
+                        "NODETECT"
+                    };
+                    format!("            selection{i}:\n                param1: '{value}'\n")
+                })
+                .collect();
+            format!(
+                "        enabled: true\n        detection:\n{selections}            condition: 15 of selection*\n        details: 'test'\n"
+            )
+        };
+
+        check_select(rule_str(15).as_str(), SIMPLE_RECORD_STR, true);
+        check_select(rule_str(14).as_str(), SIMPLE_RECORD_STR, false);
+    }
+
     #[test]
     fn test_condition_1_of_select_detect() {
         // conditionに 1 of selection* を使ったパターンのテスト
@@ -0,0 +1,641 @@
+use crate::detections::configs::{EventKeyAliasConfig, StoredStatic};
+use crate::detections::detection::EvtxRecordInfo;
+use crate::detections::message;
+use crate::detections::rule::aggregation_parser::AggregationConditionToken;
+use crate::detections::rule::count::TimeFrameInfo;
+use crate::detections::rule::{create_rule, AggResult, RuleNode};
+use crate::detections::utils;
+use chrono::{DateTime, TimeZone, Utc};
+use hashbrown::HashMap;
+use itertools::Itertools;
+use yaml_rust::Yaml;
+
+/// Sigmaの相関ルール(correlation)の種類
+#[derive(Debug, Clone, PartialEq)]
+pub enum CorrelationType {
+    EventCount,
+    ValueCount,
+    Temporal,
+    TemporalOrdered,
+}
+
+impl CorrelationType {
+    fn parse(type_str: &str) -> Option<CorrelationType> {
+        match type_str {
+            "event_count" => Some(CorrelationType::EventCount),
+            "value_count" => Some(CorrelationType::ValueCount),
+            "temporal" => Some(CorrelationType::Temporal),
+            "temporal_ordered" => Some(CorrelationType::TemporalOrdered),
+            _ => None,
+        }
+    }
+}
+
+/// 相関ルールから参照されているルールで検知されたレコードの情報
+#[derive(Clone, Debug)]
+struct CorrelationRecordInfo {
+    rule_idx: usize,
+    field_record_value: String,
+    record_time: DateTime<Utc>,
+}
+
+/// 相関ルールのcorrelationを表すノード
+pub struct CorrelationNode {
+    /// rulesに指定されたルールのidまたはname
+    rule_refs: Vec<String>,
+    /// rule_refsに対応するルール。見つからなかったルールはNoneとなる
+    base_rules: Vec<Option<RuleNode>>,
+    correlation_type: Option<CorrelationType>,
+    group_by: Vec<String>,
+    timespan: i64,
+    field_name: Option<String>,
+    conditions: Vec<(AggregationConditionToken, i64)>,
+    /// group-byの値ごとの検知レコードの一覧
+    countdata: HashMap<String, Vec<CorrelationRecordInfo>>,
+}
+
+/// YAMLが相関ルールであればtrueを返す
+pub fn is_correlation_rule(yaml: &Yaml) -> bool {
+    !yaml["correlation"].is_badvalue()
+}
+
+/// 相関ルールのrulesに指定されたルールのidまたはnameの一覧を返す
+pub fn get_rule_refs(yaml: &Yaml) -> Vec<String> {
+    let rules = &yaml["correlation"]["rules"];
+    if let Some(rule_ref) = rules.as_str() {
+        return vec![rule_ref.to_string()];
+    }
+    rules
+        .as_vec()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter_map(|x| x.as_str().map(|s| s.to_string()))
+        .collect()
+}
+
+impl CorrelationNode {
+    /// 相関ルールが参照しているルールを読み込み済みのルールファイルの中からidまたはnameで探す
+    pub fn new(yaml: &Yaml, rule_files: &[(String, Yaml)]) -> CorrelationNode {
+        let rule_refs = get_rule_refs(yaml);
+        let base_rules = rule_refs
+            .iter()
+            .map(|rule_ref| {
+                rule_files
+                    .iter()
+                    .find(|(_, rule_yaml)| {
+                        rule_yaml["id"].as_str() == Some(rule_ref)
+                            || rule_yaml["name"].as_str() == Some(rule_ref)
+                    })
+                    .map(|(rulepath, rule_yaml)| create_rule(rulepath.clone(), rule_yaml.clone()))
+            })
+            .collect();
+        CorrelationNode {
+            rule_refs,
+            base_rules,
+            correlation_type: None,
+            group_by: vec![],
+            timespan: 0,
+            field_name: None,
+            conditions: vec![],
+            countdata: HashMap::new(),
+        }
+    }
+
+    pub fn init(
+        &mut self,
+        correlation_yaml: &Yaml,
+        stored_static: &StoredStatic,
+    ) -> Result<(), Vec<String>> {
+        let mut err_msgs = vec![];
+
+        let type_str = correlation_yaml["type"].as_str().unwrap_or_default();
+        self.correlation_type = CorrelationType::parse(type_str);
+        if self.correlation_type.is_none() {
+            err_msgs.push(format!(
+                "The correlation type is invalid. [type:{type_str}]"
+            ));
+        }
+
+        // 参照しているルールの初期化
+        if self.rule_refs.is_empty() {
+            err_msgs.push("There are no rules referenced by the correlation.".to_string());
+        }
+        for (rule_ref, base_rule) in self.rule_refs.iter().zip(self.base_rules.iter_mut()) {
+            match base_rule {
+                Some(rule) => {
+                    if let Err(err_detail) = rule.init(stored_static) {
+                        err_msgs.extend(err_detail);
+                    } else if rule.has_agg_condition() {
+                        err_msgs.push(format!(
+                            "Rules with an aggregation condition cannot be referenced by the correlation. [rule:{rule_ref}]"
+                        ));
+                    }
+                }
+                None => err_msgs.push(format!(
+                    "The rule referenced by the correlation was not found. [rule:{rule_ref}]"
+                )),
+            }
+        }
+
+        self.group_by = match &correlation_yaml["group-by"] {
+            Yaml::String(s) => vec![s.to_string()],
+            Yaml::Array(fields) => fields
+                .iter()
+                .filter_map(|x| x.as_str().map(|s| s.to_string()))
+                .collect(),
+            _ => vec![],
+        };
+
+        match correlation_yaml["timespan"].as_str() {
+            Some(timespan) => {
+                let tfi = TimeFrameInfo::parse_tframe(timespan.to_string(), stored_static);
+                match tfi.to_sec() {
+                    Some(sec) => self.timespan = sec,
+                    None => err_msgs.push(format!(
+                        "The timespan of the correlation is invalid. [timespan:{timespan}]"
+                    )),
+                }
+            }
+            None => err_msgs.push("The timespan of the correlation was not found.".to_string()),
+        }
+
+        // conditionをパースする。temporalの場合のみ省略可能で、省略時は全てのルールが検知することを条件とする
+        let condition_yaml = &correlation_yaml["condition"];
+        if let Some(condition_hash) = condition_yaml.as_hash() {
+            for (key, val) in condition_hash {
+                let key = key.as_str().unwrap_or_default();
+                if key == "field" {
+                    self.field_name = val.as_str().map(|s| s.to_string());
+                    continue;
+                }
+                let op = match key {
+                    "gt" => AggregationConditionToken::GT,
+                    "gte" => AggregationConditionToken::GE,
+                    "lt" => AggregationConditionToken::LT,
+                    "lte" => AggregationConditionToken::LE,
+                    "eq" => AggregationConditionToken::EQ,
+                    _ => {
+                        err_msgs.push(format!(
+                            "The condition operator of the correlation is invalid. [operator:{key}]"
+                        ));
+                        continue;
+                    }
+                };
+                match val.as_i64() {
+                    Some(num) => self.conditions.push((op, num)),
+                    None => err_msgs.push(format!(
+                        "The condition value of the correlation should be a number. [operator:{key}]"
+                    )),
+                }
+            }
+        }
+        match self.correlation_type {
+            Some(CorrelationType::Temporal) | Some(CorrelationType::TemporalOrdered)
+                if self.conditions.is_empty() =>
+            {
+                self.conditions
+                    .push((AggregationConditionToken::GE, self.rule_refs.len() as i64));
+            }
+            Some(CorrelationType::ValueCount) if self.field_name.is_none() => {
+                err_msgs.push(
+                    "The field of the condition is required for value_count correlations."
+                        .to_string(),
+                );
+            }
+            _ => {}
+        }
+        if self.conditions.is_empty() && !condition_yaml.is_badvalue() {
+            err_msgs.push("There is no condition operator in the correlation.".to_string());
+        } else if self.conditions.is_empty() {
+            err_msgs.push("The condition of the correlation was not found.".to_string());
+        }
+
+        if err_msgs.is_empty() {
+            Result::Ok(())
+        } else {
+            Result::Err(err_msgs)
+        }
+    }
+
+    /// 参照しているルールでレコードを検知した場合に、group-byの値ごとにレコードの情報を記録する
+    pub fn select(
+        &mut self,
+        event_record: &EvtxRecordInfo,
+        json_input_flag: bool,
+        eventkey_alias: &EventKeyAliasConfig,
+    ) {
+        let mut record_time = None;
+        for (rule_idx, base_rule) in self.base_rules.iter().enumerate() {
+            let base_rule = base_rule.as_ref().unwrap();
+            if !base_rule.detection.select(event_record, eventkey_alias) {
+                continue;
+            }
+
+            let field_record_value = match &self.field_name {
+                Some(field_name) => {
+                    match utils::get_event_value(field_name, &event_record.record, eventkey_alias) {
+                        Some(value) => value.to_string().replace('\"', ""),
+                        // value_countではfieldが存在しないレコードは数えない
+                        None => continue,
+                    }
+                }
+                None => String::default(),
+            };
+            let key = self
+                .group_by
+                .iter()
+                .map(|field_name| {
+                    let value =
+                        utils::get_event_value(field_name, &event_record.record, eventkey_alias)
+                            .map(|value| value.to_string().replace('\"', ""))
+                            .unwrap_or_else(|| "-".to_string());
+                    format!("{field_name}:{value}")
+                })
+                .join(" ");
+            let record_time = *record_time.get_or_insert_with(|| {
+                message::get_event_time(&event_record.record, json_input_flag)
+                    .unwrap_or(Utc.with_ymd_and_hms(1977, 1, 1, 0, 0, 0).unwrap())
+            });
+            self.countdata
+                .entry(key)
+                .or_default()
+                .push(CorrelationRecordInfo {
+                    rule_idx,
+                    field_record_value,
+                    record_time,
+                });
+        }
+    }
+
+    /// group-byの値ごとに、timespan内で相関ルールのconditionを満たしたAggResultを配列として返却する
+    pub fn judge_satisfy_condition(&self) -> Vec<AggResult> {
+        let mut ret = vec![];
+        for (key, time_datas) in self.countdata.iter() {
+            ret.extend(self.judge_timespan(key, time_datas));
+        }
+        ret
+    }
+
+    fn judge_timespan(&self, key: &str, time_datas: &[CorrelationRecordInfo]) -> Vec<AggResult> {
+        let mut ret = vec![];
+        let mut datas = time_datas.to_owned();
+        datas.sort_by_key(|data| data.record_time);
+
+        // left <= i < rightの範囲にあるdata[i]がtimespan内にあるデータであると考える
+        let mut left = 0;
+        let mut right = 0;
+        let mut event_cnt = 0;
+        let mut value_2_cnt: HashMap<&str, i64> = HashMap::new();
+        let mut rule_2_cnt: HashMap<usize, i64> = HashMap::new();
+        while left < datas.len() {
+            while right < datas.len()
+                && (datas[right].record_time - datas[left].record_time).num_milliseconds()
+                    <= self.timespan * 1000
+            {
+                event_cnt += 1;
+                *value_2_cnt
+                    .entry(&datas[right].field_record_value)
+                    .or_default() += 1;
+                *rule_2_cnt.entry(datas[right].rule_idx).or_default() += 1;
+                right += 1;
+            }
+
+            let cnt = match self.correlation_type {
+                Some(CorrelationType::EventCount) => event_cnt,
+                Some(CorrelationType::ValueCount) => value_2_cnt.len() as i64,
+                Some(CorrelationType::Temporal) => rule_2_cnt.len() as i64,
+                Some(CorrelationType::TemporalOrdered) => {
+                    // rulesに記載された順番でルールが検知した数を数える
+                    datas[left..right].iter().fold(0, |next_idx, data| {
+                        if data.rule_idx == next_idx {
+                            next_idx + 1
+                        } else {
+                            next_idx
+                        }
+                    }) as i64
+                }
+                None => 0,
+            };
+            if self.select_condition(cnt) {
+                // 条件を満たすtimespanが見つかったので、そのtimespanに含まれるデータは以降の判定に使用しない
+                let field_values = if self.field_name.is_some() {
+                    value_2_cnt.keys().map(|x| x.to_string()).sorted().collect()
+                } else {
+                    vec![]
+                };
+                let contributing_rules = rule_2_cnt
+                    .keys()
+                    .sorted()
+                    .map(|idx| {
+                        let base_rule = self.base_rules[*idx].as_ref().unwrap();
+                        base_rule.yaml["title"]
+                            .as_str()
+                            .unwrap_or(&self.rule_refs[*idx])
+                            .to_string()
+                    })
+                    .collect();
+                let mut agg_result = AggResult::new(
+                    cnt,
+                    key.to_string(),
+                    field_values,
+                    datas[left].record_time,
+                    self.get_str_condition(),
+                );
+                agg_result.contributing_rules = contributing_rules;
+                ret.push(agg_result);
+
+                event_cnt = 0;
+                value_2_cnt.clear();
+                rule_2_cnt.clear();
+                left = right;
+            } else {
+                // 条件を満たさなかったので、leftのデータをtimespanから除外する
+                event_cnt -= 1;
+                let value = &datas[left].field_record_value;
+                if value_2_cnt[value.as_str()] <= 1 {
+                    value_2_cnt.remove(value.as_str());
+                } else {
+                    *value_2_cnt.get_mut(value.as_str()).unwrap() -= 1;
+                }
+                let rule_idx = datas[left].rule_idx;
+                if rule_2_cnt[&rule_idx] <= 1 {
+                    rule_2_cnt.remove(&rule_idx);
+                } else {
+                    *rule_2_cnt.get_mut(&rule_idx).unwrap() -= 1;
+                }
+                left += 1;
+            }
+        }
+        ret
+    }
+
+    /// conditionに指定された全ての条件を満たすか判定する
+    fn select_condition(&self, cnt: i64) -> bool {
+        self.conditions.iter().all(|(op, num)| match op {
+            AggregationConditionToken::EQ => cnt == *num,
+            AggregationConditionToken::GE => cnt >= *num,
+            AggregationConditionToken::GT => cnt > *num,
+            AggregationConditionToken::LE => cnt <= *num,
+            AggregationConditionToken::LT => cnt < *num,
+            _ => false,
+        })
+    }
+
+    /// conditionの条件式を文字として返す
+    fn get_str_condition(&self) -> String {
+        self.conditions
+            .iter()
+            .map(|(op, num)| {
+                let op_str = match op {
+                    AggregationConditionToken::EQ => "==",
+                    AggregationConditionToken::GE => ">=",
+                    AggregationConditionToken::GT => ">",
+                    AggregationConditionToken::LE => "<=",
+                    AggregationConditionToken::LT => "<",
+                    _ => "",
+                };
+                format!("{op_str} {num}")
+            })
+            .join(" and ")
+    }
+
+    /// 参照しているルールの一覧を返す
+    pub fn get_base_rules(&self) -> impl Iterator<Item = &RuleNode> {
+        self.base_rules.iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections;
+    use crate::detections::configs::STORED_EKEY_ALIAS;
+    use crate::detections::rule::tests::create_dummy_stored_static;
+    use crate::detections::rule::{create_correlation_rule, AggResult, RuleNode};
+    use crate::detections::utils;
+    use yaml_rust::{Yaml, YamlLoader};
+
+    const FAILED_LOGON_RULE: &str = r#"
+    title: Failed Logon
+    id: 00000000-0000-0000-0000-000000000001
+    name: failed_logon
+    detection:
+        selection:
+            EventID: 4625
+        condition: selection
+    "#;
+
+    const SUCCESSFUL_LOGON_RULE: &str = r#"
+    title: Successful Logon
+    id: 00000000-0000-0000-0000-000000000002
+    name: successful_logon
+    detection:
+        selection:
+            EventID: 4624
+        condition: selection
+    "#;
+
+    fn create_recstr(event_id: &str, time: &str, user: &str, ip: &str) -> String {
+        let template: &str = r#"
+    {
+      "Event": {
+        "System": {
+          "EventID": ${EVENT_ID},
+          "TimeCreated_attributes": {
+            "SystemTime": "${TIME}"
+          }
+        },
+        "EventData": {
+          "TargetUserName": "${USER}",
+          "IpAddress": "${IP}"
+        }
+      }
+    }"#;
+        template
+            .replace("${EVENT_ID}", event_id)
+            .replace("${TIME}", time)
+            .replace("${USER}", user)
+            .replace("${IP}", ip)
+    }
+
+    fn create_correlation_rule_node(correlation_str: &str) -> Result<RuleNode, Vec<String>> {
+        let rule_files: Vec<(String, Yaml)> = [FAILED_LOGON_RULE, SUCCESSFUL_LOGON_RULE]
+            .iter()
+            .map(|rule_str| {
+                (
+                    "testpath".to_string(),
+                    YamlLoader::load_from_str(rule_str).unwrap().remove(0),
+                )
+            })
+            .collect();
+        let correlation_yaml = YamlLoader::load_from_str(correlation_str)
+            .unwrap()
+            .remove(0);
+        let mut rule_node =
+            create_correlation_rule("testpath".to_string(), correlation_yaml, &rule_files);
+        rule_node.init(&create_dummy_stored_static())?;
+        Ok(rule_node)
+    }
+
+    fn check_correlation(correlation_str: &str, records_str: &[String]) -> Vec<AggResult> {
+        let mut rule_node = create_correlation_rule_node(correlation_str).unwrap();
+        let dummy_stored_static = create_dummy_stored_static();
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(dummy_stored_static.eventkey_alias.clone());
+        for record_str in records_str {
+            let record = serde_json::from_str(record_str).unwrap();
+            let keys = detections::rule::get_detection_keys(&rule_node);
+            let recinfo =
+                utils::create_rec_info(record, "testpath".to_owned(), &keys, &false, &false);
+            // 相関ルールはレコード単位では検知しない
+            assert!(!rule_node.select(
+                &recinfo,
+                dummy_stored_static.verbose_flag,
+                dummy_stored_static.quiet_errors_flag,
+                dummy_stored_static.json_input_flag,
+                &dummy_stored_static.eventkey_alias,
+            ));
+        }
+        let mut agg_results = rule_node.judge_satisfy_aggcondition(&dummy_stored_static);
+        agg_results.sort_by_key(|agg_result| agg_result.start_timedate);
+        agg_results
+    }
+
+    #[test]
+    fn test_correlation_event_count() {
+        let correlation_str = r#"
+        title: Many Failed Logons
+        correlation:
+            type: event_count
+            rules:
+                - failed_logon
+            group-by:
+                - TargetUserName
+            timespan: 5m
+            condition:
+                gte: 3
+        "#;
+        let records = vec![
+            create_recstr("4625", "2022-01-01T00:00:00Z", "alice", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:01:00Z", "alice", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:02:00Z", "bob", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:04:00Z", "alice", "10.0.0.1"),
+            create_recstr("4624", "2022-01-01T00:04:00Z", "alice", "10.0.0.1"),
+            // timespanの外なので数えない
+            create_recstr("4625", "2022-01-01T01:00:00Z", "bob", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T01:00:00Z", "bob", "10.0.0.1"),
+        ];
+        let agg_results = check_correlation(correlation_str, &records);
+        assert_eq!(agg_results.len(), 1);
+        assert_eq!(agg_results[0].data, 3);
+        assert_eq!(agg_results[0].key, "TargetUserName:alice");
+        assert_eq!(agg_results[0].condition_op_num, ">= 3");
+        assert_eq!(agg_results[0].contributing_rules, vec!["Failed Logon"]);
+    }
+
+    #[test]
+    fn test_correlation_value_count() {
+        let correlation_str = r#"
+        title: Password Spraying
+        correlation:
+            type: value_count
+            rules:
+                - 00000000-0000-0000-0000-000000000001
+            group-by:
+                - IpAddress
+            timespan: 1h
+            condition:
+                field: TargetUserName
+                gt: 2
+        "#;
+        let records = vec![
+            create_recstr("4625", "2022-01-01T00:00:00Z", "alice", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:10:00Z", "alice", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:20:00Z", "bob", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:30:00Z", "carol", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:30:00Z", "alice", "10.0.0.2"),
+        ];
+        let agg_results = check_correlation(correlation_str, &records);
+        assert_eq!(agg_results.len(), 1);
+        assert_eq!(agg_results[0].data, 3);
+        assert_eq!(agg_results[0].key, "IpAddress:10.0.0.1");
+        assert_eq!(agg_results[0].field_values, vec!["alice", "bob", "carol"]);
+    }
+
+    #[test]
+    fn test_correlation_temporal() {
+        let correlation_str = r#"
+        title: Failed Logon Followed By Success
+        correlation:
+            type: temporal
+            rules:
+                - failed_logon
+                - successful_logon
+            group-by:
+                - TargetUserName
+            timespan: 10m
+        "#;
+        let records = vec![
+            create_recstr("4624", "2022-01-01T00:00:00Z", "alice", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:05:00Z", "alice", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:05:00Z", "bob", "10.0.0.1"),
+        ];
+        let agg_results = check_correlation(correlation_str, &records);
+        assert_eq!(agg_results.len(), 1);
+        assert_eq!(agg_results[0].key, "TargetUserName:alice");
+        assert_eq!(
+            agg_results[0].contributing_rules,
+            vec!["Failed Logon", "Successful Logon"]
+        );
+    }
+
+    #[test]
+    fn test_correlation_temporal_ordered() {
+        let correlation_str = r#"
+        title: Failed Logon Followed By Success
+        correlation:
+            type: temporal_ordered
+            rules:
+                - failed_logon
+                - successful_logon
+            group-by:
+                - TargetUserName
+            timespan: 10m
+        "#;
+        let records = vec![
+            // 順番が逆なので検知しない
+            create_recstr("4624", "2022-01-01T00:00:00Z", "alice", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:05:00Z", "alice", "10.0.0.1"),
+            create_recstr("4625", "2022-01-01T00:05:00Z", "bob", "10.0.0.1"),
+            create_recstr("4624", "2022-01-01T00:06:00Z", "bob", "10.0.0.1"),
+        ];
+        let agg_results = check_correlation(correlation_str, &records);
+        assert_eq!(agg_results.len(), 1);
+        assert_eq!(agg_results[0].key, "TargetUserName:bob");
+        assert_eq!(agg_results[0].data, 2);
+        assert_eq!(agg_results[0].condition_op_num, ">= 2");
+    }
+
+    #[test]
+    fn test_correlation_parse_error() {
+        let correlation_str = r#"
+        title: Invalid Correlation
+        correlation:
+            type: unknown_count
+            rules:
+                - not_exists_rule
+            condition:
+                gte: 'a'
+        "#;
+        assert_eq!(
+            create_correlation_rule_node(correlation_str).unwrap_err(),
+            vec![
+                "The correlation type is invalid. [type:unknown_count]".to_string(),
+                "The rule referenced by the correlation was not found. [rule:not_exists_rule]"
+                    .to_string(),
+                "The timespan of the correlation was not found.".to_string(),
+                "The condition value of the correlation should be a number. [operator:gte]"
+                    .to_string(),
+                "There is no condition operator in the correlation.".to_string(),
+            ]
+        );
+    }
+}
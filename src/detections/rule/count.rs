@@ -7,12 +7,13 @@ use crate::detections::message::ERROR_LOG_STACK;
 use crate::detections::rule::AggResult;
 use crate::detections::rule::RuleNode;
 use chrono::{DateTime, TimeZone, Utc};
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use serde_json::Value;
 use std::num::ParseIntError;
 use std::path::Path;
 
-use crate::detections::rule::aggregation_parser::AggregationConditionToken;
+use crate::detections::rule::aggregation_parser::{AggregationConditionToken, AggregationFunction};
+use std::collections::VecDeque;
 
 use crate::detections::utils;
 
@@ -167,6 +168,18 @@ pub fn aggregation_condition_select(
     ret
 }
 
+/// 既に出力したAggregation Conditionの結果(count byの値と検知したブロックの最初のレコードの時間)を除いて返す関数。
+/// --followでcountdataを保持したまま判定を繰り返す場合に、同じ結果を重複して出力しないために使う
+pub fn select_new_aggresults(
+    reported: &mut HashSet<(String, DateTime<Utc>)>,
+    results: Vec<AggResult>,
+) -> Vec<AggResult> {
+    results
+        .into_iter()
+        .filter(|result| reported.insert((result.key.clone(), result.start_timedate)))
+        .collect()
+}
+
 /// aggregation condition内での条件式を文字として返す関数
 pub fn get_str_agg_eq(rule: &RuleNode) -> String {
     //この関数はaggregation ruleのパースが正常終了した後に呼ばれる想定のためOptionの判定は行わない
@@ -244,6 +257,20 @@ impl TimeFrameInfo {
             timenum: target_val.parse::<i64>(),
         }
     }
+
+    /// timeframeの値を秒数に変換する関数。数値が不正な場合はNoneを返す
+    pub fn to_sec(&self) -> Option<i64> {
+        let n = self.timenum.as_ref().ok()?;
+        if self.timetype == "d" {
+            Some(n * 86400)
+        } else if self.timetype == "h" {
+            Some(n * 3600)
+        } else if self.timetype == "m" {
+            Some(n * 60)
+        } else {
+            Some(*n)
+        }
+    }
 }
 
 /// TimeFrameInfoで格納されたtimeframeの値を秒数に変換した結果を返す関数
@@ -251,17 +278,7 @@ pub fn get_sec_timeframe(rule: &RuleNode, stored_static: &StoredStatic) -> Optio
     let timeframe = rule.detection.timeframe.as_ref();
     let tfi = timeframe?;
     match &tfi.timenum {
-        Ok(n) => {
-            if tfi.timetype == "d" {
-                Some(n * 86400)
-            } else if tfi.timetype == "h" {
-                Some(n * 3600)
-            } else if tfi.timetype == "m" {
-                Some(n * 60)
-            } else {
-                Some(*n)
-            }
-        }
+        Ok(_) => tfi.to_sec(),
         Err(err) => {
             let errmsg = format!("Timeframe number is invalid. timeframe. {err}");
             if stored_static.verbose_flag {
@@ -278,19 +295,21 @@ pub fn get_sec_timeframe(rule: &RuleNode, stored_static: &StoredStatic) -> Optio
     }
 }
 /// conditionのパイプ以降の処理をAggregationParseInfoを参照し、conditionの条件を満たすか判定するための関数
-pub fn select_aggcon(cnt: i64, rule: &RuleNode) -> bool {
+/// valueはcountの場合は件数、min, max, avg, sumの場合は集計した値
+pub fn select_aggcon(value: f64, rule: &RuleNode) -> bool {
     let agg_condition = rule.detection.aggregation_condition.as_ref();
     if agg_condition.is_none() {
         return false;
     }
 
     let agg_condition = agg_condition.unwrap();
+    let cmp_num = agg_condition._cmp_num as f64;
     match agg_condition._cmp_op {
-        AggregationConditionToken::EQ => cnt == agg_condition._cmp_num,
-        AggregationConditionToken::GE => cnt >= agg_condition._cmp_num,
-        AggregationConditionToken::GT => cnt > agg_condition._cmp_num,
-        AggregationConditionToken::LE => cnt <= agg_condition._cmp_num,
-        AggregationConditionToken::LT => cnt < agg_condition._cmp_num,
+        AggregationConditionToken::EQ => value == cmp_num,
+        AggregationConditionToken::GE => value >= cmp_num,
+        AggregationConditionToken::GT => value > cmp_num,
+        AggregationConditionToken::LE => value <= cmp_num,
+        AggregationConditionToken::LT => value < cmp_num,
         _ => false,
     }
 }
@@ -324,6 +343,12 @@ trait CountStrategy {
      * count()の値を返します。
      */
     fn count(&mut self) -> i64;
+    /**
+     * conditionの比較演算子で比較する値を返します。count()の場合は件数です。
+     */
+    fn value(&mut self) -> f64 {
+        self.count() as f64
+    }
     /**
      * AggResultを作成します。
      */
@@ -451,9 +476,87 @@ impl CountStrategy for NoFieldStrategy {
     }
 }
 
+/**
+ * min, max, avg, sumの集計関数が指定されている場合のjudgeの計算方法を表す構造体
+ */
+struct NumericStrategy {
+    function: AggregationFunction,
+    /// timeframe内のデータのindexと数値。数値に変換できないデータは含まない
+    values: VecDeque<(i64, f64)>,
+}
+
+impl CountStrategy for NumericStrategy {
+    fn add_data(&mut self, idx: i64, datas: &[AggRecordTimeInfo], _rule: &RuleNode) {
+        if idx >= datas.len() as i64 || idx < 0 {
+            return;
+        }
+
+        if let Ok(value) = datas[idx as usize].field_record_value.parse::<f64>() {
+            self.values.push_back((idx, value));
+        }
+    }
+
+    fn remove_data(&mut self, idx: i64, datas: &[AggRecordTimeInfo], _rule: &RuleNode) {
+        if idx >= datas.len() as i64 || idx < 0 {
+            return;
+        }
+
+        // timeframeからは先頭のデータから順に削除される
+        if let Some((front_idx, _)) = self.values.front() {
+            if *front_idx == idx {
+                self.values.pop_front();
+            }
+        }
+    }
+
+    fn count(&mut self) -> i64 {
+        self.values.len() as i64
+    }
+
+    fn value(&mut self) -> f64 {
+        if self.values.is_empty() {
+            // 集計対象のデータがない場合はどの条件も満たさないようにする
+            return f64::NAN;
+        }
+        let values = self.values.iter().map(|(_, value)| *value);
+        match self.function {
+            AggregationFunction::Min => values.fold(f64::INFINITY, f64::min),
+            AggregationFunction::Max => values.fold(f64::NEG_INFINITY, f64::max),
+            AggregationFunction::Sum => values.sum(),
+            AggregationFunction::Avg => values.sum::<f64>() / self.values.len() as f64,
+            AggregationFunction::Count => self.values.len() as f64,
+        }
+    }
+
+    fn create_agg_result(
+        &mut self,
+        left: i64,
+        datas: &[AggRecordTimeInfo],
+        cnt: i64,
+        key: &str,
+        rule: &RuleNode,
+    ) -> AggResult {
+        let mut ret = AggResult::new(
+            cnt,
+            key.to_string(),
+            vec![],
+            datas[left as usize].record_time,
+            get_str_agg_eq(rule),
+        );
+        ret.agg_value = Some(self.value());
+        self.values.clear(); //valuesを初期化
+        ret
+    }
+}
+
 fn _create_counter(rule: &RuleNode) -> Box<dyn CountStrategy> {
     let agg_cond = rule.get_agg_condition().unwrap();
-    if agg_cond._field_name.is_some() {
+    if agg_cond._function != AggregationFunction::Count {
+        Box::new(NumericStrategy {
+            function: agg_cond._function.clone(),
+            values: VecDeque::new(),
+        })
+    } else if agg_cond._field_name.is_some() {
         Box::new(FieldStrategy {
             value_2_cnt: HashMap::new(),
         })
@@ -518,7 +621,7 @@ pub fn judge_timeframe(
         }
 
         let cnt = counter.count();
-        if select_aggcon(cnt, rule) {
+        if select_aggcon(counter.value(), rule) {
             // 条件を満たすtimeframeが見つかった
             ret.push(counter.create_agg_result(left, &datas, cnt, key, rule));
             left = right;
@@ -553,7 +656,9 @@ mod tests {
     use crate::detections::utils;
     use chrono::DateTime;
     use chrono::NaiveDate;
-    use hashbrown::HashMap;
+    use hashbrown::{HashMap, HashSet};
+
+    use super::select_new_aggresults;
 
     use chrono::{TimeZone, Utc};
     use yaml_rust::YamlLoader;
@@ -585,6 +690,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -624,6 +730,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -637,6 +744,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -697,6 +810,42 @@ mod tests {
         );
     }
 
+    #[test]
+    /// --followで判定を繰り返す場合に、既に出力したAggregation Conditionの結果が除かれることのテスト
+    fn test_select_new_aggresults() {
+        let create_result = |key: &str, cnt: i64, day: u32| {
+            AggResult::new(
+                cnt,
+                key.to_string(),
+                vec![],
+                Utc.with_ymd_and_hms(1996, 2, day, 1, 5, 1).unwrap(),
+                ">= 1".to_string(),
+            )
+        };
+        let mut reported = HashSet::new();
+        let first = select_new_aggresults(
+            &mut reported,
+            vec![create_result("_", 1, 27), create_result("user1", 1, 27)],
+        );
+        assert_eq!(first.len(), 2);
+
+        // 同じキーと開始時間の結果はカウントが増えても出力しない
+        let second = select_new_aggresults(
+            &mut reported,
+            vec![
+                create_result("_", 2, 27),
+                create_result("user1", 1, 27),
+                create_result("user1", 1, 28),
+            ],
+        );
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].key, "user1");
+        assert_eq!(
+            second[0].start_timedate,
+            Utc.with_ymd_and_hms(1996, 2, 28, 1, 5, 1).unwrap()
+        );
+    }
+
     #[test]
     /// countのカッコ内の記載及びcount byの記載がない場合(timeframeあり)にruleで検知ができることのテスト
     fn test_count_no_field_and_by_with_timeframe() {
@@ -1661,6 +1810,97 @@ mod tests {
         check_count(&rule_str, &recs, expected_count, expected_agg_result);
     }
 
+    #[test]
+    /// min, max, avg, sumで集計した値がtimeframe単位で条件を満たすかのテスト
+    fn test_aggregation_functions_with_timeframe() {
+        let recs = vec![
+            test_create_recstr("1", "1977-01-09T00:30:00Z", "40"),
+            test_create_recstr("1", "1977-01-09T00:35:00Z", "70"),
+            test_create_recstr("1", "1977-01-09T00:39:00Z", "not number"),
+            // timeframeの外なので先頭のデータと一緒に集計しない
+            test_create_recstr("1", "1977-01-09T01:30:00Z", "10"),
+        ];
+        let first_time = Utc.with_ymd_and_hms(1977, 1, 9, 0, 30, 0).unwrap();
+        for (function, cmp, expected_value) in [
+            ("sum", "> 100", 110.0),
+            ("max", ">= 70", 70.0),
+            ("min", "== 40", 40.0),
+            ("avg", "== 55", 55.0),
+        ] {
+            let rule_str = format!(
+                r#"
+        enabled: true
+        detection:
+            selection1:
+                EventID: 1
+            condition: selection1 | {function}(param1) {cmp}
+            timeframe: 10m
+        "#
+            );
+            let agg_results = judge_records(&rule_str, &recs);
+            assert_eq!(agg_results.len(), 1);
+            assert_eq!(agg_results[0].data, 2);
+            assert_eq!(agg_results[0].agg_value, Some(expected_value));
+            assert_eq!(agg_results[0].start_timedate, first_time);
+            assert_eq!(agg_results[0].condition_op_num, cmp);
+        }
+
+        // 条件を満たさない場合
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection1:
+                EventID: 1
+            condition: selection1 | sum(param1) > 110
+            timeframe: 10m
+        "#;
+        assert!(judge_records(rule_str, &recs).is_empty());
+    }
+
+    #[test]
+    /// min, max, avg, sumでbyを指定した場合のテスト
+    fn test_aggregation_functions_with_by() {
+        let recs = vec![
+            test_create_recstr("1", "1977-01-09T00:30:00Z", "40"),
+            test_create_recstr("2", "1977-01-09T00:35:00Z", "70"),
+            test_create_recstr("1", "1977-01-09T00:39:00Z", "20"),
+        ];
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection1:
+                param1|exists: true
+            condition: selection1 | max(param1) by EventID >= 50
+        "#;
+        let agg_results = judge_records(rule_str, &recs);
+        assert_eq!(agg_results.len(), 1);
+        assert_eq!(agg_results[0].key, "2");
+        assert_eq!(agg_results[0].agg_value, Some(70.0));
+    }
+
+    /// レコードをルールで検知し、aggregation conditionの結果を返すテスト用関数
+    fn judge_records(rule_str: &str, records_str: &[String]) -> Vec<AggResult> {
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let mut rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+        let dummy_stored_static = create_dummy_stored_static();
+        assert!(rule_node.init(&dummy_stored_static).is_ok());
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(dummy_stored_static.eventkey_alias.clone());
+        for record_str in records_str {
+            let record = serde_json::from_str(record_str).unwrap();
+            let keys = detections::rule::get_detection_keys(&rule_node);
+            let recinfo =
+                utils::create_rec_info(record, "testpath".to_owned(), &keys, &false, &false);
+            rule_node.select(
+                &recinfo,
+                dummy_stored_static.verbose_flag,
+                dummy_stored_static.quiet_errors_flag,
+                dummy_stored_static.json_input_flag,
+                &dummy_stored_static.eventkey_alias,
+            );
+        }
+        rule_node.judge_satisfy_aggcondition(&dummy_stored_static)
+    }
+
     fn test_create_recstr_std(event_id: &str, time: &str) -> String {
         test_create_recstr(event_id, time, "Windows Event Log")
     }
@@ -0,0 +1,536 @@
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+use hashbrown::{HashMap, HashSet};
+use std::borrow::Cow;
+use yaml_rust::Yaml;
+
+use super::matchers::DefaultMatcher;
+use super::selectionnodes::{
+    AllSelectionNode, AndSelectionNode, CountSelectionNode, LeafSelectionNode, OrSelectionNode,
+    RefSelectionNode, SelectionNode,
+};
+use super::RuleNode;
+use crate::detections::detection::EvtxRecordInfo;
+
+/// ルールが検知対象とするChannelとEventIDの一覧と、レコードに含まれている必要がある文字列の一覧を表す構造体
+/// Noneの場合はそのフィールドの値に関わらず検知される可能性があることを表す。
+/// 値は大文字小文字を区別せずに比較するため、ascii小文字に変換して保持する。
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IndexKey {
+    pub channels: Option<HashSet<String>>,
+    pub event_ids: Option<HashSet<String>>,
+    /// いずれか1つがレコードのフィールドの値に含まれていないと検知されない文字列の一覧
+    pub literals: Option<HashSet<String>>,
+}
+
+impl IndexKey {
+    /// conditionのノードから、ルールが一致する可能性があるChannelとEventIDを抽出する
+    pub fn from_node(node: &dyn SelectionNode) -> IndexKey {
+        if let Some(leaf) = node.downcast_ref::<LeafSelectionNode>() {
+            return Self::from_leaf(leaf);
+        }
+        if let Some(ref_node) = node.downcast_ref::<RefSelectionNode>() {
+            return ref_node
+                .get_childs()
+                .first()
+                .map(|child| Self::from_node(*child))
+                .unwrap_or_default();
+        }
+        if let Some(and_node) = node.downcast_ref::<AndSelectionNode>() {
+            return Self::intersect(and_node.child_nodes.iter().map(|c| c.as_ref()));
+        }
+        if let Some(all_node) = node.downcast_ref::<AllSelectionNode>() {
+            return Self::intersect(all_node.child_nodes.iter().map(|c| c.as_ref()));
+        }
+        if let Some(or_node) = node.downcast_ref::<OrSelectionNode>() {
+            return Self::union(or_node.child_nodes.iter().map(|c| c.as_ref()));
+        }
+        // N of selection*はどの子ノードが一致するか分からないので、OR条件と同様に和集合をとる
+        if let Some(count_node) = node.downcast_ref::<CountSelectionNode>() {
+            return Self::union(count_node.child_nodes.iter().map(|c| c.as_ref()));
+        }
+        // notなどは対象の値を絞り込めないので、全てのレコードを対象にする
+        IndexKey::default()
+    }
+
+    fn from_leaf(leaf: &LeafSelectionNode) -> IndexKey {
+        let mut ret = IndexKey {
+            literals: Self::get_leaf_literals(leaf),
+            ..Default::default()
+        };
+        // パイプ(modifier)が指定されている場合は完全一致にならないので絞り込まない
+        let keys = leaf.get_key_list();
+        if keys.len() != 1 || keys[0].contains('|') {
+            return ret;
+        }
+        let value = match leaf.get_select_value() {
+            Yaml::Integer(i) => i.to_string(),
+            // ワイルドカードやエスケープを含む値は完全一致にならないので絞り込まない
+            Yaml::String(s) if !s.contains(['*', '?', '\\']) => s.to_ascii_lowercase(),
+            _ => return ret,
+        };
+        // ChannelとEventIDはインデックスで絞り込むので、含まれている必要がある文字列には使わない
+        match &keys[0] {
+            "Channel" => {
+                ret.channels = Some(HashSet::from([value]));
+                ret.literals = None;
+            }
+            "EventID" => {
+                ret.event_ids = Some(HashSet::from([value]));
+                ret.literals = None;
+            }
+            _ => {}
+        }
+        ret
+    }
+
+    fn get_leaf_literals(leaf: &LeafSelectionNode) -> Option<HashSet<String>> {
+        // EventDataはフィールドの値ではなくEvent.EventData.Dataの配列の値で判定されるので対象外
+        let key = leaf.get_key();
+        if key.is_empty() || key == "EventData" || key == "Data" {
+            return None;
+        }
+        let matcher = leaf.matcher.as_ref()?.downcast_ref::<DefaultMatcher>()?;
+        let literals = matcher.get_required_literals()?;
+        Some(literals.iter().map(|s| s.to_ascii_lowercase()).collect())
+    }
+
+    /// AND条件の場合は、子ノードの条件の積集合をとる
+    /// 含まれている必要がある文字列は、最も短い文字列が一番長い子ノードのものを採用する
+    fn intersect<'a>(nodes: impl Iterator<Item = &'a dyn SelectionNode>) -> IndexKey {
+        let intersect_set =
+            |acc: Option<HashSet<String>>, cur: Option<HashSet<String>>| match (acc, cur) {
+                (Some(acc), Some(cur)) => Some(acc.intersection(&cur).cloned().collect()),
+                (acc, cur) => acc.or(cur),
+            };
+        let min_len = |literals: &HashSet<String>| literals.iter().map(|s| s.len()).min();
+        let select_literals =
+            |acc: Option<HashSet<String>>, cur: Option<HashSet<String>>| match (acc, cur) {
+                (Some(acc), Some(cur)) if min_len(&cur) > min_len(&acc) => Some(cur),
+                (acc, cur) => acc.or(cur),
+            };
+        nodes
+            .map(Self::from_node)
+            .fold(IndexKey::default(), |acc, cur| IndexKey {
+                channels: intersect_set(acc.channels, cur.channels),
+                event_ids: intersect_set(acc.event_ids, cur.event_ids),
+                literals: select_literals(acc.literals, cur.literals),
+            })
+    }
+
+    /// OR条件の場合は、全ての子ノードで値が絞り込める場合のみ和集合をとる
+    fn union<'a>(mut nodes: impl Iterator<Item = &'a dyn SelectionNode>) -> IndexKey {
+        let union_set =
+            |acc: Option<HashSet<String>>, cur: Option<HashSet<String>>| match (acc, cur) {
+                (Some(mut acc), Some(cur)) => {
+                    acc.extend(cur);
+                    Some(acc)
+                }
+                _ => None,
+            };
+        let first = match nodes.next() {
+            Some(node) => Self::from_node(node),
+            None => return IndexKey::default(),
+        };
+        nodes.map(Self::from_node).fold(first, |acc, cur| IndexKey {
+            channels: union_set(acc.channels, cur.channels),
+            event_ids: union_set(acc.event_ids, cur.event_ids),
+            literals: union_set(acc.literals, cur.literals),
+        })
+    }
+}
+
+/// ルール読み込み時にChannelとEventIDで作成するルールのインデックス
+/// レコード毎に、検知される可能性があるルールだけを判定するために使う。
+/// また、各ルールに含まれている必要がある文字列をまとめたAho-Corasickのオートマトンで、レコードを事前にフィルタリングする。
+#[derive(Debug, Default)]
+pub struct RuleIndex {
+    rule_cnt: usize,
+    channel_and_event_id: HashMap<(String, String), Vec<usize>>,
+    channel: HashMap<String, Vec<usize>>,
+    event_id: HashMap<String, Vec<usize>>,
+    // ChannelとEventIDのどちらでも絞り込めないルール
+    unindexed: HashSet<usize>,
+    literal_matcher: Option<AhoCorasick>,
+    // ルール毎の含まれている必要がある文字列のliteral_matcherでのパターン番号
+    rule_literals: Vec<Option<Vec<usize>>>,
+    // パターン番号毎の、ChannelとEventIDで絞り込めずにそのパターンを必要とするルール
+    literal_to_unindexed_rules: Vec<Vec<usize>>,
+}
+
+impl RuleIndex {
+    pub fn new(rules: &[RuleNode]) -> RuleIndex {
+        let mut ret = RuleIndex {
+            rule_cnt: rules.len(),
+            ..Default::default()
+        };
+        let mut literal_to_id: HashMap<String, usize> = HashMap::new();
+        for (idx, rule) in rules.iter().enumerate() {
+            let key = rule.get_index_key();
+            let is_unindexed = key.channels.is_none() && key.event_ids.is_none();
+            let literal_ids = key.literals.map(|literals| {
+                literals
+                    .into_iter()
+                    .map(|literal| {
+                        let next_id = literal_to_id.len();
+                        let literal_id = *literal_to_id.entry(literal).or_insert(next_id);
+                        if literal_id == ret.literal_to_unindexed_rules.len() {
+                            ret.literal_to_unindexed_rules.push(vec![]);
+                        }
+                        if is_unindexed {
+                            ret.literal_to_unindexed_rules[literal_id].push(idx);
+                        }
+                        literal_id
+                    })
+                    .collect()
+            });
+            ret.rule_literals.push(literal_ids);
+            match (key.channels, key.event_ids) {
+                (Some(channels), Some(event_ids)) => {
+                    for channel in channels.iter() {
+                        for event_id in event_ids.iter() {
+                            ret.channel_and_event_id
+                                .entry((channel.to_owned(), event_id.to_owned()))
+                                .or_default()
+                                .push(idx);
+                        }
+                    }
+                }
+                (Some(channels), None) => {
+                    for channel in channels {
+                        ret.channel.entry(channel).or_default().push(idx);
+                    }
+                }
+                (None, Some(event_ids)) => {
+                    for event_id in event_ids {
+                        ret.event_id.entry(event_id).or_default().push(idx);
+                    }
+                }
+                (None, None) => {
+                    ret.unindexed.insert(idx);
+                }
+            }
+        }
+        if !literal_to_id.is_empty() {
+            let mut literals: Vec<(String, usize)> = literal_to_id.into_iter().collect();
+            literals.sort_unstable_by_key(|(_, literal_id)| *literal_id);
+            ret.literal_matcher = AhoCorasickBuilder::new()
+                .ascii_case_insensitive(true)
+                .build(literals.iter().map(|(literal, _)| literal))
+                .ok();
+            // オートマトンの作成に失敗した場合は、文字列によるフィルタリングを行わない
+            if ret.literal_matcher.is_none() {
+                ret.rule_literals = vec![None; ret.rule_cnt];
+                ret.literal_to_unindexed_rules.clear();
+            }
+        }
+        ret
+    }
+
+    /// レコードのフィールドの値に含まれている文字列のパターン番号を返す
+    fn find_literals(&self, record: &EvtxRecordInfo) -> HashSet<usize> {
+        let mut ret = HashSet::new();
+        let literal_matcher = match &self.literal_matcher {
+            Some(literal_matcher) => literal_matcher,
+            None => return ret,
+        };
+        for value in record.key_2_value.values() {
+            // ascii以外の文字はUnicodeの大文字小文字の変換でascii文字と一致する場合があるので、小文字に変換してから検索する
+            let haystack = if value.is_ascii() {
+                Cow::Borrowed(value)
+            } else {
+                Cow::Owned(value.to_lowercase().replace('ſ', "s"))
+            };
+            for mat in literal_matcher.find_overlapping_iter(haystack.as_str()) {
+                ret.insert(mat.pattern().as_usize());
+            }
+        }
+        ret
+    }
+
+    fn contains_literals(&self, rule_idx: usize, found_literals: &HashSet<usize>) -> bool {
+        match &self.rule_literals[rule_idx] {
+            Some(literal_ids) => literal_ids.iter().any(|id| found_literals.contains(id)),
+            None => true,
+        }
+    }
+
+    /// ルール毎に判定対象のレコードのインデックスを返す。
+    /// インデックスで絞り込めないルールは全てのレコードが対象になるため、Noneを返す。
+    pub fn get_target_records(&self, records: &[EvtxRecordInfo]) -> Vec<Option<Vec<usize>>> {
+        let mut ret: Vec<Option<Vec<usize>>> = (0..self.rule_cnt)
+            .map(|idx| {
+                if self.unindexed.contains(&idx) && self.rule_literals[idx].is_none() {
+                    None
+                } else {
+                    Some(vec![])
+                }
+            })
+            .collect();
+        let empty = String::default();
+        for (record_idx, record) in records.iter().enumerate() {
+            let found_literals = self.find_literals(record);
+            let channel = record
+                .get_value("Channel")
+                .unwrap_or(&empty)
+                .to_ascii_lowercase();
+            let event_id = record
+                .get_value("EventID")
+                .unwrap_or(&empty)
+                .to_ascii_lowercase();
+            let candidates = self
+                .channel_and_event_id
+                .get(&(channel.clone(), event_id.clone()))
+                .into_iter()
+                .chain(self.channel.get(&channel))
+                .chain(self.event_id.get(&event_id))
+                .flatten()
+                .filter(|rule_idx| self.contains_literals(**rule_idx, &found_literals));
+            // ChannelとEventIDで絞り込めないルールは、含まれている文字列から対象のルールを探す
+            let literal_candidates = found_literals
+                .iter()
+                .flat_map(|literal_id| &self.literal_to_unindexed_rules[*literal_id]);
+            for rule_idx in candidates.chain(literal_candidates) {
+                if let Some(Some(record_idxes)) = ret.get_mut(*rule_idx) {
+                    // 複数の文字列が含まれている場合に、同じレコードを重複して追加しないようにする
+                    if record_idxes.last() != Some(&record_idx) {
+                        record_idxes.push(record_idx);
+                    }
+                }
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashSet;
+    use nested::Nested;
+    use serde_json::Value;
+
+    use super::{IndexKey, RuleIndex};
+    use crate::detections::configs::STORED_EKEY_ALIAS;
+    use crate::detections::rule::tests::{create_dummy_stored_static, parse_rule_from_str};
+    use crate::detections::rule::RuleNode;
+    use crate::detections::utils;
+
+    fn to_set(values: &[&str]) -> Option<HashSet<String>> {
+        Some(values.iter().map(|v| v.to_string()).collect())
+    }
+
+    fn get_index_key(rule_str: &str) -> IndexKey {
+        let mut rule_node = parse_rule_from_str(rule_str);
+        rule_node.init(&create_dummy_stored_static()).unwrap();
+        rule_node.get_index_key()
+    }
+
+    #[test]
+    fn test_index_key_channel_and_event_id() {
+        let rule_str = r#"
+        detection:
+            selection:
+                Channel: Security
+                EventID:
+                    - 4624
+                    - '4625'
+            condition: selection
+        "#;
+        let key = get_index_key(rule_str);
+        assert_eq!(key.channels, to_set(&["security"]));
+        assert_eq!(key.event_ids, to_set(&["4624", "4625"]));
+    }
+
+    #[test]
+    fn test_index_key_or_condition() {
+        let rule_str = r#"
+        detection:
+            selection1:
+                Channel: Security
+                EventID: 4624
+            selection2:
+                Channel: System
+            condition: selection1 or selection2
+        "#;
+        let key = get_index_key(rule_str);
+        assert_eq!(key.channels, to_set(&["security", "system"]));
+        assert_eq!(key.event_ids, None);
+    }
+
+    #[test]
+    fn test_index_key_not_indexed() {
+        // not、ワイルドカード、modifierが指定されている場合は絞り込まない
+        let rule_strs = [
+            r#"
+        detection:
+            selection:
+                Channel: Security
+            condition: not selection
+        "#,
+            r#"
+        detection:
+            selection:
+                Channel: Microsoft-Windows-Sysmon*
+                EventID|gt: 10
+            condition: selection
+        "#,
+            r#"
+        detection:
+            selection:
+                Channel: Security
+            filter:
+                EventID: 4624
+            condition: selection or filter
+        "#,
+        ];
+        for rule_str in rule_strs {
+            let key = get_index_key(rule_str);
+            assert_eq!(key.channels, None);
+            assert_eq!(key.event_ids, None);
+        }
+    }
+
+    #[test]
+    fn test_index_key_literals() {
+        let rule_str = r#"
+        detection:
+            selection:
+                Channel: Security
+                TargetUserName|contains: 'Admin'
+                IpAddress|startswith:
+                    - '10.'
+                    - '192.168.'
+            condition: selection
+        "#;
+        // 最も短い文字列が一番長い条件を採用する
+        assert_eq!(get_index_key(rule_str).literals, to_set(&["admin"]));
+
+        let rule_str = r#"
+        detection:
+            selection1:
+                Image|endswith: '\cmd.exe'
+            selection2:
+                CommandLine|contains|all:
+                    - 'whoami'
+                    - '/priv'
+            condition: selection1 or selection2
+        "#;
+        assert_eq!(
+            get_index_key(rule_str).literals,
+            to_set(&["\\cmd.exe", "whoami"])
+        );
+
+        // not、正規表現、ascii以外の文字列、値を比較しないmodifierの場合は絞り込まない
+        let rule_strs = [
+            r#"
+        detection:
+            selection:
+                Image|endswith: '\cmd.exe'
+            condition: not selection
+        "#,
+            r#"
+        detection:
+            selection:
+                CommandLine|re: 'who.mi'
+            condition: selection
+        "#,
+            r#"
+        detection:
+            selection:
+                TargetUserName: '管理者'
+            condition: selection
+        "#,
+            r#"
+        detection:
+            selection:
+                TargetUserName|exists: true
+            condition: selection
+        "#,
+        ];
+        for rule_str in rule_strs {
+            assert_eq!(get_index_key(rule_str).literals, None);
+        }
+    }
+
+    #[test]
+    fn test_get_target_records() {
+        let rule_strs = [
+            r#"
+        detection:
+            selection:
+                Channel: Security
+                EventID: 4624
+            condition: selection
+        "#,
+            r#"
+        detection:
+            selection:
+                Channel: security
+            condition: selection
+        "#,
+            r#"
+        detection:
+            selection:
+                EventID: 1
+            condition: selection
+        "#,
+            r#"
+        detection:
+            selection:
+                Image|endswith: '\cmd.exe'
+            condition: selection
+        "#,
+            r#"
+        detection:
+            selection:
+                Channel: Microsoft-Windows-Sysmon/Operational
+                Image|endswith: '\explorer.exe'
+            condition: selection
+        "#,
+            r#"
+        detection:
+            selection:
+                Image|endswith: '\explorer.exe'
+            condition: not selection
+        "#,
+        ];
+        let stored_static = create_dummy_stored_static();
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(stored_static.eventkey_alias.clone());
+        let rules: Vec<RuleNode> = rule_strs
+            .iter()
+            .map(|rule_str| {
+                let mut rule_node = parse_rule_from_str(rule_str);
+                rule_node.init(&stored_static).unwrap();
+                rule_node
+            })
+            .collect();
+        let mut keys = Nested::<String>::new();
+        keys.extend(["Channel", "EventID", "Image"]);
+        let records: Vec<_> = [
+            ("Security", 4624, "C:\\\\Windows\\\\explorer.exe"),
+            ("Security", 4625, "C:\\\\Windows\\\\System32\\\\CMD.EXE"),
+            ("Microsoft-Windows-Sysmon/Operational", 1, "C:\\\\Windows\\\\System32\\\\cmd.exe"),
+        ]
+        .iter()
+        .map(|(channel, event_id, image)| {
+            let record_str = format!(
+                r#"{{"Event": {{"System": {{"EventID": {event_id}, "Channel": "{channel}"}}, "EventData": {{"Image": "{image}"}}}}}}"#
+            );
+            let record: Value = serde_json::from_str(&record_str).unwrap();
+            utils::create_rec_info(record, "testpath".to_owned(), &keys, &false, &false)
+        })
+        .collect();
+
+        let target_records = RuleIndex::new(&rules).get_target_records(&records);
+        assert_eq!(
+            target_records,
+            vec![
+                Some(vec![0]),
+                Some(vec![0, 1]),
+                Some(vec![2]),
+                Some(vec![1, 2]),
+                Some(vec![]),
+                None
+            ]
+        );
+    }
+}
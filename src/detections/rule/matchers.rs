@@ -1,15 +1,47 @@
 use base64::{engine::general_purpose, Engine as _};
 use cidr_utils::cidr::{IpCidr, IpCidrError};
 use nested::Nested;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use std::net::IpAddr;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::Instant;
 use std::{cmp::Ordering, collections::HashMap};
 use yaml_rust::Yaml;
 
+use crate::debug::rule_profiler;
 use crate::detections::{detection::EvtxRecordInfo, utils};
 use downcast_rs::Downcast;
 
+/// 正規表現のコンパイル後のサイズの上限のデフォルト値(regexクレートのデフォルト値と同じ)
+pub const DEFAULT_REGEX_SIZE_LIMIT: usize = 10 * (1 << 20);
+/// 正規表現の判定に使うDFAのキャッシュサイズの上限のデフォルト値(regexクレートのデフォルト値と同じ)
+pub const DEFAULT_REGEX_DFA_SIZE_LIMIT: usize = 2 * (1 << 20);
+
+static REGEX_SIZE_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_REGEX_SIZE_LIMIT);
+static REGEX_DFA_SIZE_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_REGEX_DFA_SIZE_LIMIT);
+
+/// ルールの正規表現のコンパイル後のサイズの上限とDFAのキャッシュサイズの上限を設定する関数
+pub fn set_regex_limits(size_limit: usize, dfa_size_limit: usize) {
+    REGEX_SIZE_LIMIT.store(size_limit, AtomicOrdering::Relaxed);
+    REGEX_DFA_SIZE_LIMIT.store(dfa_size_limit, AtomicOrdering::Relaxed);
+}
+
+/// サイズの上限を設定してルールの正規表現をコンパイルする関数。
+/// 悪意のある、または誤った正規表現でメモリを使い果たさないように、上限を超えた場合はエラーメッセージを返す
+pub fn build_regex(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT.load(AtomicOrdering::Relaxed))
+        .dfa_size_limit(REGEX_DFA_SIZE_LIMIT.load(AtomicOrdering::Relaxed))
+        .build()
+        .map_err(|e| match e {
+            regex::Error::CompiledTooBig(limit) => {
+                format!("Regex exceeded the size limit of {limit} bytes, so the rule was disabled.")
+            }
+            _ => "Cannot parse regex.".to_string(),
+        })
+}
+
 // 末端ノードがEventLogの値を比較するロジックを表す。
 // 正規条件のマッチや文字数制限など、比較ロジック毎にこのtraitを実装したクラスが存在する。
 //
@@ -113,8 +145,11 @@ impl LeafMatcher for RegexesFileMatcher {
         let regexes_strs = regexes_strs.unwrap();
         self.regexes = regexes_strs
             .iter()
-            .map(|regex_str| Regex::new(regex_str).unwrap())
-            .collect();
+            .map(|regex_str| {
+                build_regex(regex_str)
+                    .map_err(|e| vec![format!("{e} [regex:{regex_str}, file:{}]", value.unwrap())])
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         Result::Ok(())
     }
@@ -163,15 +198,19 @@ impl LeafMatcher for AllowlistFileMatcher {
             return Result::Err(vec![errmsg]);
         }
 
-        let regexes_strs = utils::read_txt(&value.unwrap());
+        let value = value.unwrap();
+        let regexes_strs = utils::read_txt(&value);
         if regexes_strs.is_err() {
             return Result::Err(vec![regexes_strs.unwrap_err()]);
         }
         self.regexes = regexes_strs
             .unwrap()
             .iter()
-            .map(|regex_str| Regex::new(regex_str).unwrap())
-            .collect();
+            .map(|regex_str| {
+                build_regex(regex_str)
+                    .map_err(|e| vec![format!("{e} [regex:{regex_str}, file:{value}]")])
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         Result::Ok(())
     }
@@ -201,6 +240,7 @@ pub struct DefaultMatcher {
     fast_match: Option<Vec<FastMatch>>,
     pipes: Vec<PipeElement>,
     key_list: Nested<String>,
+    case_sensitive: bool,
 }
 
 impl DefaultMatcher {
@@ -210,6 +250,7 @@ impl DefaultMatcher {
             fast_match: Option::None,
             pipes: Vec::new(),
             key_list: Nested::<String>::new(),
+            case_sensitive: false,
         }
     }
 
@@ -218,9 +259,56 @@ impl DefaultMatcher {
         return pipe.get_eqfield();
     }
 
+    /// このmatcherに一致するために、フィールドの値に含まれている必要がある文字列の一覧(いずれか1つ)を返します。
+    /// FastMatchに変換できなかった場合や、値が含まれていなくても一致するパイプの場合はNoneを返します。
+    /// Unicodeの大文字小文字の変換で一致する場合を考慮するため、ascii文字のみの文字列だけを対象にします。
+    pub fn get_required_literals(&self) -> Option<Vec<&String>> {
+        if self.key_list.is_empty() {
+            return None;
+        }
+        let is_not_literal_pipe = self.pipes.iter().any(|pipe| {
+            matches!(
+                pipe,
+                PipeElement::EqualsField(_)
+                    | PipeElement::Endswithfield(_)
+                    | PipeElement::Exists(_)
+                    | PipeElement::Gt(_)
+                    | PipeElement::Gte(_)
+                    | PipeElement::Lt(_)
+                    | PipeElement::Lte(_)
+                    | PipeElement::Cidr(_)
+            )
+        });
+        if is_not_literal_pipe {
+            return None;
+        }
+        self.fast_match
+            .as_ref()?
+            .iter()
+            .map(|fast_match| match fast_match {
+                FastMatch::Exact(s)
+                | FastMatch::StartsWith(s)
+                | FastMatch::EndsWith(s)
+                | FastMatch::Contains(s)
+                    if !s.is_empty() && s.is_ascii() =>
+                {
+                    Some(s)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
     /// このmatcherの正規表現とマッチするかどうか判定します。
     fn is_regex_fullmatch(&self, value: &str) -> bool {
-        return self.re.as_ref().unwrap().iter().any(|x| x.is_match(value));
+        let regexes = self.re.as_ref().unwrap();
+        if !rule_profiler::is_enabled() {
+            return regexes.iter().any(|x| x.is_match(value));
+        }
+        let start = Instant::now();
+        let ret = regexes.iter().any(|x| x.is_match(value));
+        rule_profiler::add_regex_time(start.elapsed());
+        ret
     }
 
     /// Hayabusaのルールファイルのフィールド名とそれに続いて指定されるパイプを、正規表現形式の文字列に変換します。
@@ -308,6 +396,141 @@ impl DefaultMatcher {
         // *を含まない場合は、文字列長マッチに変換
         Some(vec![FastMatch::Exact(s.replace(r"\\", r"\"))])
     }
+
+    /// base64offsetのパイプ処理です。
+    /// 対象の文字列が3バイト境界のどこから始まってもマッチできるように、オフセット0~2の3パターンのbase64文字列を返します。
+    /// 前後の文字に依存して変わってしまう先頭と末尾の文字は取り除いています。
+    fn base64offset_patterns(val_byte: &[u8]) -> Result<Vec<String>, String> {
+        let mut ret = vec![];
+        for i in 0..3 {
+            let mut b64_result = vec![];
+            let mut target_byte = vec![];
+            target_byte.resize_with(i, || 0b0);
+            target_byte.extend_from_slice(val_byte);
+            b64_result.resize_with(target_byte.len() * 4 / 3 + 4, || 0b0);
+            general_purpose::STANDARD
+                .encode_slice(target_byte, &mut b64_result)
+                .ok();
+            let b64_str = match String::from_utf8(b64_result) {
+                Ok(b64_str) => b64_str,
+                Err(e) => return Err(format!("Failed base64 encoding: {e}")),
+            };
+            let filtered_null_chr = b64_str.replace('\0', "");
+            let b64_offset_contents = match b64_str.find('=').unwrap_or_default() % 4 {
+                2 => {
+                    if i == 0 {
+                        filtered_null_chr[..filtered_null_chr.len() - 3].to_string()
+                    } else {
+                        filtered_null_chr[(i + 1)..filtered_null_chr.len() - 3].to_string()
+                    }
+                }
+                3 => {
+                    if i == 0 {
+                        filtered_null_chr[..filtered_null_chr.len() - 2].to_string()
+                    } else {
+                        filtered_null_chr[(i + 1)..filtered_null_chr.len() - 2].to_string()
+                    }
+                }
+                _ => {
+                    if i == 0 {
+                        filtered_null_chr
+                    } else {
+                        filtered_null_chr[(i + 1)..].to_string()
+                    }
+                }
+            };
+            ret.push(b64_offset_contents);
+        }
+        Ok(ret)
+    }
+
+    /// |utf16le|base64や|wide|base64offset|containsのように、エンコード系のパイプが指定された場合の初期化処理です。
+    /// パイプを左から順番に適用してパターンをエンコードし、その結果をFastMatchに変換します。
+    /// base64の文字列は大文字小文字を区別するので、比較もcase sensitiveで行います。
+    fn init_encoded_match(
+        &mut self,
+        pattern: &str,
+        key_list: &Nested<String>,
+    ) -> Result<(), Vec<String>> {
+        let mut pattern_bytes = pattern.as_bytes().to_vec();
+        let mut is_utf16 = false;
+        let mut encoded_patterns: Option<Vec<String>> = None;
+        let mut match_pipe: Option<&PipeElement> = None;
+        for pipe in self.pipes.iter() {
+            match pipe {
+                PipeElement::Utf16le | PipeElement::Utf16be | PipeElement::Utf16 => {
+                    if is_utf16 || encoded_patterns.is_some() {
+                        return Err(vec![format!(
+                            "utf16 modifiers must be specified only once and before base64 or base64offset. key:{}",
+                            utils::concat_selection_key(key_list)
+                        )]);
+                    }
+                    pattern_bytes = pipe.encode_utf16(pattern);
+                    is_utf16 = true;
+                }
+                PipeElement::Base64 | PipeElement::Base64offset => {
+                    if encoded_patterns.is_some() {
+                        return Err(vec![format!(
+                            "base64 and base64offset cannot be used together. key:{}",
+                            utils::concat_selection_key(key_list)
+                        )]);
+                    }
+                    if *pipe == PipeElement::Base64 {
+                        encoded_patterns =
+                            Some(vec![general_purpose::STANDARD.encode(&pattern_bytes)]);
+                    } else {
+                        encoded_patterns =
+                            Some(Self::base64offset_patterns(&pattern_bytes).map_err(|e| vec![e])?);
+                    }
+                }
+                PipeElement::Startswith | PipeElement::Endswith | PipeElement::Contains => {
+                    match_pipe = Some(pipe);
+                }
+                // |allは事前の分岐でAndSelectionNodeとしているので、ここでは何もしない
+                // エンコード後の値は常にcase sensitiveで比較するので、|casedも何もしない
+                // |expandは展開済みなので何もしない
+                PipeElement::All | PipeElement::Cased | PipeElement::Expand => {}
+                _ => {
+                    return Err(vec![format!(
+                        "This pipe element cannot be combined with base64 or utf16 modifiers. key:{}",
+                        utils::concat_selection_key(key_list)
+                    )]);
+                }
+            }
+        }
+        if encoded_patterns.is_none() {
+            return Err(vec![format!(
+                "utf16 modifiers must be followed by base64 or base64offset. key:{}",
+                utils::concat_selection_key(key_list)
+            )]);
+        }
+
+        let fast_matches = encoded_patterns
+            .unwrap()
+            .into_iter()
+            .map(|encoded| match match_pipe {
+                Some(PipeElement::Startswith) => FastMatch::StartsWith(encoded),
+                Some(PipeElement::Endswith) => FastMatch::EndsWith(encoded),
+                Some(PipeElement::Contains) => FastMatch::Contains(encoded),
+                _ => FastMatch::Exact(encoded),
+            })
+            .collect();
+        self.fast_match = Some(fast_matches);
+        self.case_sensitive = true;
+        Ok(())
+    }
+
+    /// FastMatchで大文字小文字を区別して比較します。
+    fn is_fast_match_case_sensitive(fast_match: &FastMatch, event_value_str: &str) -> bool {
+        match fast_match {
+            FastMatch::Exact(s) => event_value_str == s,
+            FastMatch::StartsWith(s) => event_value_str.starts_with(s.as_str()),
+            FastMatch::EndsWith(s) => event_value_str.ends_with(s.as_str()),
+            FastMatch::Contains(s) | FastMatch::AllOnly(s) => {
+                utils::contains_str(event_value_str, s)
+            }
+        }
+    }
 }
 
 impl LeafMatcher for DefaultMatcher {
@@ -376,105 +599,106 @@ impl LeafMatcher for DefaultMatcher {
         if !err_msges.is_empty() {
             return Err(err_msges);
         }
-        let n = self.pipes.len();
+        // キーワードは、値の比較方法を指定するパイプがなければSigmaと同様にフィールドの値の部分一致で比較する
+        if keys_all[0].is_empty()
+            && self
+                .pipes
+                .iter()
+                .all(|pipe| matches!(pipe, PipeElement::Cased | PipeElement::Expand))
+        {
+            self.pipes.insert(0, PipeElement::Contains);
+        }
+        if self
+            .pipes
+            .iter()
+            .any(|pipe| matches!(pipe, PipeElement::Exists(_)))
+        {
+            // |existsはフィールドの有無のみを判定するので、他のパイプとは組み合わせられない
+            if self.pipes.len() != 1 {
+                let errmsg = format!(
+                    "exists cannot be combined with other pipe elements. key:{}",
+                    utils::concat_selection_key(key_list)
+                );
+                return Result::Err(vec![errmsg]);
+            }
+            return Result::Ok(());
+        }
+        if self.pipes.iter().any(|pipe| pipe.is_numeric_compare()) {
+            // |gt等は数値として比較するので、他のパイプとは組み合わせられない
+            if self.pipes.len() != 1 {
+                let errmsg = format!(
+                    "gt, gte, lt and lte cannot be combined with other pipe elements. key:{}",
+                    utils::concat_selection_key(key_list)
+                );
+                return Result::Err(vec![errmsg]);
+            }
+            return Result::Ok(());
+        }
+        if self.pipes.iter().any(|pipe| pipe.is_encoding()) {
+            // |base64や|wide|base64offset|contains等、エンコードしてから比較するケース
+            return self.init_encoded_match(&pattern[0], key_list);
+        }
+        let is_re = self
+            .pipes
+            .iter()
+            .any(|pipe_element| matches!(pipe_element, PipeElement::Re));
+        if !is_re && self.pipes.iter().any(|pipe| pipe.is_regex_flag()) {
+            let errmsg = format!(
+                "i, m and s pipe elements must be used with re. key:{}",
+                utils::concat_selection_key(key_list)
+            );
+            return Result::Err(vec![errmsg]);
+        }
+        self.case_sensitive = self.pipes.contains(&PipeElement::Cased);
+        let ignore_case = !self.case_sensitive;
+        // |casedと正規表現のフラグは比較方法を変えるだけで、|expandは展開済みなので、FastMatchへの変換の判定からは除外する
+        let pipes: Vec<&PipeElement> = self
+            .pipes
+            .iter()
+            .filter(|pipe| {
+                !pipe.is_regex_flag()
+                    && **pipe != PipeElement::Cased
+                    && **pipe != PipeElement::Expand
+            })
+            .collect();
+        let n = pipes.len();
         if n == 0 {
             // パイプがないケース
-            self.fast_match = Self::convert_to_fast_match(&pattern[0], true);
+            self.fast_match = Self::convert_to_fast_match(&pattern[0], ignore_case);
         } else if n == 1 {
             // パイプがあるケース
-            self.fast_match = match &self.pipes[0] {
+            self.fast_match = match pipes[0] {
                 PipeElement::Startswith => {
-                    Self::convert_to_fast_match(format!("{}*", pattern[0]).as_str(), true)
+                    Self::convert_to_fast_match(format!("{}*", pattern[0]).as_str(), ignore_case)
                 }
                 PipeElement::Endswith => {
-                    Self::convert_to_fast_match(format!("*{}", pattern[0]).as_str(), true)
+                    Self::convert_to_fast_match(format!("*{}", pattern[0]).as_str(), ignore_case)
                 }
                 PipeElement::Contains => {
-                    Self::convert_to_fast_match(format!("*{}*", pattern[0]).as_str(), true)
-                }
-                PipeElement::AllOnly => {
-                    Self::convert_to_fast_match(format!("allOnly*{}*", pattern[0]).as_str(), true)
+                    Self::convert_to_fast_match(format!("*{}*", pattern[0]).as_str(), ignore_case)
                 }
+                PipeElement::AllOnly => Self::convert_to_fast_match(
+                    format!("allOnly*{}*", pattern[0]).as_str(),
+                    ignore_case,
+                ),
                 _ => None,
             };
         } else if n == 2 {
-            if self.pipes[0] == PipeElement::Base64offset && self.pipes[1] == PipeElement::Contains
-            {
-                // |base64offset|containsの場合
-                let val = pattern[0].as_str();
-                let val_byte = val.as_bytes();
-                let mut fastmatches = vec![];
-                for i in 0..3 {
-                    let mut b64_result = vec![];
-                    let mut target_byte = vec![];
-                    target_byte.resize_with(i, || 0b0);
-                    target_byte.extend_from_slice(val_byte);
-                    b64_result.resize_with(target_byte.len() * 4 / 3 + 4, || 0b0);
-                    general_purpose::STANDARD
-                        .encode_slice(target_byte, &mut b64_result)
-                        .ok();
-                    let convstr_b64 = String::from_utf8(b64_result);
-                    if let Ok(b64_str) = convstr_b64 {
-                        // ここでContainsのfastmatch対応を行う
-                        let filtered_null_chr = b64_str.replace('\0', "");
-                        let b64_offset_contents = match b64_str.find('=').unwrap_or_default() % 4 {
-                            2 => {
-                                if i == 0 {
-                                    filtered_null_chr[..filtered_null_chr.len() - 3].to_string()
-                                } else {
-                                    filtered_null_chr[(i + 1)..filtered_null_chr.len() - 3]
-                                        .to_string()
-                                }
-                            }
-                            3 => {
-                                if i == 0 {
-                                    filtered_null_chr[..filtered_null_chr.len() - 2].to_string()
-                                } else {
-                                    filtered_null_chr.replace('\0', "")
-                                        [(i + 1)..filtered_null_chr.len() - 2]
-                                        .to_string()
-                                }
-                            }
-                            _ => {
-                                if i == 0 {
-                                    filtered_null_chr
-                                } else {
-                                    filtered_null_chr[(i + 1)..].to_string()
-                                }
-                            }
-                        };
-                        if let Some(fm) =
-                            Self::convert_to_fast_match(&format!("*{b64_offset_contents}*"), false)
-                        {
-                            fastmatches.extend(fm);
-                        }
-                    } else {
-                        err_msges.push(format!(
-                            "Failed base64 encoding: {}",
-                            convstr_b64.unwrap_err()
-                        ));
-                    }
-                }
-                if !fastmatches.is_empty() {
-                    self.fast_match = Some(fastmatches);
-                }
-            } else if self.pipes[0] == PipeElement::Contains && self.pipes[1] == PipeElement::All
+            if *pipes[0] == PipeElement::Contains && *pipes[1] == PipeElement::All
             // |contains|allの場合、事前の分岐でAndSelectionNodeとしているのでここではcontainsのみとして取り扱う
             {
                 self.fast_match =
-                    Self::convert_to_fast_match(format!("*{}*", pattern[0]).as_str(), true);
-            } else if self.pipes[0] == PipeElement::Contains
-                && self.pipes[1] == PipeElement::Windash
-            {
+                    Self::convert_to_fast_match(format!("*{}*", pattern[0]).as_str(), ignore_case);
+            } else if *pipes[0] == PipeElement::Contains && *pipes[1] == PipeElement::Windash {
                 // |contains|windashの場合
                 let mut fastmatches =
-                    Self::convert_to_fast_match(format!("*{}*", pattern[0]).as_str(), true)
+                    Self::convert_to_fast_match(format!("*{}*", pattern[0]).as_str(), ignore_case)
                         .unwrap_or_default();
                 pattern.push(pattern[0].replacen('-', "/", 1));
                 fastmatches.extend(
                     Self::convert_to_fast_match(
                         format!("*{}*", pattern[0].replacen('-', "/", 1)).as_str(),
-                        true,
+                        ignore_case,
                     )
                     .unwrap_or_default(),
                 );
@@ -483,19 +707,19 @@ impl LeafMatcher for DefaultMatcher {
                 }
             }
         } else if n == 3 {
-            if self.pipes.contains(&PipeElement::Contains)
-                && self.pipes.contains(&PipeElement::All)
-                && self.pipes.contains(&PipeElement::Windash)
+            if pipes.contains(&&PipeElement::Contains)
+                && pipes.contains(&&PipeElement::All)
+                && pipes.contains(&&PipeElement::Windash)
             // |contains|all|windashの場合、事前の分岐でAndSelectionNodeとしているのでここではcontainsとwindashのみとして取り扱う
             {
                 let mut fastmatches =
-                    Self::convert_to_fast_match(format!("*{}*", pattern[0]).as_str(), true)
+                    Self::convert_to_fast_match(format!("*{}*", pattern[0]).as_str(), ignore_case)
                         .unwrap_or_default();
                 pattern.push(pattern[0].replacen('-', "/", 1));
                 fastmatches.extend(
                     Self::convert_to_fast_match(
                         format!("*{}*", pattern[0].replacen('-', "/", 1)).as_str(),
-                        true,
+                        ignore_case,
                     )
                     .unwrap_or_default(),
                 );
@@ -515,7 +739,6 @@ impl LeafMatcher for DefaultMatcher {
                 &self.fast_match.as_ref().unwrap()[0],
                 FastMatch::Exact(_) | FastMatch::Contains(_)
             )
-            && !self.key_list.is_empty()
         {
             // FastMatch::Exact/Contains検索に置き換えられたときは正規表現は不要
             return Result::Ok(());
@@ -529,26 +752,29 @@ impl LeafMatcher for DefaultMatcher {
         if !is_eqfield {
             // 正規表現ではない場合、ワイルドカードであることを表す。
             // ワイルドカードは正規表現でマッチングするので、ワイルドカードを正規表現に変換するPipeを内部的に追加することにする。
-            let is_re = self
-                .pipes
-                .iter()
-                .any(|pipe_element| matches!(pipe_element, PipeElement::Re));
             if !is_re {
                 self.pipes.push(PipeElement::Wildcard);
             }
 
             let mut re_result_vec = vec![];
             for p in pattern {
-                let pattern = DefaultMatcher::from_pattern_to_regex_str(p, &self.pipes);
+                let mut pattern = DefaultMatcher::from_pattern_to_regex_str(p, &self.pipes);
+                if self.case_sensitive && !is_re {
+                    // |casedの場合は、ワイルドカードを変換した正規表現からcase insensitiveの記号を取り除く
+                    if let Some(cased_pattern) = pattern.strip_prefix("(?i)") {
+                        pattern = cased_pattern.to_string();
+                    }
+                }
                 // Pipeで処理されたパターンを正規表現に変換
-                if let Ok(re_result) = Regex::new(&pattern) {
-                    re_result_vec.push(re_result);
-                } else {
-                    let errmsg = format!(
-                        "Cannot parse regex. [regex:{pattern}, key:{}]",
-                        utils::concat_selection_key(key_list)
-                    );
-                    return Result::Err(vec![errmsg]);
+                match build_regex(&pattern) {
+                    Ok(re_result) => re_result_vec.push(re_result),
+                    Err(e) => {
+                        let errmsg = format!(
+                            "{e} [regex:{pattern}, key:{}]",
+                            utils::concat_selection_key(key_list)
+                        );
+                        return Result::Err(vec![errmsg]);
+                    }
                 }
             }
             self.re = Some(re_result_vec);
@@ -562,6 +788,11 @@ impl LeafMatcher for DefaultMatcher {
             PipeElement::EqualsField(_) | PipeElement::Endswithfield(_) => {
                 Some(pipe.is_eqfield_match(event_value, recinfo))
             }
+            // 値がnullのフィールドはレコード内に存在しないものとして扱う
+            PipeElement::Exists(expect_exists) => Some(event_value.is_some() == *expect_exists),
+            PipeElement::Gt(_) | PipeElement::Gte(_) | PipeElement::Lt(_) | PipeElement::Lte(_) => {
+                Some(pipe.is_numeric_match(event_value))
+            }
             PipeElement::Cidr(ip_result) => match ip_result {
                 Ok(matcher_ip) => {
                     let val = String::default();
@@ -581,8 +812,8 @@ impl LeafMatcher for DefaultMatcher {
         }
 
         // yamlにnullが設定されていた場合
-        // keylistが空(==JSONのgrep検索)の場合、無視する。
-        if self.key_list.is_empty() && self.re.is_none() && self.fast_match.is_none() {
+        // キーワードの場合、無視する。
+        if is_keyword(&self.key_list) && self.re.is_none() && self.fast_match.is_none() {
             return false;
         }
 
@@ -602,16 +833,14 @@ impl LeafMatcher for DefaultMatcher {
         }
 
         let event_value_str = event_value.unwrap();
-        if self.key_list.is_empty() {
-            // この場合ただのgrep検索なので、ただ正規表現に一致するかどうか調べればよいだけ
-            return self
-                .re
-                .as_ref()
-                .unwrap()
-                .iter()
-                .any(|x| x.is_match(event_value_str));
-        } else if let Some(fast_matcher) = &self.fast_match {
-            let fast_match_result = if fast_matcher.len() == 1 {
+        if let Some(fast_matcher) = &self.fast_match {
+            let fast_match_result = if self.case_sensitive {
+                Some(
+                    fast_matcher
+                        .iter()
+                        .any(|fm| Self::is_fast_match_case_sensitive(fm, event_value_str)),
+                )
+            } else if fast_matcher.len() == 1 {
                 match &fast_matcher[0] {
                     FastMatch::Exact(s) => Some(Self::eq_ignore_case(event_value_str, s)),
                     FastMatch::StartsWith(s) => Self::starts_with_ignore_case(event_value_str, s),
@@ -646,15 +875,56 @@ enum PipeElement {
     Wildcard,
     EqualsField(String),
     Endswithfield(String),
+    Base64,
     Base64offset,
+    Utf16le,
+    Utf16be,
+    Utf16,
     Windash,
     Cidr(Result<IpCidr, IpCidrError>),
     All,
     AllOnly,
+    Exists(bool),
+    Gt(f64),
+    Gte(f64),
+    Lt(f64),
+    Lte(f64),
+    Cased,
+    ReIgnoreCase,
+    ReMultiline,
+    ReDotAll,
+    Expand,
+}
+
+/// フィールド名が指定されていないキーワード(例: `- 'mimikatz'`や`'|contains': 'mimikatz'`)であればtrueを返す
+pub fn is_keyword(key_list: &Nested<String>) -> bool {
+    key_list.is_empty() || key_list[0].starts_with('|')
+}
+
+/// ルールで指定できるパイプ(modifier)であればtrueを返す。パイプの値が不正な場合のエラーは判定に影響しない
+pub fn is_known_pipe_element(name: &str) -> bool {
+    !matches!(
+        PipeElement::parse(name, "", &Nested::<String>::new()),
+        Ok(None)
+    )
 }
 
 impl PipeElement {
     fn new(key: &str, pattern: &str, key_list: &Nested<String>) -> Result<PipeElement, String> {
+        PipeElement::parse(key, pattern, key_list)?.ok_or_else(|| {
+            format!(
+                "An unknown pipe element was specified. key:{}",
+                utils::concat_selection_key(key_list)
+            )
+        })
+    }
+
+    /// パイプの名前と値からPipeElementを作成する。対応していないパイプの場合はOk(None)を返す
+    fn parse(
+        key: &str,
+        pattern: &str,
+        key_list: &Nested<String>,
+    ) -> Result<Option<PipeElement>, String> {
         let pipe_element = match key {
             "startswith" => Option::Some(PipeElement::Startswith),
             "endswith" => Option::Some(PipeElement::Endswith),
@@ -662,21 +932,121 @@ impl PipeElement {
             "re" => Option::Some(PipeElement::Re),
             "equalsfield" => Option::Some(PipeElement::EqualsField(pattern.to_string())),
             "endswithfield" => Option::Some(PipeElement::Endswithfield(pattern.to_string())),
+            "base64" => Option::Some(PipeElement::Base64),
             "base64offset" => Option::Some(PipeElement::Base64offset),
+            "utf16le" | "wide" => Option::Some(PipeElement::Utf16le),
+            "utf16be" => Option::Some(PipeElement::Utf16be),
+            "utf16" => Option::Some(PipeElement::Utf16),
             "windash" => Option::Some(PipeElement::Windash),
             "cidr" => Option::Some(PipeElement::Cidr(IpCidr::from_str(pattern))),
             "all" => Option::Some(PipeElement::All),
             "allOnly" => Option::Some(PipeElement::AllOnly),
+            "exists" => match pattern {
+                "true" => Option::Some(PipeElement::Exists(true)),
+                "false" => Option::Some(PipeElement::Exists(false)),
+                _ => {
+                    return Result::Err(format!(
+                        "exists value should be true or false. key:{}",
+                        utils::concat_selection_key(key_list)
+                    ))
+                }
+            },
+            "cased" => Option::Some(PipeElement::Cased),
+            // placeholderの展開はselectionのパース時に行っているので、ここでは何もしない
+            "expand" => Option::Some(PipeElement::Expand),
+            "i" | "ignorecase" => Option::Some(PipeElement::ReIgnoreCase),
+            "m" | "multiline" => Option::Some(PipeElement::ReMultiline),
+            "s" | "dotall" => Option::Some(PipeElement::ReDotAll),
+            "gt" | "gte" | "lt" | "lte" => {
+                let num = PipeElement::parse_number(pattern);
+                if num.is_none() {
+                    return Result::Err(format!(
+                        "{key} value should be a number. key:{}",
+                        utils::concat_selection_key(key_list)
+                    ));
+                }
+                let num = num.unwrap();
+                match key {
+                    "gt" => Option::Some(PipeElement::Gt(num)),
+                    "gte" => Option::Some(PipeElement::Gte(num)),
+                    "lt" => Option::Some(PipeElement::Lt(num)),
+                    _ => Option::Some(PipeElement::Lte(num)),
+                }
+            }
             _ => Option::None,
         };
+        Result::Ok(pipe_element)
+    }
+
+    /// パターンをエンコードしてから比較するパイプかどうかを返します。
+    fn is_encoding(&self) -> bool {
+        matches!(
+            self,
+            PipeElement::Base64
+                | PipeElement::Base64offset
+                | PipeElement::Utf16le
+                | PipeElement::Utf16be
+                | PipeElement::Utf16
+        )
+    }
+
+    /// utf16系のパイプで文字列をバイト列に変換します。|utf16はBOM付きのリトルエンディアンです。
+    fn encode_utf16(&self, pattern: &str) -> Vec<u8> {
+        let mut ret = vec![];
+        if *self == PipeElement::Utf16 {
+            ret.extend_from_slice(&[0xFF, 0xFE]);
+        }
+        for c in pattern.encode_utf16() {
+            if *self == PipeElement::Utf16be {
+                ret.extend_from_slice(&c.to_be_bytes());
+            } else {
+                ret.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        ret
+    }
 
-        if let Some(elment) = pipe_element {
-            Result::Ok(elment)
-        } else {
-            Result::Err(format!(
-                "An unknown pipe element was specified. key:{}",
-                utils::concat_selection_key(key_list)
-            ))
+    /// |re|iのように、reの後に指定する正規表現のフラグかどうかを返します。
+    fn is_regex_flag(&self) -> bool {
+        matches!(
+            self,
+            PipeElement::ReIgnoreCase | PipeElement::ReMultiline | PipeElement::ReDotAll
+        )
+    }
+
+    /// 数値として比較するパイプかどうかを返します。
+    fn is_numeric_compare(&self) -> bool {
+        matches!(
+            self,
+            PipeElement::Gt(_) | PipeElement::Gte(_) | PipeElement::Lt(_) | PipeElement::Lte(_)
+        )
+    }
+
+    /// 10進数の文字列か0xから始まる16進数の文字列を数値に変換します。数値に変換できない場合はNoneを返します。
+    fn parse_number(value: &str) -> Option<f64> {
+        let value = value.trim();
+        if let Some(hex) = value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix("0X"))
+        {
+            return u64::from_str_radix(hex, 16).ok().map(|n| n as f64);
+        }
+        value.parse::<f64>().ok().filter(|n| n.is_finite())
+    }
+
+    /// |gt, |gte, |lt, |lteの比較を行います。イベントの値が数値に変換できない場合はマッチしないものとして扱う。
+    fn is_numeric_match(&self, event_value: Option<&String>) -> bool {
+        let event_num = event_value.and_then(|v| PipeElement::parse_number(v));
+        if event_num.is_none() {
+            return false;
+        }
+        let event_num = event_num.unwrap();
+        match self {
+            PipeElement::Gt(n) => event_num > *n,
+            PipeElement::Gte(n) => event_num >= *n,
+            PipeElement::Lt(n) => event_num < *n,
+            PipeElement::Lte(n) => event_num <= *n,
+            _ => false,
         }
     }
 
@@ -751,6 +1121,10 @@ impl PipeElement {
             PipeElement::Endswith => fn_add_asterisk_begin(pattern),
             // containsの場合はpatternの前後にwildcardを足すことで対応する
             PipeElement::Contains => fn_add_asterisk_end(fn_add_asterisk_begin(pattern)),
+            // 正規表現のフラグはpatternの先頭にフラグを表す記号を付与することで対応する
+            PipeElement::ReIgnoreCase => "(?i)".to_string() + &pattern,
+            PipeElement::ReMultiline => "(?m)".to_string() + &pattern,
+            PipeElement::ReDotAll => "(?s)".to_string() + &pattern,
             // WildCardは正規表現に変換する。
             PipeElement::Wildcard => PipeElement::pipe_pattern_wildcard(pattern),
             _ => pattern,
@@ -844,7 +1218,8 @@ mod tests {
     use std::path::Path;
 
     use super::super::matchers::{
-        AllowlistFileMatcher, DefaultMatcher, MinlengthMatcher, PipeElement, RegexesFileMatcher,
+        build_regex, is_known_pipe_element, AllowlistFileMatcher, DefaultMatcher, MinlengthMatcher,
+        PipeElement, RegexesFileMatcher,
     };
 
     use super::super::selectionnodes::{
@@ -854,9 +1229,11 @@ mod tests {
         Action, CommonOptions, Config, CsvOutputOption, DetectCommonOption, InputOption,
         OutputOption, StoredStatic, STORED_EKEY_ALIAS,
     };
+    use crate::detections::rule::create_rule;
     use crate::detections::rule::matchers::FastMatch;
-    use crate::detections::rule::tests::parse_rule_from_str;
+    use crate::detections::rule::tests::{create_dummy_stored_static, parse_rule_from_str};
     use crate::detections::{self, utils};
+    use yaml_rust::YamlLoader;
 
     fn check_select(rule_str: &str, record_str: &str, expect_select: bool) {
         let mut rule_node = parse_rule_from_str(rule_str);
@@ -869,6 +1246,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -908,6 +1286,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -921,6 +1300,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -1963,6 +2348,63 @@ mod tests {
         check_select(rule_str, record_json_str, false);
     }
 
+    #[test]
+    fn test_keyword_selection() {
+        // キーワードはいずれかのフィールドの値に部分一致すれば検知する
+        let rule_str = r#"
+        enabled: true
+        detection:
+            keywords:
+                - 'invoke-mimikatz'
+                - 'Get-GPPPassword'
+            condition: keywords
+        "#;
+
+        let record_json_str = r#"
+        {
+            "Event": {"System": {"EventID": 400, "Channel": "Windows PowerShell"}, "EventData": {"Data": ["Available", "HostApplication=powershell.exe Invoke-Mimikatz -DumpCreds"]}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        check_select(rule_str, record_json_str, true);
+
+        // フィールド名には一致しない
+        let record_json_str = r#"
+        {
+            "Event": {"System": {"EventID": 400, "Channel": "Windows PowerShell"}, "EventData": {"Get-GPPPassword": "Available"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        check_select(rule_str, record_json_str, false);
+    }
+
+    #[test]
+    fn test_keyword_selection_with_modifiers() {
+        // キーワードでもフィールドと同様にパイプで比較方法を指定できる
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                '|startswith': 'Windows Power'
+            keywords:
+                '|re': '(?i)invoke-\w+katz'
+            condition: selection and keywords
+        "#;
+
+        let record_json_str = r#"
+        {
+            "Event": {"System": {"EventID": 400, "Channel": "Windows PowerShell"}, "EventData": {"Data": ["Available", "HostApplication=powershell.exe invoke-mimikatz"]}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        check_select(rule_str, record_json_str, true);
+
+        // 値の途中に一致してもstartswithでは検知しない
+        let record_json_str = r#"
+        {
+            "Event": {"System": {"EventID": 400, "Channel": "Microsoft-Windows PowerShell"}, "EventData": {"Data": ["Available", "HostApplication=powershell.exe invoke-mimikatz"]}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        check_select(rule_str, record_json_str, false);
+    }
+
     #[test]
     fn test_detect_value_keyword() {
         // 文字列っぽいデータでも確認
@@ -2526,6 +2968,338 @@ mod tests {
         check_select(rule_str, record_json_str, false);
     }
 
+    #[test]
+    fn test_base64_contains() {
+        // base64|containsのマッチ
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                CommandLine|base64|contains: "whoami"
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"CommandLine": "echo d2hvYW1p | base64 -d | sh"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+    }
+
+    #[test]
+    fn test_base64_case_sensitive() {
+        // base64の文字列は大文字小文字を区別して比較する
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                CommandLine|base64|contains: "whoami"
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"CommandLine": "echo D2HVYW1P | base64 -d | sh"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, false);
+    }
+
+    #[test]
+    fn test_base64_exact() {
+        // パイプがbase64のみの場合は完全一致
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                Payload|base64: "whoami"
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 4103, "Channel": "Security"}, "EventData":{"Payload": "d2hvYW1p"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str2 = r#"{
+            "Event": {"System": {"EventID": 4103, "Channel": "Security"}, "EventData":{"Payload": "xd2hvYW1p"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+        check_select(rule_str, record_json_str2, false);
+    }
+
+    #[test]
+    fn test_wide_base64offset_contains() {
+        // wide|base64offset|containsのマッチ。エンコードされたPowerShellのコマンドを想定
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                CommandLine|wide|base64offset|contains: "Invoke-Expression"
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"CommandLine": "powershell -enc SQBFAFgAIAAoAEkAbgB2AG8AawBlAC0ARQB4AHAAcgBlAHMAcwBpAG8AbgAgACQAeAApAA=="}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str2 = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"CommandLine": "powershell -enc YQBJAEUAWAAgACgASQBuAHYAbwBrAGUALQBFAHgAcAByAGUAcwBzAGkAbwBuACAAJAB4ACkA"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        // utf16leでエンコードされていない場合はマッチしない
+        let record_json_str3 = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"CommandLine": "powershell -enc SW52b2tlLUV4cHJlc3Npb24="}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+        check_select(rule_str, record_json_str2, true);
+        check_select(rule_str, record_json_str3, false);
+    }
+
+    #[test]
+    fn test_utf16be_and_utf16_base64() {
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection_be:
+                Payload|utf16be|base64: "whoami"
+            selection_bom:
+                Payload|utf16|base64: "whoami"
+            condition: selection_be or selection_bom
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 4103, "Channel": "Security"}, "EventData":{"Payload": "AHcAaABvAGEAbQBp"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str2 = r#"{
+            "Event": {"System": {"EventID": 4103, "Channel": "Security"}, "EventData":{"Payload": "//53AGgAbwBhAG0AaQA="}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+        check_select(rule_str, record_json_str2, true);
+    }
+
+    #[test]
+    fn test_exists_true() {
+        // フィールドが存在する場合に検知する。値が空文字でもフィールドは存在するものとして扱う
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                Channel: Security
+                TargetUserName|exists: true
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData":{"TargetUserName": ""}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str2 = r#"{
+            "Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData":{"SubjectUserName": "user"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+        check_select(rule_str, record_json_str2, false);
+    }
+
+    #[test]
+    fn test_exists_false() {
+        // フィールドが存在しない場合に検知する
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                Channel: Security
+                TargetUserName|exists: false
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData":{"TargetUserName": ""}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str2 = r#"{
+            "Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData":{"SubjectUserName": "user"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, false);
+        check_select(rule_str, record_json_str2, true);
+    }
+
+    #[test]
+    fn test_numeric_compare_gt_lte() {
+        // gt/lteで数値として比較する
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                Channel: Security
+                LogonType|gt: 2
+                LogonType|lte: 10
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData":{"LogonType": 10}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str2 = r#"{
+            "Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData":{"LogonType": 2}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        // 文字列として比較すると"11" < "2"だが、数値として比較する
+        let record_json_str3 = r#"{
+            "Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData":{"LogonType": "11"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+        check_select(rule_str, record_json_str2, false);
+        check_select(rule_str, record_json_str3, false);
+    }
+
+    #[test]
+    fn test_numeric_compare_hex() {
+        // 16進数の値も数値として比較する
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                GrantedAccess|gte: '0x1000'
+                EventID|lt: 11
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 10, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"GrantedAccess": "0x1410"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str2 = r#"{
+            "Event": {"System": {"EventID": 10, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"GrantedAccess": "0x40"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        // 数値に変換できない値はマッチしない
+        let record_json_str3 = r#"{
+            "Event": {"System": {"EventID": 10, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"GrantedAccess": "PROCESS_ALL_ACCESS"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+        check_select(rule_str, record_json_str2, false);
+        check_select(rule_str, record_json_str3, false);
+    }
+
+    #[test]
+    fn test_re_ignorecase() {
+        // re|iの場合は大文字小文字を区別せずに正規表現でマッチする
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                CommandLine|re|i: 'invoke-(expression|webrequest)'
+        details: 'command=%CommandLine%'
+        "#;
+        let rule_str2 = r#"
+        enabled: true
+        detection:
+            selection:
+                CommandLine|re: 'invoke-(expression|webrequest)'
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"CommandLine": "powershell Invoke-WebRequest http://example.com"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+        check_select(rule_str2, record_json_str, false);
+    }
+
+    #[test]
+    fn test_re_multiline_dotall() {
+        // re|mの場合は^と$が行単位でマッチし、re|sの場合は.が改行にもマッチする
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                ScriptBlockText|re|m: '^Invoke-Mimikatz$'
+        details: 'command=%CommandLine%'
+        "#;
+        let rule_str2 = r#"
+        enabled: true
+        detection:
+            selection:
+                ScriptBlockText|re|s: 'Import-Module.*Invoke-Mimikatz'
+        details: 'command=%CommandLine%'
+        "#;
+        let rule_str3 = r#"
+        enabled: true
+        detection:
+            selection:
+                ScriptBlockText|re: 'Import-Module.*Invoke-Mimikatz'
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 4104, "Channel": "Microsoft-Windows-PowerShell/Operational"}, "EventData":{"ScriptBlockText": "Import-Module .\\mimi.ps1\nInvoke-Mimikatz\nexit"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+        check_select(rule_str2, record_json_str, true);
+        check_select(rule_str3, record_json_str, false);
+    }
+
+    #[test]
+    fn test_cased() {
+        // casedの場合は大文字小文字を区別して比較する
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection_exact:
+                Image|cased: 'C:\Windows\System32\cmd.exe'
+            selection_contains:
+                CommandLine|contains|cased: 'ShellCode'
+            selection_wildcard:
+                ParentImage|cased: '*\WmiPrvSE.exe'
+            condition: selection_exact and selection_contains and selection_wildcard
+        details: 'command=%CommandLine%'
+        "#;
+
+        let record_json_str = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"Image": "C:\\Windows\\System32\\cmd.exe", "CommandLine": "cmd /c ShellCode.bin", "ParentImage": "C:\\Windows\\System32\\wbem\\WmiPrvSE.exe"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str2 = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"Image": "C:\\Windows\\System32\\cmd.exe", "CommandLine": "cmd /c shellcode.bin", "ParentImage": "C:\\Windows\\System32\\wbem\\WmiPrvSE.exe"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str3 = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"Image": "c:\\windows\\system32\\cmd.exe", "CommandLine": "cmd /c ShellCode.bin", "ParentImage": "C:\\Windows\\System32\\wbem\\WmiPrvSE.exe"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+        let record_json_str4 = r#"{
+            "Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData":{"Image": "C:\\Windows\\System32\\cmd.exe", "CommandLine": "cmd /c ShellCode.bin", "ParentImage": "C:\\Windows\\System32\\wbem\\wmiprvse.exe"}},
+            "Event_attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"}
+        }"#;
+
+        check_select(rule_str, record_json_str, true);
+        check_select(rule_str, record_json_str2, false);
+        check_select(rule_str, record_json_str3, false);
+        check_select(rule_str, record_json_str4, false);
+    }
+
     #[test]
     fn test_cidr_ipv4_detect() {
         // cidrにマッチするIP
@@ -2929,6 +3703,57 @@ mod tests {
         check_select(rule_str, record_json_str, false);
     }
 
+    #[test]
+    fn test_all_only_matches_field_values() {
+        // '|all'のキーワードはレコード全体の文字列ではなく、各フィールドの値と比較する
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                '|all':
+                    - 'Sysmon/Operational'
+                    - 'indows\'
+            condition: selection
+        "#;
+        let record_json_str = r#"
+        {
+          "Event": {
+            "System": {
+              "EventID": 1,
+              "Channel": "Microsoft-Windows-Sysmon/Operational"
+            },
+            "EventData": {
+              "CurrentDirectory": "C:\\Windows\\system32\\"
+            }
+          }
+        }"#;
+        check_select(rule_str, record_json_str, true);
+
+        // フィールド名には一致しない
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                '|all':
+                    - 'Sysmon/Operational'
+                    - 'CurrentDirectory'
+            condition: selection
+        "#;
+        check_select(rule_str, record_json_str, false);
+
+        // 複数のフィールドの値にまたがる文字列には一致しない
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                '|all':
+                    - 'Sysmon/Operational'
+                    - 'Operational","EventID'
+            condition: selection
+        "#;
+        check_select(rule_str, record_json_str, false);
+    }
+
     #[test]
     fn test_contains_windash() {
         let rule_str = r#"
@@ -3008,4 +3833,35 @@ mod tests {
         check_select(rule_str, record_json_str, true);
         check_select(rule_str, record_json_str2, false);
     }
+
+    #[test]
+    fn test_is_known_pipe_element() {
+        for name in ["contains", "wide", "cidr", "exists", "gte", "expand", "i"] {
+            assert!(is_known_pipe_element(name));
+        }
+        assert!(!is_known_pipe_element("unknown"));
+        assert!(!is_known_pipe_element("Contains"));
+    }
+
+    #[test]
+    fn test_regex_size_limit() {
+        // サイズの上限を超える正規表現はエラーになり、ルールは読み込まれない
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection1:
+                'CommandLine|re': '(?:\w{100}){100}'
+            condition: selection1
+        "#;
+        let rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().remove(0);
+        let mut rule_node = create_rule("testpath".to_string(), rule_yaml);
+        let errmsgs = rule_node.init(&create_dummy_stored_static()).unwrap_err();
+        assert_eq!(errmsgs.len(), 1);
+        assert!(errmsgs[0].starts_with(
+            "Regex exceeded the size limit of 10485760 bytes, so the rule was disabled."
+        ));
+
+        assert!(build_regex(r"(?i)\\cvtres\.exe").is_ok());
+        assert_eq!(build_regex("(test").unwrap_err(), "Cannot parse regex.");
+    }
 }
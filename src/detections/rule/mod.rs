@@ -2,35 +2,73 @@ extern crate regex;
 
 use chrono::{DateTime, Utc};
 
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use nested::Nested;
 use std::{fmt::Debug, sync::Arc, vec};
 
 use yaml_rust::Yaml;
 
 mod matchers;
+pub use self::matchers::{
+    build_regex, set_regex_limits, DEFAULT_REGEX_DFA_SIZE_LIMIT, DEFAULT_REGEX_SIZE_LIMIT,
+};
 mod selectionnodes;
 use self::selectionnodes::{LeafSelectionNode, SelectionNode};
 mod aggregation_parser;
 use self::aggregation_parser::AggregationParseInfo;
 
 mod condition_parser;
+mod correlation;
 mod count;
+pub mod index;
+use self::correlation::CorrelationNode;
 use self::count::{AggRecordTimeInfo, TimeFrameInfo};
 
 use super::configs::{EventKeyAliasConfig, StoredStatic};
 use super::detection::EvtxRecordInfo;
+use super::pipeline::{self, PipelineVariant};
+use super::placeholder::{self, PlaceholderMap};
+use super::utils;
 
 pub fn create_rule(rulepath: String, yaml: Yaml) -> RuleNode {
     RuleNode::new(rulepath, yaml)
 }
 
+/// 相関ルールを作成する。相関ルールが参照しているルールはrule_filesの中からidまたはnameで探す
+pub fn create_correlation_rule(
+    rulepath: String,
+    yaml: Yaml,
+    rule_files: &[(String, Yaml)],
+) -> RuleNode {
+    let correlation = CorrelationNode::new(&yaml, rule_files);
+    let mut rule = RuleNode::new(rulepath, yaml);
+    rule.correlation = Some(correlation);
+    rule
+}
+
+/// YAMLが相関ルール(correlation)であればtrueを返す
+pub fn is_correlation_rule(yaml: &Yaml) -> bool {
+    correlation::is_correlation_rule(yaml)
+}
+
+/// ルールで指定できるパイプ(modifier)であればtrueを返す
+pub fn is_known_modifier(name: &str) -> bool {
+    matchers::is_known_pipe_element(name)
+}
+
+/// 相関ルールのrulesに指定されたルールのidまたはnameの一覧を返す
+pub fn get_correlation_rule_refs(yaml: &Yaml) -> Vec<String> {
+    correlation::get_rule_refs(yaml)
+}
+
 /// Ruleファイルを表すノード
 pub struct RuleNode {
     pub rulepath: String,
     pub yaml: Yaml,
     detection: DetectionNode,
     countdata: HashMap<String, Vec<AggRecordTimeInfo>>,
+    reported_aggresults: HashSet<(String, DateTime<Utc>)>,
+    correlation: Option<CorrelationNode>,
 }
 
 impl Debug for RuleNode {
@@ -46,16 +84,47 @@ impl RuleNode {
             yaml: yaml_data,
             detection: DetectionNode::new(),
             countdata: HashMap::new(),
+            reported_aggresults: HashSet::new(),
+            correlation: None,
         }
     }
 
     pub fn init(&mut self, stored_static: &StoredStatic) -> Result<(), Vec<String>> {
         let mut errmsgs: Vec<String> = vec![];
 
+        // 相関ルールはdetectionの代わりにcorrelationを初期化する
+        if is_correlation_rule(&self.yaml) {
+            return match self.correlation.as_mut() {
+                Some(correlation) => correlation.init(&self.yaml["correlation"], stored_static),
+                None => Result::Err(vec![
+                    "The rules referenced by the correlation were not loaded.".to_string(),
+                ]),
+            };
+        }
+
         // detection node initialization
-        let detection_result = self.detection.init(&self.yaml["detection"], stored_static);
+        // Sigmaのlogsourceに対応するパイプラインがある場合は、パイプラインのvariant毎の条件に展開する
+        let variants = pipeline::get_target_variants(&stored_static.pipelines, &self.yaml);
+        let detection_result = if variants.is_empty() {
+            self.detection.init(&self.yaml["detection"], stored_static)
+        } else {
+            self.detection
+                .init_with_variants(&self.yaml["detection"], &variants, stored_static)
+        };
         if let Err(err_detail) = detection_result {
             errmsgs.extend(err_detail);
+        } else if variants.is_empty() && !self.detection.has_channel_condition() {
+            // detectionでChannelを指定していないルールは、logsourceに対応するChannelとEventIDの条件をandで追加する
+            if let Some(conditions) =
+                pipeline::get_logsource_conditions(&stored_static.logsource_mappings, &self.yaml)
+            {
+                if let Err(err_detail) = self
+                    .detection
+                    .add_logsource_condition(conditions, stored_static)
+                {
+                    errmsgs.extend(err_detail);
+                }
+            }
         }
 
         if errmsgs.is_empty() {
@@ -73,6 +142,11 @@ impl RuleNode {
         json_input_flag: bool,
         eventkey_alias: &EventKeyAliasConfig,
     ) -> bool {
+        // 相関ルールは参照しているルールの検知結果を記録するのみで、レコード単位では検知しない
+        if let Some(correlation) = self.correlation.as_mut() {
+            correlation.select(event_record, json_input_flag, eventkey_alias);
+            return false;
+        }
         let result = self.detection.select(event_record, eventkey_alias);
         if result && self.has_agg_condition() {
             count::count(
@@ -85,9 +159,24 @@ impl RuleNode {
         }
         result
     }
+    /// レコードが検知された理由(一致したselectionとフィールド)を返す関数
+    pub fn get_match_reasons(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+    ) -> Vec<String> {
+        if self.is_correlation() {
+            return vec![];
+        }
+        self.detection.explain(event_record, eventkey_alias)
+    }
     /// aggregation conditionが存在するかを返す関数
     pub fn has_agg_condition(&self) -> bool {
-        self.detection.aggregation_condition.is_some()
+        self.detection.aggregation_condition.is_some() || self.correlation.is_some()
+    }
+    /// 相関ルールであるかを返す関数
+    pub fn is_correlation(&self) -> bool {
+        self.correlation.is_some()
     }
     /// Aggregation Conditionの結果を配列で返却する関数
     pub fn judge_satisfy_aggcondition(&self, stored_static: &StoredStatic) -> Vec<AggResult> {
@@ -95,15 +184,47 @@ impl RuleNode {
         if !self.has_agg_condition() {
             return ret;
         }
+        if let Some(correlation) = self.correlation.as_ref() {
+            return correlation.judge_satisfy_condition();
+        }
         ret.append(&mut count::aggregation_condition_select(
             self,
             stored_static,
         ));
         ret
     }
+    /// --followで検知処理を繰り返す場合に、前回までに返却していないAggregation Conditionの結果を配列で返却する関数
+    pub fn judge_new_aggcondition(&mut self, stored_static: &StoredStatic) -> Vec<AggResult> {
+        let results = self.judge_satisfy_aggcondition(stored_static);
+        count::select_new_aggresults(&mut self.reported_aggresults, results)
+    }
     pub fn check_exist_countdata(&self) -> bool {
         !self.countdata.is_empty()
     }
+    /// Sigmaのフィルタールールのfilterの条件をandで追加する関数
+    pub fn apply_filter(
+        &mut self,
+        filter_yaml: &Yaml,
+        stored_static: &StoredStatic,
+    ) -> Result<(), Vec<String>> {
+        if self.is_correlation() {
+            return Result::Err(vec![
+                "Filter rules cannot be applied to correlation rules.".to_string()
+            ]);
+        }
+        self.detection.add_filter(filter_yaml, stored_static)
+    }
+    /// ルールのインデックスに使うChannelとEventIDを取得する関数
+    pub fn get_index_key(&self) -> index::IndexKey {
+        // 相関ルールは参照しているルールの検知結果を記録する必要があるので、全てのレコードを対象にする
+        if self.is_correlation() {
+            return index::IndexKey::default();
+        }
+        match self.detection.condition.as_ref() {
+            Some(condition) => index::IndexKey::from_node(condition.as_ref()),
+            None => index::IndexKey::default(),
+        }
+    }
     /// ルール内のAggregationParseInfo(Aggregation Condition)を取得する関数
     pub fn get_agg_condition(&self) -> Option<&AggregationParseInfo> {
         if self.detection.aggregation_condition.as_ref().is_some() {
@@ -136,6 +257,12 @@ pub fn get_detection_keys(node: &RuleNode) -> Nested<String> {
             ret.extend(keys);
         });
     }
+    // 相関ルールの場合は参照しているルールのキーを取得する
+    if let Some(correlation) = node.correlation.as_ref() {
+        for base_rule in correlation.get_base_rules() {
+            ret.extend(get_detection_keys(base_rule).iter().map(|x| x.to_string()));
+        }
+    }
 
     ret
 }
@@ -164,7 +291,7 @@ impl DetectionNode {
         stored_static: &StoredStatic,
     ) -> Result<(), Vec<String>> {
         // selection nodeの初期化
-        self.parse_name_to_selection(detection_yaml)?;
+        self.parse_name_to_selection(detection_yaml, &stored_static.placeholders)?;
 
         //timeframeに指定されている値を取得
         let timeframe = &detection_yaml["timeframe"].as_str();
@@ -217,6 +344,134 @@ impl DetectionNode {
         }
     }
 
+    /// パイプラインのvariant毎に、フィールド名を変換したdetectionとvariantの条件をandで結合し、それらをorで結合したconditionを作成する
+    fn init_with_variants(
+        &mut self,
+        detection_yaml: &Yaml,
+        variants: &[&PipelineVariant],
+        stored_static: &StoredStatic,
+    ) -> Result<(), Vec<String>> {
+        let mut or_node = selectionnodes::OrSelectionNode::new();
+        for (idx, variant) in variants.iter().enumerate() {
+            let mut detection_hash = detection_yaml.as_hash().cloned().unwrap_or_default();
+            for (key, val) in detection_hash.iter_mut() {
+                if key.as_str() != Some("condition") && key.as_str() != Some("timeframe") {
+                    *val = variant.map_fields(val);
+                }
+            }
+            let mut variant_node = DetectionNode::new();
+            variant_node.init(&Yaml::Hash(detection_hash), stored_static)?;
+
+            let logsource_node =
+                self.parse_logsource_selection(&variant.conditions, stored_static)?;
+            or_node.child_nodes.push(Self::and_logsource(
+                &logsource_node,
+                variant_node.condition.unwrap(),
+            ));
+
+            // get_detection_keysでvariantのキーも取得できるように、ルールのselectionと重複しない名前で登録しておく
+            self.name_to_selection
+                .insert(format!("_pipeline{idx}_logsource"), logsource_node);
+            for (name, selection) in variant_node.name_to_selection {
+                self.name_to_selection
+                    .insert(format!("_pipeline{idx}_{name}"), selection);
+            }
+            // aggregation conditionとtimeframeはvariantによらず同じ
+            self.aggregation_condition = variant_node.aggregation_condition;
+            self.timeframe = variant_node.timeframe;
+        }
+        self.condition = Some(Box::new(or_node));
+        Result::Ok(())
+    }
+
+    /// logsourceに対応するChannelとEventIDの条件をselectionとしてパースする
+    fn parse_logsource_selection(
+        &self,
+        conditions: &Yaml,
+        stored_static: &StoredStatic,
+    ) -> Result<Arc<Box<dyn SelectionNode>>, Vec<String>> {
+        let mut logsource_node = self.parse_selection(conditions, &stored_static.placeholders)?;
+        logsource_node.init()?;
+        Ok(Arc::new(logsource_node))
+    }
+
+    /// logsourceの条件とconditionをandで結合する。検知理由にはlogsourceという名前で出力する
+    fn and_logsource(
+        logsource_node: &Arc<Box<dyn SelectionNode>>,
+        condition: Box<dyn SelectionNode>,
+    ) -> Box<dyn SelectionNode> {
+        let mut and_node = selectionnodes::AndSelectionNode::new();
+        and_node
+            .child_nodes
+            .push(Box::new(selectionnodes::RefSelectionNode::new(
+                "logsource".to_string(),
+                Arc::clone(logsource_node),
+            )));
+        and_node.child_nodes.push(condition);
+        Box::new(and_node)
+    }
+
+    /// detectionのいずれかのselectionでChannelを指定している場合にtrueを返す
+    fn has_channel_condition(&self) -> bool {
+        self.name_to_selection.values().any(|selection| {
+            selection.get_descendants().iter().any(|node| {
+                node.downcast_ref::<LeafSelectionNode>()
+                    .is_some_and(|leaf| leaf.get_key() == "Channel")
+            })
+        })
+    }
+
+    /// logsourceに対応するChannelとEventIDの条件をパースして、conditionにandで追加する
+    fn add_logsource_condition(
+        &mut self,
+        conditions: &Yaml,
+        stored_static: &StoredStatic,
+    ) -> Result<(), Vec<String>> {
+        let logsource_node = self.parse_logsource_selection(conditions, stored_static)?;
+        self.condition = Some(Self::and_logsource(
+            &logsource_node,
+            self.condition.take().unwrap(),
+        ));
+        // get_detection_keysでlogsourceのキーも取得できるように、ルールのselectionと重複しない名前で登録しておく
+        self.name_to_selection
+            .insert("_logsource".to_string(), logsource_node);
+        Result::Ok(())
+    }
+
+    /// フィルタールールのfilterをパースして、conditionにandで追加する
+    fn add_filter(
+        &mut self,
+        filter_yaml: &Yaml,
+        stored_static: &StoredStatic,
+    ) -> Result<(), Vec<String>> {
+        // rulesはselectionではないので除いてからパースする
+        let mut filter_hash = filter_yaml.as_hash().cloned().unwrap_or_default();
+        filter_hash.remove(&Yaml::String("rules".to_string()));
+        let mut filter_node = DetectionNode::new();
+        filter_node.init(&Yaml::Hash(filter_hash), stored_static)?;
+        if filter_node.aggregation_condition.is_some() {
+            return Result::Err(vec![
+                "An aggregation condition cannot be used in filter rules.".to_string(),
+            ]);
+        }
+
+        let mut and_node = selectionnodes::AndSelectionNode::new();
+        and_node.child_nodes.push(self.condition.take().unwrap());
+        and_node.child_nodes.push(filter_node.condition.unwrap());
+        self.condition = Some(Box::new(and_node));
+        // get_detection_keysでフィルターのキーも取得できるように、ルールのselectionと重複しない名前で登録しておく
+        let filter_cnt = self
+            .name_to_selection
+            .keys()
+            .filter(|name| name.starts_with("_filter"))
+            .count();
+        for (name, selection) in filter_node.name_to_selection {
+            self.name_to_selection
+                .insert(format!("_filter{filter_cnt}_{name}"), selection);
+        }
+        Result::Ok(())
+    }
+
     pub fn select(
         &self,
         event_record: &EvtxRecordInfo,
@@ -230,8 +485,25 @@ impl DetectionNode {
         condition.select(event_record, eventkey_alias)
     }
 
+    /// レコードが検知された理由として、一致したselectionとフィールドの一覧を返す
+    pub fn explain(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+    ) -> Vec<String> {
+        let mut reasons = vec![];
+        if let Some(condition) = self.condition.as_ref() {
+            condition.explain(event_record, eventkey_alias, &mut reasons);
+        }
+        reasons
+    }
+
     /// selectionノードをパースします。
-    fn parse_name_to_selection(&mut self, detection_yaml: &Yaml) -> Result<(), Vec<String>> {
+    fn parse_name_to_selection(
+        &mut self,
+        detection_yaml: &Yaml,
+        placeholders: &PlaceholderMap,
+    ) -> Result<(), Vec<String>> {
         let detection_hash = detection_yaml.as_hash();
         if detection_hash.is_none() {
             return Result::Err(vec!["Detection node was not found.".to_string()]);
@@ -252,17 +524,20 @@ impl DetectionNode {
             }
 
             // パースして、エラーメッセージがあれば配列にためて、戻り値で返す。
-            let selection_node = self.parse_selection(&detection_hash[key]);
-            if let Some(node) = selection_node {
-                let mut selection_node = node;
-                let init_result = selection_node.init();
-                if let Err(err_detail) = init_result {
-                    err_msgs.extend(err_detail);
-                } else {
-                    let rc_selection = Arc::new(selection_node);
-                    self.name_to_selection
-                        .insert(name.to_string(), rc_selection);
+            let selection_node = self.parse_selection(&detection_hash[key], placeholders);
+            match selection_node {
+                Ok(node) => {
+                    let mut selection_node = node;
+                    let init_result = selection_node.init();
+                    if let Err(err_detail) = init_result {
+                        err_msgs.extend(err_detail);
+                    } else {
+                        let rc_selection = Arc::new(selection_node);
+                        self.name_to_selection
+                            .insert(name.to_string(), rc_selection);
+                    }
                 }
+                Err(err_detail) => err_msgs.extend(err_detail),
             }
         }
         if !err_msgs.is_empty() {
@@ -280,61 +555,105 @@ impl DetectionNode {
     }
 
     /// selectionをパースします。
-    fn parse_selection(&self, selection_yaml: &Yaml) -> Option<Box<dyn SelectionNode>> {
-        Option::Some(Self::parse_selection_recursively(
-            &Nested::<String>::new(),
-            selection_yaml,
-        ))
+    fn parse_selection(
+        &self,
+        selection_yaml: &Yaml,
+        placeholders: &PlaceholderMap,
+    ) -> Result<Box<dyn SelectionNode>, Vec<String>> {
+        Self::parse_selection_recursively(&Nested::<String>::new(), selection_yaml, placeholders)
     }
 
     /// selectionをパースします。
     fn parse_selection_recursively(
         key_list: &Nested<String>,
         yaml: &Yaml,
-    ) -> Box<dyn SelectionNode> {
+        placeholders: &PlaceholderMap,
+    ) -> Result<Box<dyn SelectionNode>, Vec<String>> {
         if yaml.as_hash().is_some() {
             // 連想配列はAND条件と解釈する
             let yaml_hash = yaml.as_hash().unwrap();
             let mut and_node = selectionnodes::AndSelectionNode::new();
 
-            yaml_hash.keys().for_each(|hash_key| {
+            for hash_key in yaml_hash.keys() {
                 let child_yaml = yaml_hash.get(hash_key).unwrap();
                 let mut child_key_list = key_list.clone();
                 child_key_list.push(hash_key.as_str().unwrap());
-                let child_node = Self::parse_selection_recursively(&child_key_list, child_yaml);
+                let child_node =
+                    Self::parse_selection_recursively(&child_key_list, child_yaml, placeholders)?;
                 and_node.child_nodes.push(child_node);
-            });
-            Box::new(and_node)
+            }
+            Ok(Box::new(and_node))
         } else if yaml.as_vec().is_some() && key_list.len() == 1 && key_list[0].eq("|all") {
             // |all だけの場合、
             let mut or_node = selectionnodes::AllSelectionNode::new();
-            yaml.as_vec().unwrap().iter().for_each(|child_yaml| {
-                let child_node = Self::parse_selection_recursively(key_list, child_yaml);
+            for child_yaml in yaml.as_vec().unwrap() {
+                let child_node =
+                    Self::parse_selection_recursively(key_list, child_yaml, placeholders)?;
                 or_node.child_nodes.push(child_node);
-            });
-            Box::new(or_node)
+            }
+            Ok(Box::new(or_node))
         } else if yaml.as_vec().is_some() && key_list.iter().any(|k: &str| k.contains("|all")) {
             //key_listにallが入っていた場合は子要素の配列はAND条件と解釈する。
             let mut and_node = selectionnodes::AndSelectionNode::new();
-            yaml.as_vec().unwrap().iter().for_each(|child_yaml| {
-                let child_node = Self::parse_selection_recursively(key_list, child_yaml);
+            for child_yaml in yaml.as_vec().unwrap() {
+                let child_node =
+                    Self::parse_selection_recursively(key_list, child_yaml, placeholders)?;
                 and_node.child_nodes.push(child_node);
-            });
-            Box::new(and_node)
+            }
+            Ok(Box::new(and_node))
         } else if yaml.as_vec().is_some() {
             // 配列はOR条件と解釈する。
             let mut or_node = selectionnodes::OrSelectionNode::new();
-            yaml.as_vec().unwrap().iter().for_each(|child_yaml| {
-                let child_node = Self::parse_selection_recursively(key_list, child_yaml);
+            for child_yaml in yaml.as_vec().unwrap() {
+                let child_node =
+                    Self::parse_selection_recursively(key_list, child_yaml, placeholders)?;
                 or_node.child_nodes.push(child_node);
-            });
-            Box::new(or_node)
+            }
+            Ok(Box::new(or_node))
+        } else if yaml.as_str().is_some() && key_list.iter().any(|k: &str| k.contains("|expand")) {
+            // |expandの場合、%placeholder%を展開した値の配列として扱う
+            Self::parse_expand_selection(key_list, yaml.as_str().unwrap(), placeholders)
         } else {
             // 連想配列と配列以外は末端ノード
-            Box::new(selectionnodes::LeafSelectionNode::new(
+            Ok(Box::new(selectionnodes::LeafSelectionNode::new(
                 key_list.clone(),
                 yaml.to_owned(),
-            ))
+            )))
+        }
+    }
+
+    /// |expandが指定されたselectionをパースします。
+    /// 展開した値はOR条件(|allが指定されている場合はAND条件)と解釈する。
+    fn parse_expand_selection(
+        key_list: &Nested<String>,
+        value: &str,
+        placeholders: &PlaceholderMap,
+    ) -> Result<Box<dyn SelectionNode>, Vec<String>> {
+        let expanded_values =
+            placeholder::expand_placeholders(value, placeholders).map_err(|name| {
+                vec![format!(
+                    "Undefined placeholder was specified. [placeholder:%{name}%, key:{}]",
+                    utils::concat_selection_key(key_list)
+                )]
+            })?;
+        // 展開した値に%が含まれていても再度展開しないように、ここで末端ノードを作成する
+        let child_nodes: Vec<Box<dyn SelectionNode>> = expanded_values
+            .into_iter()
+            .map(|expanded_value| -> Box<dyn SelectionNode> {
+                Box::new(selectionnodes::LeafSelectionNode::new(
+                    key_list.clone(),
+                    Yaml::String(expanded_value),
+                ))
+            })
+            .collect();
+        if key_list.iter().any(|k: &str| k.contains("|all")) {
+            let mut and_node = selectionnodes::AndSelectionNode::new();
+            and_node.child_nodes = child_nodes;
+            Ok(Box::new(and_node))
+        } else {
+            let mut or_node = selectionnodes::OrSelectionNode::new();
+            or_node.child_nodes = child_nodes;
+            Ok(Box::new(or_node))
         }
     }
 }
@@ -352,6 +671,10 @@ pub struct AggResult {
     pub start_timedate: DateTime<Utc>,
     ///条件式の情報
     pub condition_op_num: String,
+    ///相関ルールの場合に、検知に寄与したルールのタイトルの配列
+    pub contributing_rules: Vec<String>,
+    ///min, max, avg, sumの集計関数で集計した値。countの場合はNone
+    pub agg_value: Option<f64>,
 }
 
 impl AggResult {
@@ -368,12 +691,14 @@ impl AggResult {
             field_values: field_value,
             start_timedate: event_start_timedate,
             condition_op_num: condition_op_number,
+            contributing_rules: vec![],
+            agg_value: None,
         }
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use std::path::Path;
 
     use super::RuleNode;
@@ -383,12 +708,14 @@ mod tests {
             Action, CommonOptions, Config, CsvOutputOption, DetectCommonOption, InputOption,
             OutputOption, StoredStatic, STORED_EKEY_ALIAS,
         },
+        pipeline::{create_logsource_mappings, create_pipelines},
+        placeholder::create_placeholder_map,
         rule::create_rule,
         utils,
     };
     use yaml_rust::YamlLoader;
 
-    fn create_dummy_stored_static() -> StoredStatic {
+    pub fn create_dummy_stored_static() -> StoredStatic {
         StoredStatic::create_static_data(Some(Config {
             action: Some(Action::CsvTimeline(CsvOutputOption {
                 output_options: OutputOption {
@@ -398,6 +725,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -437,6 +765,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -450,6 +779,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -963,6 +1298,357 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_detect_utf16_without_base64() {
+        // utf16系のパイプの後にbase64が無い場合はエラーになることを確認するテスト
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                CommandLine|wide|contains: whoami
+        details: 'Rule parse test'
+        "#;
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let mut rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+
+        assert_eq!(
+            rule_node.init(&create_dummy_stored_static()),
+            Err(vec![
+                "utf16 modifiers must be followed by base64 or base64offset. key:detection -> selection -> CommandLine|wide|contains"
+                    .to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_detect_exists_not_boolean() {
+        // existsの値がtrue/false以外の場合はエラーになることを確認するテスト
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                CommandLine|exists: whoami
+        details: 'Rule parse test'
+        "#;
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let mut rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+
+        assert_eq!(
+            rule_node.init(&create_dummy_stored_static()),
+            Err(vec![
+                "exists value should be true or false. key:detection -> selection -> CommandLine|exists"
+                    .to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_detect_numeric_compare_not_number() {
+        // gt等の値が数値でない場合はエラーになることを確認するテスト
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                LogonType|gt: ten
+        details: 'Rule parse test'
+        "#;
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let mut rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+
+        assert_eq!(
+            rule_node.init(&create_dummy_stored_static()),
+            Err(vec![
+                "gt value should be a number. key:detection -> selection -> LogonType|gt"
+                    .to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_detect_regex_flag_without_re() {
+        // 正規表現のフラグがreなしで指定された場合はエラーになることを確認するテスト
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                CommandLine|contains|i: whoami
+        details: 'Rule parse test'
+        "#;
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let mut rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+
+        assert_eq!(
+            rule_node.init(&create_dummy_stored_static()),
+            Err(vec![
+                "i, m and s pipe elements must be used with re. key:detection -> selection -> CommandLine|contains|i"
+                    .to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_expand_placeholder() {
+        // |expandで指定したplaceholderが展開されて、OR条件で検知できることを確認するテスト
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                Channel: Security
+                EventID: 4624
+                WorkstationName|expand: '%Admins_Workstations%'
+            filter:
+                TargetServerName|expand|startswith: '%DomainControllers%'
+            condition: selection and not filter
+        details: 'Rule parse test'
+        "#;
+        let mut dummy_stored_static = create_dummy_stored_static();
+        dummy_stored_static.placeholders =
+            create_placeholder_map(Path::new("test_files/config/placeholders"));
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(dummy_stored_static.eventkey_alias.clone());
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let mut rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+        assert!(rule_node.init(&dummy_stored_static).is_ok());
+
+        let records = [
+            (
+                r#"{"Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData": {"WorkstationName": "admin-pc02", "TargetServerName": "FS01"}}}"#,
+                true,
+            ),
+            (
+                r#"{"Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData": {"WorkstationName": "USER-PC01", "TargetServerName": "FS01"}}}"#,
+                false,
+            ),
+            (
+                r#"{"Event": {"System": {"EventID": 4624, "Channel": "Security"}, "EventData": {"WorkstationName": "ADMIN-PC01", "TargetServerName": "DC02.corp.local"}}}"#,
+                false,
+            ),
+        ];
+        for (record_str, expect_select) in records {
+            let record = serde_json::from_str(record_str).unwrap();
+            let keys = detections::rule::get_detection_keys(&rule_node);
+            let recinfo =
+                utils::create_rec_info(record, "testpath".to_owned(), &keys, &false, &false);
+            assert_eq!(
+                rule_node.select(
+                    &recinfo,
+                    dummy_stored_static.verbose_flag,
+                    dummy_stored_static.quiet_errors_flag,
+                    dummy_stored_static.json_input_flag,
+                    &dummy_stored_static.eventkey_alias
+                ),
+                expect_select
+            );
+        }
+    }
+
+    #[test]
+    fn test_pipeline_variants() {
+        // パイプラインでlogsourceがvariant毎の条件に展開され、フィールド名が変換されて検知できることを確認するテスト
+        let rule_str = r#"
+        title: upstream sigma rule
+        logsource:
+            category: process_creation
+            product: windows
+        detection:
+            selection:
+                Image|endswith: '\rundll32.exe'
+                CommandLine|contains: 'javascript:'
+            condition: selection
+        "#;
+        let mut dummy_stored_static = create_dummy_stored_static();
+        dummy_stored_static.pipelines = create_pipelines(Path::new("test_files/config/pipelines"));
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(dummy_stored_static.eventkey_alias.clone());
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let mut rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+        assert!(rule_node.init(&dummy_stored_static).is_ok());
+
+        let records = [
+            (
+                r#"{"Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData": {"Image": "C:\\Windows\\System32\\rundll32.exe", "CommandLine": "rundll32.exe javascript:alert()"}}}"#,
+                true,
+            ),
+            (
+                r#"{"Event": {"System": {"EventID": 4688, "Channel": "Security"}, "EventData": {"NewProcessName": "C:\\Windows\\System32\\rundll32.exe", "CommandLine": "rundll32.exe javascript:alert()"}}}"#,
+                true,
+            ),
+            (
+                r#"{"Event": {"System": {"EventID": 4688, "Channel": "Security"}, "EventData": {"Image": "C:\\Windows\\System32\\rundll32.exe", "CommandLine": "rundll32.exe javascript:alert()"}}}"#,
+                false,
+            ),
+            (
+                r#"{"Event": {"System": {"EventID": 1, "Channel": "Security"}, "EventData": {"Image": "C:\\Windows\\System32\\rundll32.exe", "CommandLine": "rundll32.exe javascript:alert()"}}}"#,
+                false,
+            ),
+        ];
+        for (record_str, expect_select) in records {
+            let record = serde_json::from_str(record_str).unwrap();
+            let keys = detections::rule::get_detection_keys(&rule_node);
+            let recinfo =
+                utils::create_rec_info(record, "testpath".to_owned(), &keys, &false, &false);
+            assert_eq!(
+                rule_node.select(
+                    &recinfo,
+                    dummy_stored_static.verbose_flag,
+                    dummy_stored_static.quiet_errors_flag,
+                    dummy_stored_static.json_input_flag,
+                    &dummy_stored_static.eventkey_alias
+                ),
+                expect_select
+            );
+        }
+        let index_key = rule_node.get_index_key();
+        assert_eq!(
+            index_key.event_ids,
+            Some(["1".to_string(), "4688".to_string()].into_iter().collect())
+        );
+    }
+
+    #[test]
+    fn test_logsource_conditions() {
+        // detectionでChannelを指定していないルールは、logsourceに対応するChannelとEventIDの条件で絞り込まれることを確認するテスト
+        let rule_str = r#"
+        title: converted sigma rule
+        ruletype: Sigma
+        logsource:
+            category: process_creation
+            product: windows
+        detection:
+            selection:
+                Image|endswith: '\rundll32.exe'
+            condition: selection
+        "#;
+        let mut dummy_stored_static = create_dummy_stored_static();
+        dummy_stored_static.logsource_mappings =
+            create_logsource_mappings(Path::new("test_files/config/logsource_mapping.yaml"));
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(dummy_stored_static.eventkey_alias.clone());
+        let check = |rule_str: &str, records: &[(&str, bool)]| {
+            let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+            let mut rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+            assert!(rule_node.init(&dummy_stored_static).is_ok());
+            for (record_str, expect_select) in records {
+                let record = serde_json::from_str(record_str).unwrap();
+                let keys = detections::rule::get_detection_keys(&rule_node);
+                let recinfo =
+                    utils::create_rec_info(record, "testpath".to_owned(), &keys, &false, &false);
+                assert_eq!(
+                    rule_node.select(
+                        &recinfo,
+                        dummy_stored_static.verbose_flag,
+                        dummy_stored_static.quiet_errors_flag,
+                        dummy_stored_static.json_input_flag,
+                        &dummy_stored_static.eventkey_alias
+                    ),
+                    *expect_select
+                );
+            }
+        };
+
+        let sysmon_record = r#"{"Event": {"System": {"EventID": 1, "Channel": "Microsoft-Windows-Sysmon/Operational"}, "EventData": {"Image": "C:\\Windows\\System32\\rundll32.exe"}}}"#;
+        let other_record = r#"{"Event": {"System": {"EventID": 1, "Channel": "Application"}, "EventData": {"Image": "C:\\Windows\\System32\\rundll32.exe"}}}"#;
+        check(rule_str, &[(sysmon_record, true), (other_record, false)]);
+
+        // detectionでChannelを指定している場合は条件を追加しない
+        let rule_str = rule_str.replace(
+            "            condition: selection",
+            "                Channel: Application\n            condition: selection",
+        );
+        check(&rule_str, &[(sysmon_record, false), (other_record, true)]);
+    }
+
+    #[test]
+    fn test_apply_filter() {
+        // フィルタールールのfilterがandで追加されて検知が除外されることを確認するテスト
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                Channel: Security
+                EventID: 4625
+            condition: selection
+        details: 'Rule parse test'
+        "#;
+        let filter_str = r#"
+        rules:
+            - 0c5ae6b2-6bde-4f4d-8d9b-3fc7b1c4ddf1
+        selection:
+            WorkstationName: 'ADMIN-PC01'
+        condition: not selection
+        "#;
+        let dummy_stored_static = create_dummy_stored_static();
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(dummy_stored_static.eventkey_alias.clone());
+        let mut rule_node = parse_rule_from_str(rule_str);
+        let filter_yaml = YamlLoader::load_from_str(filter_str).unwrap().remove(0);
+        assert!(rule_node
+            .apply_filter(&filter_yaml, &dummy_stored_static)
+            .is_ok());
+
+        let records = [
+            (
+                r#"{"Event": {"System": {"EventID": 4625, "Channel": "Security"}, "EventData": {"WorkstationName": "USER-PC01"}}}"#,
+                true,
+            ),
+            (
+                r#"{"Event": {"System": {"EventID": 4625, "Channel": "Security"}, "EventData": {"WorkstationName": "ADMIN-PC01"}}}"#,
+                false,
+            ),
+        ];
+        for (record_str, expect_select) in records {
+            let record = serde_json::from_str(record_str).unwrap();
+            let keys = detections::rule::get_detection_keys(&rule_node);
+            assert!(keys.iter().any(|key| key == "WorkstationName"));
+            let recinfo =
+                utils::create_rec_info(record, "testpath".to_owned(), &keys, &false, &false);
+            assert_eq!(
+                rule_node.select(
+                    &recinfo,
+                    dummy_stored_static.verbose_flag,
+                    dummy_stored_static.quiet_errors_flag,
+                    dummy_stored_static.json_input_flag,
+                    &dummy_stored_static.eventkey_alias
+                ),
+                expect_select
+            );
+        }
+
+        // aggregation conditionはフィルタールールでは使えない
+        let filter_str = r#"
+        rules:
+            - 0c5ae6b2-6bde-4f4d-8d9b-3fc7b1c4ddf1
+        selection:
+            WorkstationName: 'ADMIN-PC01'
+        condition: selection | count() > 1
+        "#;
+        let filter_yaml = YamlLoader::load_from_str(filter_str).unwrap().remove(0);
+        assert_eq!(
+            rule_node.apply_filter(&filter_yaml, &dummy_stored_static),
+            Err(vec![
+                "An aggregation condition cannot be used in filter rules.".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_expand_undefined_placeholder() {
+        // 定義されていないplaceholderが指定された場合はエラーになることを確認するテスト
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection:
+                WorkstationName|expand: '%NotDefined%'
+        details: 'Rule parse test'
+        "#;
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let mut rule_node = create_rule("testpath".to_string(), rule_yaml.next().unwrap());
+
+        assert_eq!(
+            rule_node.init(&create_dummy_stored_static()),
+            Err(vec![
+                "Undefined placeholder was specified. [placeholder:%NotDefined%, key:detection -> selection -> WorkstationName|expand]"
+                    .to_string()
+            ])
+        );
+    }
+
     #[test]
     fn test_detect_not_defined_selection() {
         // 不明な文字列オプションがルールに書かれていたら警告するテスト
@@ -1035,6 +1721,52 @@ mod tests {
         check_select(rule_str, record_json_str2, false);
     }
 
+    #[test]
+    fn test_get_match_reasons() {
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection_img:
+                Event.EventData.Image|endswith: '\rundll32.exe'
+            selection_cli:
+                Event.EventData.CommandLine|contains:
+                    - 'javascript:'
+                    - '.dll,'
+            filter:
+                Event.EventData.User: SYSTEM
+            condition: selection_img and selection_cli and not filter
+        "#;
+        let rule_node = parse_rule_from_str(rule_str);
+        let dummy_stored_static = create_dummy_stored_static();
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(dummy_stored_static.eventkey_alias.clone());
+        let get_reasons = |record_str: &str| {
+            let record = serde_json::from_str(record_str).unwrap();
+            let keys = detections::rule::get_detection_keys(&rule_node);
+            let recinfo =
+                utils::create_rec_info(record, "testpath".to_owned(), &keys, &false, &false);
+            rule_node.get_match_reasons(&recinfo, &dummy_stored_static.eventkey_alias)
+        };
+
+        let record_json_str = r#"
+        {
+            "Event": {"System": {"EventID": 1}, "EventData": {"Image": "C:\\Windows\\System32\\rundll32.exe", "CommandLine": "rundll32.exe test.dll,Start", "User": "user01"}}
+        }"#;
+        assert_eq!(
+            get_reasons(record_json_str),
+            vec![
+                "selection_img: Event.EventData.Image|endswith '\\rundll32.exe'".to_string(),
+                "selection_cli: Event.EventData.CommandLine|contains '.dll,'".to_string()
+            ]
+        );
+
+        // 検知されない場合は、一部の条件に一致していても検知理由を返さない
+        let record_json_str = r#"
+        {
+            "Event": {"System": {"EventID": 1}, "EventData": {"Image": "C:\\Windows\\System32\\rundll32.exe", "CommandLine": "rundll32.exe test.dll,Start", "User": "SYSTEM"}}
+        }"#;
+        assert!(get_reasons(record_json_str).is_empty());
+    }
+
     /// countで対象の数値確認を行うためのテスト用関数
     fn _check_count(rule_str: &str, record_str: &str, key: &str, expect_count: i32) {
         let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
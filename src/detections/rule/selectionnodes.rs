@@ -13,6 +13,15 @@ pub trait SelectionNode: Downcast + Send + Sync {
     // このトレイトを実装する構造体毎に適切な判定処理を書く必要がある。
     fn select(&self, event_record: &EvtxRecordInfo, eventkey_alias: &EventKeyAliasConfig) -> bool;
 
+    // selectと同じ判定を行い、一致した場合は一致したselectionとフィールドの説明をreasonsに追加する
+    // 一致しなかった場合はreasonsに何も追加しない
+    fn explain(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+        reasons: &mut Vec<String>,
+    ) -> bool;
+
     // 初期化処理を行う
     // 戻り値としてエラーを返却できるようになっているので、Ruleファイルが間違っていて、SelectionNodeを構成出来ない時はここでエラーを出す
     // AndSelectionNode等ではinit()関数とは別にnew()関数を実装しているが、new()関数はただインスタンスを作るだけにして、あまり長い処理を書かないようにしている。
@@ -47,6 +56,23 @@ impl SelectionNode for AndSelectionNode {
             .all(|child_node| child_node.select(event_record, eventkey_alias))
     }
 
+    fn explain(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+        reasons: &mut Vec<String>,
+    ) -> bool {
+        let reasons_len = reasons.len();
+        let result = self
+            .child_nodes
+            .iter()
+            .all(|child_node| child_node.explain(event_record, eventkey_alias, reasons));
+        if !result {
+            reasons.truncate(reasons_len);
+        }
+        result
+    }
+
     fn init(&mut self) -> Result<(), Vec<String>> {
         let err_msgs = self
             .child_nodes
@@ -117,6 +143,23 @@ impl SelectionNode for AllSelectionNode {
             .all(|child_node| child_node.select(event_record, eventkey_alias))
     }
 
+    fn explain(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+        reasons: &mut Vec<String>,
+    ) -> bool {
+        let reasons_len = reasons.len();
+        let result = self
+            .child_nodes
+            .iter()
+            .all(|child_node| child_node.explain(event_record, eventkey_alias, reasons));
+        if !result {
+            reasons.truncate(reasons_len);
+        }
+        result
+    }
+
     fn init(&mut self) -> Result<(), Vec<String>> {
         let err_msgs = self
             .child_nodes
@@ -187,6 +230,17 @@ impl SelectionNode for OrSelectionNode {
             .any(|child_node| child_node.select(event_record, eventkey_alias))
     }
 
+    fn explain(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+        reasons: &mut Vec<String>,
+    ) -> bool {
+        self.child_nodes
+            .iter()
+            .any(|child_node| child_node.explain(event_record, eventkey_alias, reasons))
+    }
+
     fn init(&mut self) -> Result<(), Vec<String>> {
         let err_msgs = self
             .child_nodes
@@ -237,6 +291,89 @@ impl SelectionNode for OrSelectionNode {
     }
 }
 
+/// conditionでN of selection*を表すノード。子ノードのうちN個以上が一致する場合に一致する
+pub struct CountSelectionNode {
+    pub child_nodes: Vec<Box<dyn SelectionNode>>,
+    count: usize,
+}
+
+impl CountSelectionNode {
+    pub fn new(count: usize) -> CountSelectionNode {
+        CountSelectionNode {
+            child_nodes: vec![],
+            count,
+        }
+    }
+}
+
+impl SelectionNode for CountSelectionNode {
+    fn select(&self, event_record: &EvtxRecordInfo, eventkey_alias: &EventKeyAliasConfig) -> bool {
+        // 各子ノードは1回だけ判定し、N個一致した時点で判定を終える
+        let mut matched_cnt = 0;
+        self.child_nodes.iter().any(|child_node| {
+            if child_node.select(event_record, eventkey_alias) {
+                matched_cnt += 1;
+            }
+            matched_cnt >= self.count
+        })
+    }
+
+    fn explain(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+        reasons: &mut Vec<String>,
+    ) -> bool {
+        let reasons_len = reasons.len();
+        let mut matched_cnt = 0;
+        let result = self.child_nodes.iter().any(|child_node| {
+            if child_node.explain(event_record, eventkey_alias, reasons) {
+                matched_cnt += 1;
+            }
+            matched_cnt >= self.count
+        });
+        if !result {
+            reasons.truncate(reasons_len);
+        }
+        result
+    }
+
+    fn init(&mut self) -> Result<(), Vec<String>> {
+        let err_msgs: Vec<String> = self
+            .child_nodes
+            .iter_mut()
+            .filter_map(|child_node| child_node.init().err())
+            .flatten()
+            .collect();
+
+        if err_msgs.is_empty() {
+            Result::Ok(())
+        } else {
+            Result::Err(err_msgs)
+        }
+    }
+
+    fn get_childs(&self) -> Vec<&dyn SelectionNode> {
+        self.child_nodes
+            .iter()
+            .map(|child_node| child_node.as_ref())
+            .collect()
+    }
+
+    fn get_descendants(&self) -> Vec<&dyn SelectionNode> {
+        let mut ret = self.get_childs();
+
+        self.child_nodes
+            .iter()
+            .flat_map(|child_node| child_node.get_descendants())
+            .for_each(|descendant_node| {
+                ret.push(descendant_node);
+            });
+
+        ret
+    }
+}
+
 /// conditionでNotを表すノード
 pub struct NotSelectionNode {
     node: Box<dyn SelectionNode>,
@@ -253,6 +390,16 @@ impl SelectionNode for NotSelectionNode {
         !self.node.select(event_record, eventkey_alias)
     }
 
+    fn explain(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+        _reasons: &mut Vec<String>,
+    ) -> bool {
+        // notの場合は一致しなかった条件なので、説明を追加しない
+        self.select(event_record, eventkey_alias)
+    }
+
     fn init(&mut self) -> Result<(), Vec<String>> {
         Result::Ok(())
     }
@@ -272,12 +419,15 @@ pub struct RefSelectionNode {
     // そこでArcを使って、DetectionNodeのname_2_nodeとRefSelectionNodeのselection_nodeで所有権を共有する。
     // RcじゃなくてArcなのはマルチスレッド対応のため
     selection_node: Arc<Box<dyn SelectionNode>>,
+    // 検知理由の出力に使うselectionの名前
+    name: String,
 }
 
 impl RefSelectionNode {
-    pub fn new(select_node: Arc<Box<dyn SelectionNode>>) -> RefSelectionNode {
+    pub fn new(name: String, select_node: Arc<Box<dyn SelectionNode>>) -> RefSelectionNode {
         RefSelectionNode {
             selection_node: select_node,
+            name,
         }
     }
 }
@@ -287,6 +437,22 @@ impl SelectionNode for RefSelectionNode {
         self.selection_node.select(event_record, eventkey_alias)
     }
 
+    fn explain(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+        reasons: &mut Vec<String>,
+    ) -> bool {
+        let reasons_len = reasons.len();
+        let result = self
+            .selection_node
+            .explain(event_record, eventkey_alias, reasons);
+        for reason in reasons.iter_mut().skip(reasons_len) {
+            *reason = format!("{}: {}", self.name, reason);
+        }
+        result
+    }
+
     fn init(&mut self) -> Result<(), Vec<String>> {
         Result::Ok(())
     }
@@ -340,6 +506,14 @@ impl LeafSelectionNode {
         keys
     }
 
+    pub fn get_key_list(&self) -> &Nested<String> {
+        &self.key_list
+    }
+
+    pub fn get_select_value(&self) -> &Yaml {
+        &self.select_value
+    }
+
     fn _create_key(&self) -> String {
         if self.key_list.is_empty() {
             return String::default();
@@ -351,12 +525,28 @@ impl LeafSelectionNode {
 
     /// JSON形式のEventJSONから値を取得する関数 aliasも考慮されている。
     fn get_event_value<'a>(&self, record: &'a EvtxRecordInfo) -> Option<&'a String> {
-        // keyが指定されていない場合はそのままのレコードのデータを取得する
+        record.get_value(self.get_key())
+    }
+
+    /// フィールド名が指定されていないキーワードのノードかを返す関数
+    pub fn is_keyword(&self) -> bool {
+        matchers::is_keyword(&self.key_list)
+    }
+
+    /// 検知理由として出力する、キーと値を表す文字列を取得する。例: Image|endswith '\rundll32.exe'
+    pub fn get_match_reason(&self) -> String {
+        let value = match &self.select_value {
+            Yaml::String(s) => s.to_owned(),
+            Yaml::Integer(i) => i.to_string(),
+            Yaml::Real(r) => r.to_owned(),
+            Yaml::Boolean(b) => b.to_string(),
+            _ => "null".to_string(),
+        };
         if self.key_list.is_empty() {
-            return Option::Some(&record.data_string);
+            return format!("'{value}'");
         }
-
-        record.get_value(self.get_key())
+        let key = self.key_list.iter().collect::<Vec<_>>().join(".");
+        format!("{key} '{value}'")
     }
 
     /// matchers::LeafMatcherの一覧を取得する。
@@ -377,6 +567,14 @@ impl SelectionNode for LeafSelectionNode {
             return false;
         }
 
+        // キーワードの場合は、レコード内のいずれかのフィールドの値に一致するかを判定する
+        if self.is_keyword() {
+            let matcher = self.matcher.as_ref().unwrap();
+            return utils::any_event_value(&event_record.record, &mut |value| {
+                matcher.is_match(Some(value), event_record)
+            });
+        }
+
         // EventDataはXMLが特殊な形式になっているので特別対応。
         //// 元のXMLは下記のような形式
         /*
@@ -446,16 +644,16 @@ impl SelectionNode for LeafSelectionNode {
             }
         }
 
-        let mut event_value = self.get_event_value(event_record);
-        if self.get_key() == "EventID" && !self.select_value.is_null() {
+        let event_value = self.get_event_value(event_record);
+        if self.get_key() == "EventID"
+            && !self.select_value.is_null()
+            && !utils::contains_str(&self.key_list[0], "|")
+        {
             if let Some(event_id) = self.select_value.as_i64() {
                 // 正規表現は重いので、数値のEventIDのみ文字列完全一致で判定
                 return event_value.unwrap_or(&String::default()) == &event_id.to_string();
             }
         }
-        if !self.key_list.is_empty() && self.key_list[0].eq("|all") {
-            event_value = Some(&event_record.data_string);
-        }
         return self
             .matcher
             .as_ref()
@@ -463,6 +661,19 @@ impl SelectionNode for LeafSelectionNode {
             .is_match(event_value, event_record);
     }
 
+    fn explain(
+        &self,
+        event_record: &EvtxRecordInfo,
+        eventkey_alias: &EventKeyAliasConfig,
+        reasons: &mut Vec<String>,
+    ) -> bool {
+        let result = self.select(event_record, eventkey_alias);
+        if result {
+            reasons.push(self.get_match_reason());
+        }
+        result
+    }
+
     fn init(&mut self) -> Result<(), Vec<String>> {
         let matchers = self.get_matchers();
         self.matcher = matchers
@@ -525,6 +736,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -564,6 +776,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -577,6 +790,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -0,0 +1,266 @@
+use crate::detections::configs::{StoredStatic, CURRENT_EXE_PATH};
+use crate::detections::utils;
+use crate::filter::RuleExclude;
+use crate::yaml::ParseYaml;
+use hashbrown::HashMap;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh3::Xxh3;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::Yaml;
+
+/// キャッシュファイルの保存先のデフォルトのパス
+pub fn default_cache_path() -> PathBuf {
+    CURRENT_EXE_PATH.join("cache").join("rules.json")
+}
+
+/// yaml_rust::Yamlはシリアライズできないので、型を保持したままシリアライズするための列挙型
+#[derive(Serialize, Deserialize)]
+enum CachedYaml {
+    Real(String),
+    Integer(i64),
+    String(String),
+    Boolean(bool),
+    Array(Vec<CachedYaml>),
+    Hash(Vec<(CachedYaml, CachedYaml)>),
+    Alias(usize),
+    Null,
+    BadValue,
+}
+
+impl From<&Yaml> for CachedYaml {
+    fn from(yaml: &Yaml) -> Self {
+        match yaml {
+            Yaml::Real(v) => CachedYaml::Real(v.to_owned()),
+            Yaml::Integer(v) => CachedYaml::Integer(*v),
+            Yaml::String(v) => CachedYaml::String(v.to_owned()),
+            Yaml::Boolean(v) => CachedYaml::Boolean(*v),
+            Yaml::Array(v) => CachedYaml::Array(v.iter().map(CachedYaml::from).collect()),
+            Yaml::Hash(v) => CachedYaml::Hash(
+                v.iter()
+                    .map(|(key, val)| (CachedYaml::from(key), CachedYaml::from(val)))
+                    .collect(),
+            ),
+            Yaml::Alias(v) => CachedYaml::Alias(*v),
+            Yaml::Null => CachedYaml::Null,
+            Yaml::BadValue => CachedYaml::BadValue,
+        }
+    }
+}
+
+impl From<CachedYaml> for Yaml {
+    fn from(yaml: CachedYaml) -> Self {
+        match yaml {
+            CachedYaml::Real(v) => Yaml::Real(v),
+            CachedYaml::Integer(v) => Yaml::Integer(v),
+            CachedYaml::String(v) => Yaml::String(v),
+            CachedYaml::Boolean(v) => Yaml::Boolean(v),
+            CachedYaml::Array(v) => Yaml::Array(v.into_iter().map(Yaml::from).collect()),
+            CachedYaml::Hash(v) => Yaml::Hash(
+                v.into_iter()
+                    .map(|(key, val)| (Yaml::from(key), Yaml::from(val)))
+                    .collect::<YamlHash>(),
+            ),
+            CachedYaml::Alias(v) => Yaml::Alias(v),
+            CachedYaml::Null => Yaml::Null,
+            CachedYaml::BadValue => Yaml::BadValue,
+        }
+    }
+}
+
+/// ParseYamlで読み込み、フィルタリングしたルールとルール数の集計結果のキャッシュ
+#[derive(Serialize, Deserialize)]
+struct RuleCache {
+    key: String,
+    files: Vec<(String, CachedYaml)>,
+    filtered_files: Vec<(String, CachedYaml)>,
+    rulecounter: Vec<(String, u128)>,
+    rule_load_cnt: Vec<(String, u128)>,
+    rule_status_cnt: Vec<(String, u128)>,
+}
+
+/// ParseYaml::read_dirと同じ条件で読み込み対象になるルールファイルのパスを名前順で返す
+fn collect_rule_paths(path: &Path, ret: &mut Vec<PathBuf>) -> io::Result<()> {
+    if fs::metadata(path)?.is_file() {
+        if path.extension().unwrap_or_else(|| OsStr::new("")) == "yml" {
+            ret.push(path.to_path_buf());
+        }
+        return Ok(());
+    }
+    for entry in fs::read_dir(path)?.sorted_by_key(|e| e.as_ref().map(|e| e.path()).ok()) {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_rule_paths(&path, ret)?;
+            continue;
+        }
+        if !file_type.is_file() || path.extension().unwrap_or_else(|| OsStr::new("")) != "yml" {
+            continue;
+        }
+        let path_str = path.to_str().unwrap_or_default();
+        if utils::contains_str(path_str, "/.git/")
+            || utils::contains_str(path_str, "\\.git\\")
+            || utils::contains_str(path_str, "rules/tools/sigmac/test_files")
+            || utils::contains_str(path_str, "rules\\tools\\sigmac\\test_files")
+        {
+            continue;
+        }
+        ret.push(path);
+    }
+    Ok(())
+}
+
+/// キャッシュのキーに値を追加する。値の区切りが曖昧にならないように、長さを先に追加する
+fn update_key(hasher: &mut Xxh3, value: &[u8]) {
+    hasher.update(&(value.len() as u64).to_le_bytes());
+    hasher.update(value);
+}
+
+/// 文字列の一覧を名前順にしてキャッシュのキーに追加する
+fn update_key_sorted<T: AsRef<str> + Ord>(hasher: &mut Xxh3, values: impl Iterator<Item = T>) {
+    let values: Vec<T> = values.sorted().collect();
+    hasher.update(&(values.len() as u64).to_le_bytes());
+    values
+        .iter()
+        .for_each(|value| update_key(hasher, value.as_ref().as_bytes()));
+}
+
+/// ルールファイルのパスと内容、hayabusaのバージョン、ルールの読み込み条件からキャッシュのキーを計算する。
+/// update-rules等でルールファイルが変更された場合はキーが変わるので、キャッシュは自動的に使われなくなる。
+/// Rustのバージョンによってキーが変わらないように、DefaultHasherではなくxxh3でハッシュ値を計算する。
+pub fn calc_cache_key(
+    path: &Path,
+    min_level: &str,
+    target_level: &str,
+    exclude_ids: &RuleExclude,
+    stored_static: &StoredStatic,
+) -> io::Result<String> {
+    let mut hasher = Xxh3::new();
+    update_key(&mut hasher, env!("CARGO_PKG_VERSION").as_bytes());
+    update_key(&mut hasher, path.to_string_lossy().as_bytes());
+    let mut rule_paths = vec![];
+    collect_rule_paths(path, &mut rule_paths)?;
+    hasher.update(&(rule_paths.len() as u64).to_le_bytes());
+    for rule_path in rule_paths {
+        update_key(&mut hasher, rule_path.to_string_lossy().as_bytes());
+        update_key(&mut hasher, &fs::read(&rule_path)?);
+    }
+
+    // ルールの読み込み条件が異なる場合はフィルタリングの結果も異なるので、キーに含める
+    update_key(&mut hasher, min_level.as_bytes());
+    update_key(&mut hasher, target_level.as_bytes());
+    update_key_sorted(&mut hasher, exclude_ids.no_use_rule.keys());
+    update_key_sorted(&mut hasher, stored_static.target_ruleids.get_ids().iter());
+    update_key_sorted(&mut hasher, stored_static.include_status.iter());
+    match stored_static.output_option.as_ref() {
+        Some(o) => {
+            hasher.update(&[
+                1,
+                o.enable_noisy_rules as u8,
+                o.enable_deprecated_rules as u8,
+                o.enable_unsupported_rules as u8,
+            ]);
+            for values in [
+                &o.exclude_status,
+                &o.include_category,
+                &o.exclude_category,
+                &o.include_tag,
+                &o.exclude_tag,
+            ] {
+                match values {
+                    Some(values) => {
+                        hasher.update(&[1]);
+                        update_key_sorted(&mut hasher, values.iter());
+                    }
+                    None => hasher.update(&[0]),
+                }
+            }
+        }
+        None => hasher.update(&[0]),
+    }
+    Ok(format!("{:032x}", hasher.digest128()))
+}
+
+fn to_sorted_vec<K: ToString>(counter: &HashMap<K, u128>) -> Vec<(String, u128)> {
+    counter
+        .iter()
+        .map(|(key, cnt)| (key.to_string(), *cnt))
+        .sorted()
+        .collect()
+}
+
+/// キャッシュのキーが一致する場合に、キャッシュしたルールと集計結果をParseYamlに設定してtrueを返す
+pub fn load(cache_path: &Path, key: &str, parse_yaml: &mut ParseYaml) -> bool {
+    let cache: RuleCache = match fs::read(cache_path)
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+    {
+        Some(cache) => cache,
+        None => return false,
+    };
+    if cache.key != key {
+        return false;
+    }
+    parse_yaml.files = cache
+        .files
+        .into_iter()
+        .map(|(filepath, yaml)| (filepath, Yaml::from(yaml)))
+        .collect();
+    parse_yaml.filtered_files = cache
+        .filtered_files
+        .into_iter()
+        .map(|(filepath, yaml)| (filepath, Yaml::from(yaml)))
+        .collect();
+    parse_yaml.rulecounter = cache
+        .rulecounter
+        .into_iter()
+        .map(|(key, cnt)| (key.into(), cnt))
+        .collect();
+    parse_yaml.rule_load_cnt = cache
+        .rule_load_cnt
+        .into_iter()
+        .map(|(key, cnt)| (key.into(), cnt))
+        .collect();
+    parse_yaml.rule_status_cnt = cache
+        .rule_status_cnt
+        .into_iter()
+        .map(|(key, cnt)| (key.into(), cnt))
+        .collect();
+    true
+}
+
+/// ParseYamlで読み込んだルールと集計結果をキャッシュファイルに保存する
+pub fn save(cache_path: &Path, key: &str, parse_yaml: &ParseYaml) -> Result<(), String> {
+    let cache = RuleCache {
+        key: key.to_string(),
+        files: parse_yaml
+            .files
+            .iter()
+            .map(|(filepath, yaml)| (filepath.to_owned(), CachedYaml::from(yaml)))
+            .collect(),
+        filtered_files: parse_yaml
+            .filtered_files
+            .iter()
+            .map(|(filepath, yaml)| (filepath.to_owned(), CachedYaml::from(yaml)))
+            .collect(),
+        rulecounter: to_sorted_vec(&parse_yaml.rulecounter),
+        rule_load_cnt: to_sorted_vec(&parse_yaml.rule_load_cnt),
+        rule_status_cnt: to_sorted_vec(&parse_yaml.rule_status_cnt),
+    };
+    let content = serde_json::to_vec(&cache).map_err(|e| e.to_string())?;
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    // 書き込み途中のキャッシュファイルを読み込まないように、一時ファイルに書き込んでから置き換える
+    let tmp_path = cache_path.with_extension(format!("{key}.tmp"));
+    fs::write(&tmp_path, content).map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, cache_path).map_err(|e| {
+        fs::remove_file(&tmp_path).ok();
+        e.to_string()
+    })
+}
@@ -2,6 +2,7 @@ extern crate base64;
 extern crate csv;
 extern crate regex;
 
+use crate::archive;
 use crate::detections::configs::CURRENT_EXE_PATH;
 use crate::options::htmlreport;
 
@@ -22,12 +23,12 @@ use regex::Regex;
 use serde_json::{json, Error, Map, Value};
 use std::cmp::Ordering;
 use std::fs::{read_to_string, File};
+use std::io;
 use std::io::prelude::*;
 use std::io::{BufRead, BufReader};
 use std::str;
 use std::string::String;
 use std::vec;
-use std::{fs, io};
 use termcolor::{BufferWriter, ColorSpec, WriteColor};
 
 use super::configs::{EventKeyAliasConfig, OutputOption, STORED_EKEY_ALIAS};
@@ -80,6 +81,17 @@ pub fn value_to_string(value: &Value) -> Option<String> {
     }
 }
 
+/// レコード内の全てのフィールドの値のいずれかがpredicateを満たすかを判定する関数
+pub fn any_event_value(value: &Value, predicate: &mut dyn FnMut(&String) -> bool) -> bool {
+    match value {
+        Value::Object(map) => map.values().any(|v| any_event_value(v, predicate)),
+        Value::Array(values) => values.iter().any(|v| any_event_value(v, predicate)),
+        // value_to_stringと同様に前後の空白を除いた値で比較する
+        Value::String(s) if s.trim().len() == s.len() => predicate(s),
+        _ => value_to_string(value).is_some_and(|s| predicate(&s)),
+    }
+}
+
 pub fn read_txt(filename: &str) -> Result<Nested<String>, String> {
     let filepath = if filename.starts_with("./") {
         check_setting_path(&CURRENT_EXE_PATH.to_path_buf(), filename, true)
@@ -101,18 +113,37 @@ pub fn read_txt(filename: &str) -> Result<Nested<String>, String> {
     ))
 }
 
+/// -f -で標準入力からログを読み込む場合に指定するパス
+pub const STDIN_PATH: &str = "-";
+
+/// 標準入力から読み込むことを示すパス(-)の場合にtrueを返す
+pub fn is_stdin_path(path: &Path) -> bool {
+    path.as_os_str() == STDIN_PATH
+}
+
 /// convert jsonl fmt string to serde_json Value iterator
 pub fn read_jsonl_to_value(path: &str) -> Result<Box<dyn Iterator<Item = Value>>, String> {
-    let f = File::open(path);
-    if f.is_err() {
-        return Err("Cannot open file. [file:{path}]".to_string());
+    if is_stdin_path(Path::new(path)) {
+        // 標準入力はメモリ上に全て読み込まずに、1行ずつ読み込んで検知処理に渡す
+        return read_jsonl_from_reader(io::stdin().lock());
     }
-    let reader = BufReader::new(f.unwrap());
+    // zip等のアーカイブ内のファイル(archive.zip!/path/file.jsonl)も読み込めるようにする
+    match archive::open_file(Path::new(path)) {
+        Ok(f) => read_jsonl_from_reader(BufReader::new(f)),
+        Err(_) => Err(format!("Cannot open file. [file:{path}]")),
+    }
+}
+
+/// JSONL形式の入力を1行ずつ読み込み、serde_json Value iteratorに変換する
+pub fn read_jsonl_from_reader<R: BufRead + 'static>(
+    reader: R,
+) -> Result<Box<dyn Iterator<Item = Value>>, String> {
     let mut peekable_lines = reader.lines().peekable();
-    let first_line = peekable_lines.peek().unwrap();
-    let is_jsonl = match first_line {
-        Ok(s) => serde_json::from_str::<Value>(s).is_ok(),
-        Err(_) => false,
+    let is_jsonl = match peekable_lines.peek() {
+        Some(Ok(s)) => serde_json::from_str::<Value>(s).is_ok(),
+        Some(Err(_)) => false,
+        // 空のファイル(標準入力)の場合はレコードなしとして扱う
+        None => return Ok(Box::new(std::iter::empty())),
     };
     if is_jsonl {
         let ret = peekable_lines
@@ -129,7 +160,10 @@ pub fn read_jsonl_to_value(path: &str) -> Result<Box<dyn Iterator<Item = Value>>
 
 /// convert json fmt string to serde_json Value iterator
 pub fn read_json_to_value(path: &str) -> Result<Box<dyn Iterator<Item = Value>>, String> {
-    let f = fs::read_to_string(path);
+    let f = archive::open_file(Path::new(path)).and_then(|mut f| {
+        let mut contents = String::new();
+        f.read_to_string(&mut contents).map(|_| contents)
+    });
     if f.is_err() {
         return Err("Cannot open file. [file:{path}]".to_string());
     }
@@ -163,6 +197,58 @@ pub fn read_json_to_value(path: &str) -> Result<Box<dyn Iterator<Item = Value>>,
     }
 }
 
+/// Winlogbeat(Elastic Common Schema)形式のレコードの場合に、ChannelやEventID等のフィールドを
+/// hayabusaのJSON入力と同じ形式(EventData直下のフィールド)に変換したレコードを返す。
+/// Elasticsearchからエクスポートしたデータ(_sourceにイベントが格納されている形式)にも対応する。
+pub fn convert_winlogbeat_json(record: &Value) -> Option<Value> {
+    let record = if record["_source"].is_object() {
+        &record["_source"]
+    } else {
+        record
+    };
+    let winlog = record["winlog"].as_object()?;
+    let mut event_data = Map::new();
+    for key in ["user_data", "event_data"] {
+        if let Some(data) = winlog.get(key).and_then(|v| v.as_object()) {
+            event_data.extend(data.to_owned());
+        }
+    }
+    // Winlogbeatのバージョンによってフィールドが存在しない場合があるので、先に見つかったフィールドの値を使う
+    let system_fields: [(&str, &[&str]); 5] = [
+        ("Channel", &["/winlog/channel"]),
+        ("EventID", &["/winlog/event_id", "/event/code"]),
+        (
+            "Hostname",
+            &["/winlog/computer_name", "/host/name", "/host/hostname"],
+        ),
+        ("SourceName", &["/winlog/provider_name", "/event/provider"]),
+        ("RecordNumber", &["/winlog/record_id"]),
+    ];
+    for (key, pointers) in system_fields {
+        let val = pointers
+            .iter()
+            .filter_map(|pointer| record.pointer(pointer))
+            .find(|v| !v.is_null());
+        let Some(val) = val else {
+            continue;
+        };
+        // Winlogbeat 8以降ではEventIDとRecordNumberが文字列なので、evtxと同じく数値に変換する
+        let val = match val.as_str().and_then(|s| s.parse::<i64>().ok()) {
+            Some(num) => Value::from(num),
+            None => val.to_owned(),
+        };
+        event_data.insert(key.to_string(), val);
+    }
+    if let Some(timestamp) = record["@timestamp"].as_str() {
+        // ナノ秒まで記録されている場合もあるので、JSON入力のタイムスタンプの形式に揃える
+        let timestamp = str_time_to_datetime(timestamp)
+            .map(|t| t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+            .unwrap_or_else(|| timestamp.to_string());
+        event_data.insert("@timestamp".to_string(), Value::from(timestamp));
+    }
+    Some(Value::Object(event_data))
+}
+
 pub fn read_csv(filename: &str) -> Result<Nested<Vec<String>>, String> {
     let f = File::open(filename);
     if f.is_err() {
@@ -725,6 +811,7 @@ pub fn remove_sp_char(record_value: CompactString) -> CompactString {
 
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
     use std::path::Path;
 
     use crate::detections::field_data_map::FieldDataMapKey;
@@ -745,7 +832,7 @@ mod tests {
     use regex::Regex;
     use serde_json::Value;
 
-    use super::{output_duration, output_profile_name};
+    use super::{is_stdin_path, output_duration, output_profile_name};
 
     #[test]
     fn test_create_recordinfos() {
@@ -991,6 +1078,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_read_jsonl_from_reader() {
+        // 標準入力と同様に、1行ずつ読み込んだJSONLのレコードを変換できること
+        let input = "{\"EventID\": 4624}\n\n{\"EventID\": 4625}\n";
+        let records: Vec<Value> = utils::read_jsonl_from_reader(Cursor::new(input))
+            .unwrap()
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["Event"]["EventData"]["EventID"], 4624);
+        assert_eq!(records[1]["Event"]["EventData"]["EventID"], 4625);
+
+        // 空の入力はレコードなしとして扱うこと
+        let records = utils::read_jsonl_from_reader(Cursor::new("")).unwrap();
+        assert_eq!(records.count(), 0);
+
+        // JSONL形式ではない入力はErr
+        let r = utils::read_jsonl_from_reader(Cursor::new("<Events>\n</Events>\n"));
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn test_convert_winlogbeat_json() {
+        // Winlogbeat形式ではないレコードは変換しない
+        let record: Value =
+            serde_json::from_str(r#"{"Channel": "Security", "EventID": 4688}"#).unwrap();
+        assert!(utils::convert_winlogbeat_json(&record).is_none());
+
+        let record_str = r#"
+        {
+            "_index": "winlogbeat-8.13.0",
+            "_source": {
+                "@timestamp": "2024-04-01T12:34:56.123456789Z",
+                "event": {"code": "4688", "provider": "Microsoft-Windows-Security-Auditing"},
+                "host": {"name": "host.example.com"},
+                "winlog": {
+                    "channel": "Security",
+                    "computer_name": "WIN-HOST",
+                    "record_id": "1234",
+                    "event_data": {"NewProcessName": "C:\\Windows\\System32\\cmd.exe", "CommandLine": "cmd.exe /c whoami"}
+                }
+            }
+        }"#;
+        let record: Value = serde_json::from_str(record_str).unwrap();
+        let converted = utils::convert_winlogbeat_json(&record).unwrap();
+        assert_eq!(converted["Channel"], "Security");
+        assert_eq!(converted["EventID"], 4688);
+        assert_eq!(converted["Hostname"], "WIN-HOST");
+        assert_eq!(
+            converted["SourceName"],
+            "Microsoft-Windows-Security-Auditing"
+        );
+        assert_eq!(converted["RecordNumber"], 1234);
+        assert_eq!(converted["@timestamp"], "2024-04-01T12:34:56.123Z");
+        assert_eq!(converted["CommandLine"], "cmd.exe /c whoami");
+    }
+
     #[test]
     fn test_jq_c_file_to_serde_json_value() {
         // 存在しないパスはErr
@@ -1024,6 +1167,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: Some("super-verbose".to_string()),
                     enable_deprecated_rules: false,
@@ -1063,6 +1207,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -1076,6 +1221,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -1174,4 +1325,12 @@ mod tests {
         let ms = duration.num_milliseconds() - 1000 * s;
         assert_eq!(output_duration((s, ms)), "25:11:03.322".to_string());
     }
+
+    #[test]
+    /// -f -で標準入力を指定した場合のパスの判定のテスト
+    fn test_is_stdin_path() {
+        assert!(is_stdin_path(Path::new("-")));
+        assert!(!is_stdin_path(Path::new("./-")));
+        assert!(!is_stdin_path(Path::new("test_files/evtx/test.json")));
+    }
 }
@@ -1,9 +1,12 @@
 use crate::detections::configs::{self, StoredStatic};
 use crate::detections::message::{AlertMessage, ERROR_LOG_STACK};
+use crate::yaml::ParseYaml;
 use hashbrown::HashMap;
 use regex::Regex;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
+use std::path::Path;
+use yaml_rust::{Yaml, YamlLoader};
 
 #[derive(Debug)]
 pub struct DataFilterRule {
@@ -79,3 +82,138 @@ impl RuleExclude {
         }
     }
 }
+
+/// Sigmaのフィルタールール。rulesに指定されたルールに、filterの条件をandで追加して検知を除外する
+#[derive(Clone, Debug)]
+pub struct FilterRule {
+    pub filepath: String,
+    pub rule_refs: Vec<String>,
+    pub yaml: Yaml,
+}
+
+impl FilterRule {
+    /// ルールのidまたはnameがフィルタールールのrulesに含まれていればtrueを返す
+    pub fn is_target(&self, rule_yaml: &Yaml) -> bool {
+        ["id", "name"].iter().any(|key| {
+            rule_yaml[*key]
+                .as_str()
+                .is_some_and(|v| self.rule_refs.iter().any(|rule_ref| rule_ref == v))
+        })
+    }
+}
+
+/// 指定されたディレクトリまたはファイルからSigmaのフィルタールールを読み込む
+pub fn load_filter_rules(path: &Path, stored_static: &StoredStatic) -> Vec<FilterRule> {
+    let mut ret = vec![];
+    let push_err = |errmsg: String| {
+        if stored_static.verbose_flag {
+            AlertMessage::warn(&errmsg).ok();
+        }
+        if !stored_static.quiet_errors_flag {
+            ERROR_LOG_STACK
+                .lock()
+                .unwrap()
+                .push(format!("[WARN] {errmsg}"));
+        }
+    };
+    if path.is_dir() {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                push_err(format!(
+                    "Failed to open filter rule dir. [path:{}] {e}",
+                    path.display()
+                ));
+                return ret;
+            }
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            ret.extend(load_filter_rules(&entry.path(), stored_static));
+        }
+        return ret;
+    }
+    if !path.is_file() {
+        push_err(format!(
+            "Filter rule path does not exist. [path:{}]",
+            path.display()
+        ));
+        return ret;
+    }
+    let ext = path.extension().unwrap_or_default();
+    if ext != "yml" && ext != "yaml" {
+        return ret;
+    }
+
+    let filepath = path.display().to_string();
+    let yaml_docs = match ParseYaml::read_file(path.to_path_buf())
+        .and_then(|content| YamlLoader::load_from_str(&content).map_err(|e| e.to_string()))
+    {
+        Ok(yaml_docs) => yaml_docs,
+        Err(e) => {
+            push_err(format!(
+                "Failed to parse filter rule. [path:{filepath}] {e}"
+            ));
+            return ret;
+        }
+    };
+    for yaml in yaml_docs {
+        let rules = &yaml["filter"]["rules"];
+        let rule_refs: Vec<String> = match rules.as_str() {
+            Some(rule_ref) => vec![rule_ref.to_string()],
+            None => rules
+                .as_vec()
+                .unwrap_or(&vec![])
+                .iter()
+                .filter_map(|x| x.as_str().map(|s| s.to_string()))
+                .collect(),
+        };
+        if rule_refs.is_empty() {
+            push_err(format!(
+                "The filter rule does not have filter.rules. [path:{filepath}]"
+            ));
+            continue;
+        }
+        if stored_static.verbose_flag {
+            println!("Loaded filter rule: {filepath}");
+        }
+        ret.push(FilterRule {
+            filepath: filepath.clone(),
+            rule_refs,
+            yaml,
+        });
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::rule::tests::create_dummy_stored_static;
+    use crate::filter::load_filter_rules;
+    use std::path::Path;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn test_load_filter_rules() {
+        let filter_rules = load_filter_rules(
+            Path::new("test_files/rules/filter"),
+            &create_dummy_stored_static(),
+        );
+        assert_eq!(filter_rules.len(), 1);
+        assert_eq!(
+            filter_rules[0].rule_refs,
+            vec!["0c5ae6b2-6bde-4f4d-8d9b-3fc7b1c4ddf1".to_string()]
+        );
+
+        let rule_yaml = YamlLoader::load_from_str(
+            "title: test\nid: 0c5ae6b2-6bde-4f4d-8d9b-3fc7b1c4ddf1\nname: failed_logon",
+        )
+        .unwrap()
+        .remove(0);
+        assert!(filter_rules[0].is_target(&rule_yaml));
+        let rule_yaml =
+            YamlLoader::load_from_str("title: test\nid: 00000000-0000-0000-0000-000000000000")
+                .unwrap()
+                .remove(0);
+        assert!(!filter_rules[0].is_target(&rule_yaml));
+    }
+}
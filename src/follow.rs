@@ -0,0 +1,219 @@
+use crate::archive::{self, ReadSeek};
+use crate::detections::message::ERROR_LOG_STACK;
+use evtx::SerializedEvtxRecord;
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// --followで監視しているディレクトリに新しいファイルや追記されたレコードがないかを確認する間隔
+pub const FOLLOW_INTERVAL_SECS: u64 = 5;
+
+lazy_static! {
+    // --followで監視しているファイルごとの、前回確認した時点の状態と読み込み済みの位置
+    static ref FOLLOW_POSITIONS: Mutex<HashMap<PathBuf, FollowPosition>> =
+        Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Default, Clone)]
+struct FollowPosition {
+    /// 前回確認した時点のファイルサイズ
+    len: u64,
+    /// 前回確認した時点のファイルの更新日時
+    modified: Option<SystemTime>,
+    /// JSONLファイルの読み込み済みのバイト数
+    offset: u64,
+    /// evtxファイルの読み込み済みのレコードの最大のEventRecordID。未読み込みの場合はNone
+    last_record_id: Option<u64>,
+}
+
+/// 前回確認した時点から新しく追加された、またはサイズか更新日時が変わったファイルを返す。
+/// ファイルサイズが小さくなった場合は、ローテーション等で置き換えられたものとして先頭から読み込み直す
+pub fn updated_files(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut positions = FOLLOW_POSITIONS.lock().unwrap();
+    files
+        .into_iter()
+        .filter(|path| {
+            // アーカイブ内のファイルの場合は、アーカイブのファイルの状態で判定する
+            let target_path = match archive::split_member_path(path) {
+                Some((archive_path, _)) => archive_path,
+                None => path.to_path_buf(),
+            };
+            let Ok(metadata) = target_path.metadata() else {
+                return false;
+            };
+            let (len, modified) = (metadata.len(), metadata.modified().ok());
+            let is_new_file = !positions.contains_key(path);
+            let position = positions.entry(path.to_path_buf()).or_default();
+            if !is_new_file && position.len == len && position.modified == modified {
+                return false;
+            }
+            if len < position.len {
+                *position = FollowPosition::default();
+            }
+            position.len = len;
+            position.modified = modified;
+            true
+        })
+        .collect()
+}
+
+/// JSONLファイルの前回読み込んだ位置以降に追記された行を読み込む。
+/// 書き込み途中で改行で終わっていない最後の行は、次回の確認時に読み込む
+pub fn read_appended_jsonl(path: &Path) -> Result<Box<dyn Iterator<Item = Value>>, String> {
+    let offset = FOLLOW_POSITIONS
+        .lock()
+        .unwrap()
+        .get(path)
+        .map(|position| position.offset)
+        .unwrap_or_default();
+    let mut reader = match archive::open_file(path) {
+        Ok(f) => BufReader::new(f),
+        Err(_) => return Err(format!("Cannot open file. [file:{}]", path.display())),
+    };
+    if let Err(e) = reader.seek(SeekFrom::Start(offset)) {
+        return Err(format!("Cannot seek file. [file:{}] {e}", path.display()));
+    }
+    Ok(Box::new(AppendedLines {
+        reader,
+        path: path.to_path_buf(),
+        offset,
+    }))
+}
+
+/// 追記された行をJSON入力のレコードに変換するイテレータ。読み込んだ位置はdrop時に保存する
+struct AppendedLines {
+    reader: BufReader<Box<dyn ReadSeek + Send>>,
+    path: PathBuf,
+    offset: u64,
+}
+
+impl Iterator for AppendedLines {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = vec![];
+        loop {
+            line.clear();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(n) if n > 0 && line.ends_with(b"\n") => self.offset += n as u64,
+                _ => return None,
+            }
+            let line_str = String::from_utf8_lossy(&line);
+            if line_str.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Value>(&line_str) {
+                Ok(v) => return Some(json!({"Event":{"EventData": v}})),
+                Err(e) => {
+                    ERROR_LOG_STACK.lock().unwrap().push(format!(
+                        "[ERROR] Failed to parse JSONL line. [file:{}] {e}",
+                        self.path.display()
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for AppendedLines {
+    fn drop(&mut self) {
+        let mut positions = FOLLOW_POSITIONS.lock().unwrap();
+        positions.entry(self.path.clone()).or_default().offset = self.offset;
+    }
+}
+
+/// evtxファイルのレコードのうち、前回読み込んだレコードよりもEventRecordIDが大きいレコードのみを返す。
+/// パースエラーは初回の読み込み時のみ返す
+pub fn new_evtx_records<'a>(
+    path: &Path,
+    records: Box<dyn Iterator<Item = Result<SerializedEvtxRecord<Value>, String>> + 'a>,
+) -> Box<dyn Iterator<Item = Result<SerializedEvtxRecord<Value>, String>> + 'a> {
+    let last_record_id = FOLLOW_POSITIONS
+        .lock()
+        .unwrap()
+        .get(path)
+        .and_then(|position| position.last_record_id);
+    Box::new(NewEvtxRecords {
+        records,
+        path: path.to_path_buf(),
+        last_record_id,
+        max_record_id: last_record_id,
+    })
+}
+
+/// 新しいevtxのレコードのみを返すイテレータ。読み込んだ最大のEventRecordIDはdrop時に保存する
+struct NewEvtxRecords<'a> {
+    records: Box<dyn Iterator<Item = Result<SerializedEvtxRecord<Value>, String>> + 'a>,
+    path: PathBuf,
+    last_record_id: Option<u64>,
+    max_record_id: Option<u64>,
+}
+
+impl Iterator for NewEvtxRecords<'_> {
+    type Item = Result<SerializedEvtxRecord<Value>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for record in self.records.by_ref() {
+            match &record {
+                Ok(rec) => {
+                    if self
+                        .last_record_id
+                        .is_some_and(|id| rec.event_record_id <= id)
+                    {
+                        continue;
+                    }
+                    self.max_record_id = self.max_record_id.max(Some(rec.event_record_id));
+                    return Some(record);
+                }
+                Err(_) if self.last_record_id.is_none() => return Some(record),
+                Err(_) => continue,
+            }
+        }
+        None
+    }
+}
+
+impl Drop for NewEvtxRecords<'_> {
+    fn drop(&mut self) {
+        let mut positions = FOLLOW_POSITIONS.lock().unwrap();
+        let position = positions.entry(self.path.clone()).or_default();
+        // 空のファイルでも読み込み済みとして扱うため、レコードがない場合は0とする
+        position.last_record_id = self.max_record_id.or(Some(0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::follow::{read_appended_jsonl, updated_files};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    #[test]
+    fn test_read_appended_jsonl() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.jsonl");
+        fs::write(&path, "{\"EventID\": 1}\n{\"EventID\": 2}\n{\"EventID\"").unwrap();
+
+        assert_eq!(updated_files(vec![path.clone()]).len(), 1);
+        let values: Vec<_> = read_appended_jsonl(&path).unwrap().collect();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[1]["Event"]["EventData"]["EventID"], 2);
+
+        // 書き込み途中だった行の残りと新しい行を追記した場合は、追記された行のみを読み込む
+        let mut f = OpenOptions::new().append(true).open(&path).unwrap();
+        f.write_all(b": 3}\n{\"EventID\": 4}\n").unwrap();
+        drop(f);
+        assert_eq!(updated_files(vec![path.clone()]).len(), 1);
+        let values: Vec<_> = read_appended_jsonl(&path).unwrap().collect();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0]["Event"]["EventData"]["EventID"], 3);
+        assert_eq!(values[1]["Event"]["EventData"]["EventID"], 4);
+
+        // 変更がない場合は対象のファイルとならない
+        assert!(updated_files(vec![path.clone()]).is_empty());
+    }
+}
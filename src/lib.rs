@@ -1,10 +1,13 @@
 pub mod afterfact;
+pub mod archive;
 pub mod debug;
 pub mod detections;
 pub mod filter;
+pub mod follow;
 pub mod notify;
 pub mod options;
 pub mod timeline;
+pub mod xml_event;
 pub mod yaml;
 #[macro_use]
 extern crate horrorshow;
@@ -11,17 +11,22 @@ use compact_str::CompactString;
 use console::{style, Style};
 use dialoguer::Confirm;
 use dialoguer::{theme::ColorfulTheme, Select};
-use evtx::{EvtxParser, ParserSettings, RecordAllocation};
+use evtx::{EvtxParser, ParserSettings, RecordAllocation, SerializedEvtxRecord};
 use hashbrown::{HashMap, HashSet};
 use hayabusa::afterfact::{self, AfterfactInfo, AfterfactWriter};
+use hayabusa::archive::{self, ReadSeek};
 use hayabusa::debug::checkpoint_process_timer::CHECKPOINT;
+use hayabusa::debug::rule_profiler::{self, RULE_PROFILER};
 use hayabusa::detections::configs::{
     load_pivot_keywords, Action, ConfigReader, EventKeyAliasConfig, StoredStatic, TargetEventTime,
     TargetIds, CURRENT_EXE_PATH, STORED_EKEY_ALIAS, STORED_STATIC,
 };
 use hayabusa::detections::detection::{self, EvtxRecordInfo};
 use hayabusa::detections::message::{AlertMessage, DetectInfo, ERROR_LOG_STACK};
-use hayabusa::detections::rule::{get_detection_keys, RuleNode};
+use hayabusa::detections::rule::{
+    get_detection_keys, set_regex_limits, RuleNode, DEFAULT_REGEX_DFA_SIZE_LIMIT,
+    DEFAULT_REGEX_SIZE_LIMIT,
+};
 use hayabusa::detections::utils;
 use hayabusa::detections::utils::{
     check_setting_path, get_writable_color, output_and_data_stack_for_html, output_profile_name,
@@ -30,10 +35,14 @@ use hayabusa::options::htmlreport::{self, HTML_REPORTER};
 use hayabusa::options::pivot::create_output;
 use hayabusa::options::pivot::PIVOT_KEYWORD;
 use hayabusa::options::profile::set_default_profile;
-use hayabusa::options::{level_tuning::LevelTuning, update::Update};
+use hayabusa::options::{
+    level_tuning::LevelTuning, test_rules::RuleTester, update::Update,
+    validate_rules::RuleValidator,
+};
 use hayabusa::timeline::computer_metrics::countup_event_by_computer;
+use hayabusa::xml_event;
 use hayabusa::{detections::configs, timeline::timelines::Timeline};
-use hayabusa::{detections::utils::write_color_buffer, filter};
+use hayabusa::{detections::utils::write_color_buffer, filter, follow};
 use hayabusa::{options, yaml};
 use indicatif::ProgressBar;
 use indicatif::{ProgressDrawTarget, ProgressStyle};
@@ -51,14 +60,10 @@ use std::io::{BufWriter, Write};
 use std::path::Path;
 use std::ptr::null_mut;
 use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
 use std::u128;
-use std::{
-    env,
-    fs::{self, File},
-    path::PathBuf,
-    vec,
-};
+use std::{env, fs, path::PathBuf, vec};
 use termcolor::{BufferWriter, Color, ColorChoice};
 use tokio::runtime::Runtime;
 use tokio::spawn;
@@ -176,6 +181,32 @@ impl App {
                     .unwrap();
         }
 
+        // validate-rulesとtest-rulesは解析開始時刻等を出力せずに結果のみを出力する
+        match &stored_static.config.action {
+            Some(Action::ValidateRules(option)) => {
+                let rules_path = self.get_target_rules_path(&option.rules);
+                let mut validator = RuleValidator::new(stored_static);
+                validator.validate_path(&rules_path);
+                validator.output(option.json_output, stored_static.common_options.no_color);
+                if validator.has_error() {
+                    std::process::exit(1);
+                }
+                return;
+            }
+            Some(Action::TestRules(option)) => {
+                let rules_path = self.get_target_rules_path(&option.rules);
+                *STORED_EKEY_ALIAS.write().unwrap() = Some(stored_static.eventkey_alias.clone());
+                let mut tester = RuleTester::new(stored_static);
+                tester.test_path(&rules_path);
+                tester.output(stored_static.common_options.no_color);
+                if tester.has_failure() {
+                    std::process::exit(1);
+                }
+                return;
+            }
+            _ => {}
+        }
+
         let time_filter = TargetEventTime::new(stored_static);
         if !time_filter.is_parse_success() {
             return;
@@ -227,6 +258,25 @@ impl App {
             .as_mut()
             .unwrap()
             .set_checkpoint(analysis_start_time);
+        let rule_profiling = stored_static
+            .output_option
+            .as_ref()
+            .and_then(|opt| opt.rule_profiling);
+        if rule_profiling.is_some() {
+            rule_profiler::enable();
+        }
+        if let Some(opt) = stored_static.output_option.as_ref() {
+            set_regex_limits(
+                opt.regex_size_limit.unwrap_or(DEFAULT_REGEX_SIZE_LIMIT),
+                opt.regex_dfa_size_limit
+                    .unwrap_or(DEFAULT_REGEX_DFA_SIZE_LIMIT),
+            );
+        }
+        archive::set_max_member_size(
+            stored_static
+                .max_archive_member_size
+                .unwrap_or(archive::DEFAULT_MAX_MEMBER_SIZE),
+        );
         let target_extensions = if stored_static.output_option.is_some() {
             configs::get_target_extensions(
                 stored_static
@@ -688,6 +738,8 @@ impl App {
                 }
                 return;
             }
+            // validate-rulesとtest-rulesは解析開始前に処理済み
+            Action::ValidateRules(_) | Action::TestRules(_) => return,
             Action::ListProfiles(_) => {
                 let profile_list = options::profile::get_profile_list("config/profiles.yaml");
                 write_color_buffer(
@@ -732,6 +784,29 @@ impl App {
             "General Overview {#general_overview}",
             &stored_static.html_report_flag,
         );
+
+        // ルール毎の判定時間の計測結果の出力
+        if let Some(top_n) = rule_profiling {
+            println!();
+            let profiler = RULE_PROFILER.lock().unwrap();
+            profiler.output_top(top_n);
+            if let Some(path) = &stored_static
+                .output_option
+                .as_ref()
+                .unwrap()
+                .rule_profiling_output
+            {
+                match profiler.write_csv(path) {
+                    Ok(_) => println!("Rule profiling results saved to: {}", path.display()),
+                    Err(e) => {
+                        AlertMessage::alert(&format!(
+                            "Failed to save the rule profiling results. {e}"
+                        ))
+                        .ok();
+                    }
+                }
+            }
+        }
         match stored_static.config.action {
             Some(Action::CsvTimeline(_)) | Some(Action::JsonTimeline(_)) => {
                 println!();
@@ -795,6 +870,12 @@ impl App {
                 target_extensions,
                 stored_static,
             );
+            // --followの場合は、ファイルがまだない場合もディレクトリの監視を続ける
+            if stored_static.follow_flag {
+                let evtx_files = follow::updated_files(evtx_files);
+                self.analysis_files(evtx_files, time_filter, stored_static.borrow_mut());
+                return;
+            }
             if evtx_files.is_empty() {
                 AlertMessage::alert("No .evtx files were found.").ok();
                 return;
@@ -817,6 +898,15 @@ impl App {
                     replaced_filepath.remove(replaced_filepath.len() - 1);
                 }
                 let check_path = Path::new(&replaced_filepath);
+                // -f -の場合は標準入力から読み込む(-Jを指定した場合はJSONL、それ以外はevtxのバイト列)
+                if utils::is_stdin_path(check_path) {
+                    self.analysis_files(
+                        vec![check_path.to_path_buf()],
+                        time_filter,
+                        stored_static.borrow_mut(),
+                    );
+                    return;
+                }
                 if !check_path.exists() {
                     AlertMessage::alert(&format!(
                         " The file {} does not exist. Please specify a valid file path.",
@@ -825,22 +915,34 @@ impl App {
                     .ok();
                     return;
                 }
-                if !target_extensions.contains(
+                if archive::is_archive(check_path) {
+                    let evtx_files =
+                        Self::collect_archive_files(check_path, target_extensions, stored_static);
+                    if evtx_files.is_empty() {
+                        AlertMessage::alert("No .evtx files were found.").ok();
+                        return;
+                    }
+                    self.analysis_files(evtx_files, time_filter, stored_static.borrow_mut());
+                    return;
+                }
+                // XML形式でエクスポートされたイベントログは拡張子で判定して読み込む
+                if !(target_extensions.contains(
                     check_path
                         .extension()
                         .unwrap_or_else(|| OsStr::new("."))
                         .to_str()
                         .unwrap(),
-                ) || check_path
-                    .file_stem()
-                    .unwrap_or_else(|| OsStr::new("."))
-                    .to_str()
-                    .unwrap()
-                    .trim()
-                    .starts_with('.')
+                ) || (!stored_static.json_input_flag && xml_event::is_xml_file(check_path)))
+                    || check_path
+                        .file_stem()
+                        .unwrap_or_else(|| OsStr::new("."))
+                        .to_str()
+                        .unwrap()
+                        .trim()
+                        .starts_with('.')
                 {
                     AlertMessage::alert(
-                        "--filepath only accepts .evtx files. Hidden files are ignored.",
+                        "--filepath only accepts .evtx and .xml files. Hidden files are ignored.",
                     )
                     .ok();
                     return;
@@ -936,6 +1038,12 @@ impl App {
                     ret.extend(subdir_ret);
                     Option::Some(())
                 });
+            } else if archive::is_archive(&path) {
+                ret.extend(Self::collect_archive_files(
+                    &path,
+                    target_extensions,
+                    stored_static,
+                ));
             } else if target_extensions.contains(
                 path.extension()
                     .unwrap_or_else(|| OsStr::new(""))
@@ -955,6 +1063,33 @@ impl App {
         ret
     }
 
+    /// zip, tar, tar.gzのアーカイブ内の解析対象のファイルのパス(archive.zip!/path/Security.evtx)を取得する
+    fn collect_archive_files(
+        archive_path: &Path,
+        target_extensions: &HashSet<String>,
+        stored_static: &StoredStatic,
+    ) -> Vec<PathBuf> {
+        match archive::collect_archive_members(archive_path, target_extensions) {
+            Ok(files) => files,
+            Err(e) => {
+                let errmsg = format!(
+                    "Failed to read the archive file. [file:{}] {e}",
+                    archive_path.display()
+                );
+                if stored_static.verbose_flag {
+                    AlertMessage::alert(&errmsg).ok();
+                }
+                if !stored_static.quiet_errors_flag {
+                    ERROR_LOG_STACK
+                        .lock()
+                        .unwrap()
+                        .push(format!("[ERROR] {errmsg}"));
+                }
+                vec![]
+            }
+        }
+    }
+
     fn print_contributors(&self) {
         match fs::read_to_string(
             utils::check_setting_path(&CURRENT_EXE_PATH.to_path_buf(), "contributors.txt", true)
@@ -1002,9 +1137,16 @@ impl App {
         )
         .ok();
         let mut total_file_size = ByteSize::b(0);
+        let mut counted_archives = HashSet::new();
         for file_path in &evtx_files {
-            let file_size = match fs::metadata(file_path) {
-                Ok(res) => res.len(),
+            // アーカイブ内のファイルの場合は、アーカイブのファイルサイズを1回だけ加算する
+            if let Some((archive_path, _)) = archive::split_member_path(file_path) {
+                if !counted_archives.insert(archive_path) {
+                    continue;
+                }
+            }
+            let file_size = match archive::file_size(file_path) {
+                Ok(res) => res,
                 Err(err) => {
                     if stored_static.verbose_flag {
                         AlertMessage::warn(&err.to_string()).ok();
@@ -1470,24 +1612,14 @@ impl App {
                 pb.set_message(pb_msg);
             }
 
-            let (detection_tmp, cnt_tmp, tl_tmp, recover_cnt_tmp, mut detect_infos) =
-                if evtx_file.extension().unwrap() == "json" {
-                    self.analysis_json_file(
-                        (evtx_file, time_filter, target_event_ids, stored_static),
-                        detection,
-                        tl.to_owned(),
-                        &mut afterfact_writer,
-                        &mut afterfact_info,
-                    )
-                } else {
-                    self.analysis_file(
-                        (evtx_file, time_filter, target_event_ids, stored_static),
-                        detection,
-                        tl.to_owned(),
-                        &mut afterfact_writer,
-                        &mut afterfact_info,
-                    )
-                };
+            let (detection_tmp, cnt_tmp, tl_tmp, recover_cnt_tmp, mut detect_infos) = self
+                .analysis_target_file(
+                    (evtx_file, time_filter, target_event_ids, stored_static),
+                    detection,
+                    tl.to_owned(),
+                    &mut afterfact_writer,
+                    &mut afterfact_info,
+                );
             detection = detection_tmp;
             tl = tl_tmp;
             afterfact_info.record_cnt += cnt_tmp as u128;
@@ -1497,6 +1629,16 @@ impl App {
                 pb.inc(1);
             }
         }
+        if stored_static.follow_flag {
+            pb.finish_and_clear();
+            self.follow_directory(
+                (time_filter, target_event_ids, stored_static),
+                detection,
+                tl,
+                &mut afterfact_writer,
+                &mut afterfact_info,
+            );
+        }
         pb.finish_with_message(
             "Scanning finished. Please wait while the results are being saved.\r\n",
         );
@@ -1571,6 +1713,110 @@ impl App {
             .set_checkpoint(Local::now());
     }
 
+    /// --followで-dのディレクトリを監視し、新しいファイルや追記されたレコードのみを検知して結果を随時出力する。
+    /// aggregation conditionの集計は監視を開始してからの全てのレコードを対象にする。Ctrl+Cで終了するまで戻らない
+    fn follow_directory(
+        &self,
+        (time_filter, target_event_ids, stored_static): (
+            &TargetEventTime,
+            &TargetIds,
+            &StoredStatic,
+        ),
+        mut detection: detection::Detection,
+        mut tl: Timeline,
+        afterfact_writer: &mut AfterfactWriter,
+        afterfact_info: &mut AfterfactInfo,
+    ) -> ! {
+        let output_option = stored_static.output_option.as_ref().unwrap();
+        let directory = output_option.input_args.directory.as_ref().unwrap();
+        let target_extensions = configs::get_target_extensions(
+            output_option.detect_common_options.evtx_file_ext.as_ref(),
+            stored_static.json_input_flag,
+        );
+        let empty_ids = HashSet::new();
+        println!(
+            "Watching {} for new records. Press Ctrl+C to stop.",
+            directory.display()
+        );
+        loop {
+            // 前回の出力以降に条件を満たしたaggregation conditionの検知結果を出力する
+            let log_records = detection.add_new_aggcondition_msges(stored_static);
+            afterfact::emit_csv(
+                &log_records,
+                &empty_ids,
+                stored_static,
+                afterfact_writer,
+                afterfact_info,
+            );
+            thread::sleep(Duration::from_secs(follow::FOLLOW_INTERVAL_SECS));
+
+            let evtx_files = follow::updated_files(Self::collect_evtxfiles(
+                directory.as_os_str().to_str().unwrap(),
+                &target_extensions,
+                stored_static,
+            ));
+            for evtx_file in evtx_files {
+                let (detection_tmp, cnt_tmp, tl_tmp, recover_cnt_tmp, _) = self
+                    .analysis_target_file(
+                        (evtx_file, time_filter, target_event_ids, stored_static),
+                        detection,
+                        tl,
+                        afterfact_writer,
+                        afterfact_info,
+                    );
+                detection = detection_tmp;
+                tl = tl_tmp;
+                afterfact_info.record_cnt += cnt_tmp as u128;
+                afterfact_info.recover_record_cnt += recover_cnt_tmp as u128;
+            }
+        }
+    }
+
+    // 拡張子に応じて、JSON形式またはWindowsイベントログ形式のファイルとして1ファイル分解析する。
+    fn analysis_target_file(
+        &self,
+        (evtx_file, time_filter, target_event_ids, stored_static): (
+            PathBuf,
+            &TargetEventTime,
+            &TargetIds,
+            &StoredStatic,
+        ),
+        detection: detection::Detection,
+        tl: Timeline,
+        afterfact_writer: &mut AfterfactWriter,
+        afterfact_info: &mut AfterfactInfo,
+    ) -> (
+        detection::Detection,
+        usize,
+        Timeline,
+        usize,
+        Vec<DetectInfo>,
+    ) {
+        // 標準入力の場合は拡張子がないため、-Jの指定有無でJSONLとevtxのどちらとして読み込むかを判定する
+        let is_json_input = if utils::is_stdin_path(&evtx_file) {
+            stored_static.json_input_flag
+        } else {
+            evtx_file.extension().unwrap() == "json"
+        };
+        if is_json_input {
+            self.analysis_json_file(
+                (evtx_file, time_filter, target_event_ids, stored_static),
+                detection,
+                tl,
+                afterfact_writer,
+                afterfact_info,
+            )
+        } else {
+            self.analysis_file(
+                (evtx_file, time_filter, target_event_ids, stored_static),
+                detection,
+                tl,
+                afterfact_writer,
+                afterfact_info,
+            )
+        }
+    }
+
     // Windowsイベントログファイルを1ファイル分解析する。
     fn analysis_file(
         &self,
@@ -1592,16 +1838,37 @@ impl App {
         Vec<DetectInfo>,
     ) {
         let path = evtx_filepath.display();
-        let parser = self.evtx_to_jsons(&evtx_filepath, stored_static.enable_recover_records);
         let mut record_cnt = 0;
         let mut recover_records_cnt = 0;
         let mut detect_infos: Vec<DetectInfo> = vec![];
-        if parser.is_none() {
-            return (detection, record_cnt, tl, 0, detect_infos);
-        }
+        let mut parser;
+        // XML形式でエクスポートされたイベントログは、evtxファイルと同じ形式のレコードに変換して読み込む
+        let mut records: Box<
+            dyn Iterator<Item = Result<SerializedEvtxRecord<Value>, String>> + '_,
+        > = if xml_event::is_xml_file(&evtx_filepath) {
+            match xml_event::read_xml_records(&evtx_filepath) {
+                Ok(records) => Box::new(records),
+                Err(e) => {
+                    AlertMessage::alert(&e).ok();
+                    return (detection, record_cnt, tl, 0, detect_infos);
+                }
+            }
+        } else {
+            parser = self.evtx_to_jsons(&evtx_filepath, stored_static.enable_recover_records);
+            match parser.as_mut() {
+                Some(parser) => Box::new(
+                    parser
+                        .records_json_value()
+                        .map(|record| record.map_err(|e| e.to_string())),
+                ),
+                None => return (detection, record_cnt, tl, 0, detect_infos),
+            }
+        };
 
-        let mut parser = parser.unwrap();
-        let mut records = parser.records_json_value();
+        // --followの場合は、前回読み込んだレコード以降に追加されたレコードのみを対象にする
+        if stored_static.follow_flag {
+            records = follow::new_evtx_records(&evtx_filepath, records);
+        }
 
         let verbose_flag = stored_static.verbose_flag;
         let quiet_errors_flag = stored_static.quiet_errors_flag;
@@ -1767,11 +2034,21 @@ impl App {
         } else {
             filename.to_string()
         };
-        let jsonl_value_iter = utils::read_jsonl_to_value(&filepath);
+        // --followの場合は、前回読み込んだ位置以降に追記された行のみを対象にする
+        let jsonl_value_iter = if stored_static.follow_flag {
+            follow::read_appended_jsonl(Path::new(&filepath))
+        } else {
+            utils::read_jsonl_to_value(&filepath)
+        };
         let mut detect_infos: Vec<DetectInfo> = vec![];
         let mut records = match jsonl_value_iter {
             // JSONL形式の場合
             Ok(values) => values,
+            // 標準入力は先頭行を読み込み済みのため、--followは追記された行を読み込むため、JSONL形式のみ対応する
+            Err(e) if stored_static.follow_flag || utils::is_stdin_path(Path::new(&filepath)) => {
+                AlertMessage::alert(&e).ok();
+                return (detection, record_cnt, tl, recover_records_cnt, detect_infos);
+            }
             // JSONL形式以外(JSON(Array or jq)形式)の場合
             Err(_) => {
                 let json_value_iter = utils::read_json_to_value(&filepath);
@@ -1795,6 +2072,12 @@ impl App {
                 }
                 record_cnt += 1;
                 let mut data = next_rec.unwrap();
+                // Winlogbeat(Elastic Common Schema)形式の場合は、winlog.event_data等のフィールドをEventData直下に展開する
+                if let Some(event_data) =
+                    utils::convert_winlogbeat_json(&data["Event"]["EventData"])
+                {
+                    data["Event"]["EventData"] = event_data;
+                }
                 let is_splunk_json = data["Event"]["EventData"]["result"].is_object();
                 // ChannelなどのデータはEvent -> Systemに存在する必要があるが、他処理のことも考え、Event -> EventDataのデータをそのまま投入する形にした。cloneを利用しているのはCopy trait実装がserde_json::Valueにないため
 
@@ -2070,10 +2353,18 @@ impl App {
 
     fn evtx_to_jsons(
         &self,
-        evtx_filepath: &PathBuf,
+        evtx_filepath: &Path,
         enable_recover_records: bool,
-    ) -> Option<EvtxParser<File>> {
-        match EvtxParser::from_path(evtx_filepath) {
+    ) -> Option<EvtxParser<Box<dyn ReadSeek + Send>>> {
+        // zip等のアーカイブ内のファイル(archive.zip!/path/Security.evtx)はメモリ上に展開して読み込む
+        let reader = match archive::open_file(evtx_filepath) {
+            Ok(reader) => reader,
+            Err(e) => {
+                eprintln!("{e}");
+                return Option::None;
+            }
+        };
+        match EvtxParser::from_read_seek(reader) {
             Ok(evtx_parser) => {
                 // parserのデフォルト設定を変更
                 let mut parse_config =
@@ -2154,6 +2445,25 @@ impl App {
         true
     }
 
+    /// validate-rules、test-rulesの対象のルールのパスを返す。存在しない場合はエラーを出力して終了する
+    fn get_target_rules_path(&self, rules: &Path) -> PathBuf {
+        // カレントディレクトリ以外からの実行の際にrulesオプションの指定がないとエラーが発生することを防ぐための処理
+        let rules_path = if rules == Path::new("./rules") {
+            utils::check_setting_path(&CURRENT_EXE_PATH.to_path_buf(), "rules", true).unwrap()
+        } else {
+            rules.to_path_buf()
+        };
+        if !rules_path.exists() {
+            AlertMessage::alert(&format!(
+                "The rule directory or file does not exist. [{}]",
+                rules_path.display()
+            ))
+            .ok();
+            std::process::exit(1);
+        }
+        rules_path
+    }
+
     fn check_is_valid_args_num(&self, action: Option<&Action>) -> bool {
         match action.as_ref().unwrap() {
             Action::CsvTimeline(_)
@@ -2207,6 +2517,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -2246,6 +2557,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -2259,6 +2571,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -2287,6 +2605,20 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_collect_evtxfiles_in_archive() {
+        let files = App::collect_evtxfiles(
+            "test_files/archive",
+            &HashSet::from(["jsonl".to_string()]),
+            &create_dummy_stored_static(),
+        );
+        assert_eq!(3, files.len());
+        assert!(files
+            .contains(&Path::new("test_files/archive/logs.zip!/logs/test.jsonl").to_path_buf()));
+        assert!(files
+            .contains(&Path::new("test_files/archive/logs.tar.gz!/logs/test.jsonl").to_path_buf()));
+    }
+
     #[test]
     fn test_exec_none_storedstatic() {
         let mut app = App::new(None);
@@ -2366,6 +2698,53 @@ mod tests {
         //assert_eq!(MESSAGES.len(), 2);
     }
 
+    #[test]
+    fn test_analysis_xml_file() {
+        let mut app = App::new(None);
+        let mut stored_static = create_dummy_stored_static();
+        stored_static.json_input_flag = false;
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(stored_static.eventkey_alias.clone());
+        *STORED_STATIC.write().unwrap() = Some(stored_static.clone());
+
+        let rule_str = r#"
+        enabled: true
+        detection:
+            selection1:
+                Channel: 'Microsoft-Windows-Sysmon/Operational'
+                CommandLine|contains: 'whoami'
+            condition: selection1
+        details: testdata
+        "#;
+        let mut rule_yaml = YamlLoader::load_from_str(rule_str).unwrap().into_iter();
+        let test_yaml_data = rule_yaml.next().unwrap();
+        let mut rule = create_rule("testpath".to_string(), test_yaml_data);
+        let rule_init = rule.init(&stored_static);
+        assert!(rule_init.is_ok());
+        let rule_files = vec![rule];
+        app.rule_keys = app.get_all_keys(&rule_files);
+        let detection = detection::Detection::new(rule_files);
+        let target_time_filter = TargetEventTime::new(&stored_static);
+        let tl = Timeline::default();
+        let target_event_ids = TargetIds::default();
+        let mut afterfact_info = AfterfactInfo::default();
+        let mut afterfact_writer = afterfact::init_writer(&stored_static);
+
+        let actual = app.analysis_file(
+            (
+                Path::new("test_files/evtx/test.xml").to_path_buf(),
+                &target_time_filter,
+                &target_event_ids,
+                &stored_static,
+            ),
+            detection,
+            tl,
+            &mut afterfact_writer,
+            &mut afterfact_info,
+        );
+        assert_eq!(actual.1, 2);
+        assert_eq!(actual.4.len(), 1);
+    }
+
     #[test]
     fn test_same_file_output_csv_exit() {
         // 先に空ファイルを作成する
@@ -2379,6 +2758,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -2418,6 +2798,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -2431,6 +2812,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("overwrite.csv").to_path_buf()),
@@ -2465,6 +2852,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -2504,6 +2892,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: true,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -2517,6 +2906,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("overwrite.csv").to_path_buf()),
@@ -2550,6 +2945,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -2589,6 +2985,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -2602,6 +2999,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("overwrite.json").to_path_buf()),
@@ -2636,6 +3039,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -2675,6 +3079,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: true,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -2688,6 +3093,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: Some(Path::new("overwrite.json").to_path_buf()),
@@ -2721,6 +3132,7 @@ mod tests {
                 live_analysis: false,
                 recover_records: false,
                 timeline_offset: None,
+                max_archive_member_size: None,
             },
             common_options: CommonOptions {
                 no_color: false,
@@ -2776,6 +3188,7 @@ mod tests {
                 live_analysis: false,
                 recover_records: false,
                 timeline_offset: None,
+                max_archive_member_size: None,
             },
             common_options: CommonOptions {
                 no_color: false,
@@ -2828,6 +3241,7 @@ mod tests {
                 filepath: Some(Path::new("test_files/evtx/test_metrics.json").to_path_buf()),
                 live_analysis: false,
                 timeline_offset: None,
+                max_archive_member_size: None,
                 recover_records: false,
             },
             common_options: CommonOptions {
@@ -2885,6 +3299,7 @@ mod tests {
                 live_analysis: false,
                 recover_records: false,
                 timeline_offset: None,
+                max_archive_member_size: None,
             },
             common_options: CommonOptions {
                 no_color: false,
@@ -2940,6 +3355,7 @@ mod tests {
                 live_analysis: false,
                 recover_records: false,
                 timeline_offset: None,
+                max_archive_member_size: None,
             },
             common_options: CommonOptions {
                 no_color: false,
@@ -2983,6 +3399,7 @@ mod tests {
                 live_analysis: false,
                 recover_records: false,
                 timeline_offset: None,
+                max_archive_member_size: None,
             },
             common_options: CommonOptions {
                 no_color: false,
@@ -251,6 +251,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -290,6 +291,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -303,6 +305,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: None,
@@ -319,6 +327,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -358,6 +367,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -371,6 +381,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             geo_ip: None,
             output: None,
@@ -390,6 +406,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -429,6 +446,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -442,6 +460,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             jsonl_timeline: false,
             geo_ip: None,
@@ -458,6 +482,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 profile: None,
                 enable_deprecated_rules: false,
@@ -497,6 +522,7 @@ mod tests {
                 enable_unsupported_rules: false,
                 clobber: false,
                 proven_rules: false,
+                filter_rules: None,
                 include_tag: None,
                 exclude_tag: None,
                 include_category: None,
@@ -510,6 +536,12 @@ mod tests {
                 no_wizard: true,
                 include_status: None,
                 low_memory_mode: false,
+                rule_profiling: None,
+                rule_profiling_output: None,
+                regex_size_limit: None,
+                regex_dfa_size_limit: None,
+                follow: false,
+                rule_cache: false,
             },
             jsonl_timeline: false,
             geo_ip: None,
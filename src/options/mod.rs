@@ -3,4 +3,6 @@ pub mod htmlreport;
 pub mod level_tuning;
 pub mod pivot;
 pub mod profile;
+pub mod test_rules;
 pub mod update;
+pub mod validate_rules;
@@ -3,9 +3,9 @@ use crate::detections::message::AlertMessage;
 use crate::detections::utils::check_setting_path;
 use crate::options::profile::Profile::{
     AllFieldInfo, Channel, Computer, Details, EventID, EvtxFile, ExtraFieldInfo, Level, Literal,
-    MitreTactics, MitreTags, OtherTags, Provider, RecordID, RecoveredRecord, RenderedMessage,
-    RuleAuthor, RuleCreationDate, RuleFile, RuleID, RuleModifiedDate, RuleTitle, SrcASN, SrcCity,
-    SrcCountry, Status, TgtASN, TgtCity, TgtCountry, Timestamp,
+    MatchReason, MitreTactics, MitreTags, OtherTags, Provider, RecordID, RecoveredRecord,
+    RenderedMessage, RuleAuthor, RuleCreationDate, RuleFile, RuleID, RuleModifiedDate, RuleTitle,
+    SrcASN, SrcCity, SrcCountry, Status, TgtASN, TgtCity, TgtCountry, Timestamp,
 };
 use crate::yaml;
 use compact_str::CompactString;
@@ -48,6 +48,7 @@ pub enum Profile {
     TgtCity(Cow<'static, str>),
     ExtraFieldInfo(Cow<'static, str>),
     RecoveredRecord(Cow<'static, str>),
+    MatchReason(Cow<'static, str>),
     Literal(Cow<'static, str>), // profiles.yamlの固定文字列を変換なしでそのまま出力する場合
 }
 
@@ -59,9 +60,8 @@ impl Profile {
             | MitreTags(v) | OtherTags(v) | RuleAuthor(v) | RuleCreationDate(v)
             | RuleModifiedDate(v) | Status(v) | RuleID(v) | Provider(v) | Details(v)
             | RenderedMessage(v) | SrcASN(v) | SrcCountry(v) | SrcCity(v) | TgtASN(v)
-            | TgtCountry(v) | TgtCity(v) | RecoveredRecord(v) | ExtraFieldInfo(v) | Literal(v) => {
-                v.to_string()
-            }
+            | TgtCountry(v) | TgtCity(v) | RecoveredRecord(v) | ExtraFieldInfo(v)
+            | MatchReason(v) | Literal(v) => v.to_string(),
         }
     }
 
@@ -94,6 +94,7 @@ impl Profile {
             TgtCity(_) => TgtCity(converted_string.to_owned().into()),
             ExtraFieldInfo(_) => ExtraFieldInfo(converted_string.to_owned().into()),
             RecoveredRecord(_) => RecoveredRecord(converted_string.to_owned().into()),
+            MatchReason(_) => MatchReason(converted_string.to_owned().into()),
             Details(_) => Details(converted_string.to_owned().into()),
             AllFieldInfo(_) => AllFieldInfo(converted_string.to_owned().into()),
             p => p.to_owned(),
@@ -127,6 +128,7 @@ impl From<&str> for Profile {
             "%RenderedMessage%" => RenderedMessage(Default::default()),
             "%ExtraFieldInfo%" => ExtraFieldInfo(Default::default()),
             "%RecoveredRecord%" => RecoveredRecord(Default::default()),
+            "%MatchReason%" => MatchReason(Default::default()),
             s => Literal(s.to_string().into()), // profiles.yamlの固定文字列を変換なしでそのまま出力する場合
         }
     }
@@ -486,6 +488,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -525,6 +528,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -538,6 +542,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -565,6 +575,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: Some("minimal".to_string()),
                     enable_deprecated_rules: false,
@@ -604,6 +615,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -617,6 +629,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -674,6 +692,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: Some("not_exist".to_string()),
                     enable_deprecated_rules: false,
@@ -713,6 +732,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -726,6 +746,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -0,0 +1,372 @@
+use crate::detections::configs::StoredStatic;
+use crate::detections::rule::{self, get_detection_keys, RuleNode};
+use crate::detections::utils::{self, get_writable_color, write_color_buffer};
+use crate::options::validate_rules::get_rule_files;
+use crate::yaml::ParseYaml;
+use serde_json::{Map, Value};
+use std::path::Path;
+use termcolor::{BufferWriter, Color, ColorChoice};
+use yaml_rust::{Yaml, YamlLoader};
+
+/// 1つのルールのテスト結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleTestResult {
+    pub filepath: String,
+    pub title: String,
+    /// 失敗したテストの内容。空の場合はテストに合格したことを表す
+    pub failures: Vec<String>,
+}
+
+/// test-rulesコマンドで、ルールのtestsに記載されたサンプルイベントを使ってルールの検知結果を確認する構造体
+///
+/// サンプルイベントは`positive`(検知されるべきイベント)と`negative`(検知されるべきでないイベント)に分けて、
+/// hayabusaのJSON形式(`Event.System`、`Event.EventData`)のマッピングまたはJSON文字列で記載する。
+/// aggregation conditionを持つルールの場合は、`positive`と`negative`のそれぞれのイベントをまとめて判定する。
+pub struct RuleTester<'a> {
+    stored_static: &'a StoredStatic,
+    pub results: Vec<RuleTestResult>,
+}
+
+impl<'a> RuleTester<'a> {
+    pub fn new(stored_static: &'a StoredStatic) -> RuleTester<'a> {
+        RuleTester {
+            stored_static,
+            results: vec![],
+        }
+    }
+
+    /// 指定されたディレクトリ配下(またはファイル)のルールのテストを実行する
+    pub fn test_path(&mut self, path: &Path) {
+        for rule_file in get_rule_files(path) {
+            let filepath = rule_file.display().to_string();
+            match ParseYaml::read_file(rule_file) {
+                Ok(content) => self.test_file(&filepath, &content),
+                Err(e) => self.results.push(RuleTestResult {
+                    filepath,
+                    title: String::default(),
+                    failures: vec![e],
+                }),
+            }
+        }
+    }
+
+    /// ルールファイルに記載されているテストを実行する。testsが記載されていないルールは対象外とする
+    pub fn test_file(&mut self, filepath: &str, content: &str) {
+        let docs = match YamlLoader::load_from_str(content) {
+            Ok(docs) => docs,
+            // yml自体のエラーはvalidate-rulesで確認するので、testsを読み込めない場合は対象外とする
+            Err(_) => return,
+        };
+        for doc in docs {
+            if doc["tests"].is_badvalue() {
+                continue;
+            }
+            let failures = self.test_rule(filepath, &doc);
+            self.results.push(RuleTestResult {
+                filepath: filepath.to_string(),
+                title: doc["title"].as_str().unwrap_or_default().to_string(),
+                failures,
+            });
+        }
+    }
+
+    /// 1つのルールのテストを実行し、失敗したテストの内容を返す
+    fn test_rule(&self, filepath: &str, doc: &Yaml) -> Vec<String> {
+        if rule::is_correlation_rule(doc) {
+            return vec!["Tests are not supported for correlation rules.".to_string()];
+        }
+        let positives = match parse_samples(&doc["tests"]["positive"]) {
+            Ok(samples) => samples,
+            Err(e) => return vec![format!("Failed to parse the positive samples. {e}")],
+        };
+        let negatives = match parse_samples(&doc["tests"]["negative"]) {
+            Ok(samples) => samples,
+            Err(e) => return vec![format!("Failed to parse the negative samples. {e}")],
+        };
+        if positives.is_empty() && negatives.is_empty() {
+            return vec!["There are no positive or negative samples in tests.".to_string()];
+        }
+        // aggregation conditionのカウントはRuleNodeに保持されるので、判定毎にルールを読み込み直す
+        let create_rule_node = || -> Result<RuleNode, Vec<String>> {
+            let mut rule_node = rule::create_rule(filepath.to_string(), doc.clone());
+            rule_node.init(self.stored_static)?;
+            Ok(rule_node)
+        };
+        let mut rule_node = match create_rule_node() {
+            Ok(rule_node) => rule_node,
+            Err(err_msgs) => {
+                return err_msgs
+                    .iter()
+                    .map(|e| format!("Failed to parse the rule. {e}"))
+                    .collect()
+            }
+        };
+
+        let mut failures = vec![];
+        if rule_node.has_agg_condition() {
+            if !positives.is_empty() && !self.is_detected(&mut rule_node, &positives) {
+                failures.push(
+                    "The positive samples were not detected by the aggregation condition."
+                        .to_string(),
+                );
+            }
+            if !negatives.is_empty() {
+                let mut rule_node = create_rule_node().unwrap();
+                if self.is_detected(&mut rule_node, &negatives) {
+                    failures.push(
+                        "The negative samples were detected by the aggregation condition."
+                            .to_string(),
+                    );
+                }
+            }
+            return failures;
+        }
+        for (i, sample) in positives.iter().enumerate() {
+            if !self.is_detected(&mut rule_node, std::slice::from_ref(sample)) {
+                failures.push(format!("The positive sample #{} was not detected.", i + 1));
+            }
+        }
+        for (i, sample) in negatives.iter().enumerate() {
+            if self.is_detected(&mut rule_node, std::slice::from_ref(sample)) {
+                failures.push(format!("The negative sample #{} was detected.", i + 1));
+            }
+        }
+        failures
+    }
+
+    /// サンプルイベントを実際のログと同じようにEvtxRecordInfoに変換してルールにかけ、検知されたかを返す
+    fn is_detected(&self, rule_node: &mut RuleNode, samples: &[Value]) -> bool {
+        let keys = get_detection_keys(rule_node);
+        let mut is_selected = false;
+        for sample in samples {
+            let record = utils::create_rec_info(
+                sample.clone(),
+                rule_node.rulepath.to_owned(),
+                &keys,
+                &false,
+                &self.stored_static.no_pwsh_field_extraction,
+            );
+            is_selected |= rule_node.select(
+                &record,
+                self.stored_static.verbose_flag,
+                self.stored_static.quiet_errors_flag,
+                false,
+                &self.stored_static.eventkey_alias,
+            );
+        }
+        if rule_node.has_agg_condition() {
+            return !rule_node
+                .judge_satisfy_aggcondition(self.stored_static)
+                .is_empty();
+        }
+        is_selected
+    }
+
+    /// 1つでも失敗したテストがあればtrueを返す
+    pub fn has_failure(&self) -> bool {
+        self.results.iter().any(|r| !r.failures.is_empty())
+    }
+
+    /// テスト結果を標準出力に出力する
+    pub fn output(&self, no_color: bool) {
+        let stdout = BufferWriter::stdout(ColorChoice::Always);
+        for result in self.results.iter() {
+            let (label, color) = if result.failures.is_empty() {
+                ("[PASS]", Color::Green)
+            } else {
+                ("[FAIL]", Color::Red)
+            };
+            write_color_buffer(
+                &stdout,
+                get_writable_color(Some(color), no_color),
+                label,
+                false,
+            )
+            .ok();
+            let msg = format!(" {} ({})", result.title, result.filepath);
+            write_color_buffer(&stdout, None, &msg, true).ok();
+            for failure in result.failures.iter() {
+                write_color_buffer(&stdout, None, &format!("  - {failure}"), true).ok();
+            }
+        }
+        if !self.results.is_empty() {
+            println!();
+        }
+        let failed_cnt = self
+            .results
+            .iter()
+            .filter(|r| !r.failures.is_empty())
+            .count();
+        let summary = format!(
+            "Tested rules: {} / Passed: {} / Failed: {}",
+            self.results.len(),
+            self.results.len() - failed_cnt,
+            failed_cnt
+        );
+        write_color_buffer(&stdout, None, &summary, true).ok();
+        println!();
+    }
+}
+
+/// testsのpositive/negativeに記載されたサンプルイベントをJSONのValueに変換する
+fn parse_samples(samples: &Yaml) -> Result<Vec<Value>, String> {
+    let samples = match samples {
+        Yaml::BadValue | Yaml::Null => return Ok(vec![]),
+        Yaml::Array(samples) => samples.iter().collect(),
+        _ => vec![samples],
+    };
+    samples
+        .into_iter()
+        .enumerate()
+        .map(|(i, sample)| match sample {
+            Yaml::String(json_str) => serde_json::from_str(json_str)
+                .map_err(|e| format!("Sample #{} is not valid JSON. {e}", i + 1)),
+            Yaml::Hash(_) => Ok(yaml_to_json(sample)),
+            _ => Err(format!(
+                "Sample #{} must be a mapping or a JSON string.",
+                i + 1
+            )),
+        })
+        .collect()
+}
+
+fn yaml_to_json(yaml: &Yaml) -> Value {
+    match yaml {
+        Yaml::Hash(hash) => Value::Object(
+            hash.iter()
+                .map(|(key, val)| {
+                    let key = match key {
+                        Yaml::String(s) => s.to_owned(),
+                        Yaml::Integer(i) => i.to_string(),
+                        Yaml::Real(r) => r.to_owned(),
+                        Yaml::Boolean(b) => b.to_string(),
+                        _ => String::default(),
+                    };
+                    (key, yaml_to_json(val))
+                })
+                .collect::<Map<String, Value>>(),
+        ),
+        Yaml::Array(vals) => Value::Array(vals.iter().map(yaml_to_json).collect()),
+        Yaml::String(s) => Value::String(s.to_owned()),
+        Yaml::Integer(i) => Value::from(*i),
+        Yaml::Real(r) => r
+            .parse::<f64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| Value::String(r.to_owned())),
+        Yaml::Boolean(b) => Value::Bool(*b),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RuleTester;
+    use crate::detections::configs::STORED_EKEY_ALIAS;
+    use crate::detections::rule::tests::create_dummy_stored_static;
+
+    fn test_rule_str(rule_str: &str) -> Vec<String> {
+        let stored_static = create_dummy_stored_static();
+        *STORED_EKEY_ALIAS.write().unwrap() = Some(stored_static.eventkey_alias.clone());
+        let mut tester = RuleTester::new(&stored_static);
+        tester.test_file("testpath", rule_str);
+        assert_eq!(tester.results.len(), 1);
+        tester.results[0].failures.clone()
+    }
+
+    #[test]
+    fn test_rule_tests_pass() {
+        let rule_str = r#"
+title: whoami execution
+detection:
+    selection:
+        Event.System.EventID: 1
+        Event.EventData.CommandLine|contains: whoami
+    condition: selection
+tests:
+    positive:
+        - Event:
+              System:
+                  EventID: 1
+              EventData:
+                  CommandLine: 'cmd.exe /c WHOAMI /all'
+        - '{"Event": {"System": {"EventID": 1}, "EventData": {"CommandLine": "whoami"}}}'
+    negative:
+        - Event:
+              System:
+                  EventID: 1
+              EventData:
+                  CommandLine: 'hostname'
+"#;
+        assert!(test_rule_str(rule_str).is_empty());
+    }
+
+    #[test]
+    fn test_rule_tests_fail() {
+        let rule_str = r#"
+title: whoami execution
+detection:
+    selection:
+        Event.System.EventID: 1
+        Event.EventData.CommandLine|contains: whoami
+    condition: selection
+tests:
+    positive:
+        - Event:
+              System:
+                  EventID: 4688
+              EventData:
+                  CommandLine: 'whoami'
+    negative:
+        - Event:
+              System:
+                  EventID: 1
+              EventData:
+                  CommandLine: 'whoami'
+        - '{"Event": '
+"#;
+        assert_eq!(
+            test_rule_str(rule_str),
+            vec!["Failed to parse the negative samples. Sample #2 is not valid JSON. EOF while parsing a value at line 1 column 10".to_string()]
+        );
+        let rule_str = rule_str.replace("        - '{\"Event\": '\n", "");
+        assert_eq!(
+            test_rule_str(&rule_str),
+            vec![
+                "The positive sample #1 was not detected.".to_string(),
+                "The negative sample #1 was detected.".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rule_tests_aggregation() {
+        let rule_str = r#"
+title: many failed logons
+detection:
+    selection:
+        Event.System.EventID: 4625
+    condition: selection | count() >= 2
+    timeframe: 5m
+tests:
+    positive:
+        - '{"Event": {"System": {"EventID": 4625, "TimeCreated_attributes": {"SystemTime": "2024-01-01T00:00:00Z"}}}}'
+        - '{"Event": {"System": {"EventID": 4625, "TimeCreated_attributes": {"SystemTime": "2024-01-01T00:01:00Z"}}}}'
+    negative:
+        - '{"Event": {"System": {"EventID": 4625, "TimeCreated_attributes": {"SystemTime": "2024-01-01T00:00:00Z"}}}}'
+        - '{"Event": {"System": {"EventID": 4625, "TimeCreated_attributes": {"SystemTime": "2024-01-01T01:00:00Z"}}}}'
+"#;
+        assert!(test_rule_str(rule_str).is_empty());
+    }
+
+    #[test]
+    fn test_rule_without_tests() {
+        let stored_static = create_dummy_stored_static();
+        let mut tester = RuleTester::new(&stored_static);
+        tester.test_file(
+            "testpath",
+            "title: no tests\ndetection:\n    selection:\n        EventID: 1\n    condition: selection\n",
+        );
+        assert!(tester.results.is_empty());
+        assert!(!tester.has_failure());
+    }
+}
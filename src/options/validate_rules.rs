@@ -0,0 +1,775 @@
+use crate::detections::configs::StoredStatic;
+use crate::detections::rule;
+use crate::detections::utils::{get_writable_color, write_color_buffer};
+use crate::yaml::ParseYaml;
+use hashbrown::{HashMap, HashSet};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::json;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use termcolor::{BufferWriter, Color, ColorChoice};
+use yaml_rust::{Yaml, YamlLoader};
+
+lazy_static! {
+    static ref TIMEFRAME_REGEX: Regex = Regex::new(r"^\d+[smhd]$").unwrap();
+    static ref CONDITION_TOKEN_REGEX: Regex = Regex::new(r"[\w*]+").unwrap();
+}
+
+const LEVELS: [&str; 5] = ["informational", "low", "medium", "high", "critical"];
+const STATUSES: [&str; 5] = [
+    "stable",
+    "test",
+    "experimental",
+    "deprecated",
+    "unsupported",
+];
+const CONDITION_KEYWORDS: [&str; 7] = ["and", "or", "not", "of", "them", "all", "any"];
+
+/// ルールの検証で見つかった問題の重要度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// ルールの検証で見つかった問題を表す構造体
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleDiagnostic {
+    pub filepath: String,
+    /// 問題が見つかった行番号(1始まり)。行を特定できない場合は0
+    pub line: usize,
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// validate-rulesコマンドでルールファイルを検証する構造体
+pub struct RuleValidator<'a> {
+    stored_static: &'a StoredStatic,
+    // 重複したidを検出するための、idと最初にそのidが使われていたファイルパスのマップ
+    rule_ids: HashMap<String, String>,
+    pub file_cnt: usize,
+    pub diagnostics: Vec<RuleDiagnostic>,
+}
+
+impl<'a> RuleValidator<'a> {
+    pub fn new(stored_static: &'a StoredStatic) -> RuleValidator<'a> {
+        RuleValidator {
+            stored_static,
+            rule_ids: HashMap::new(),
+            file_cnt: 0,
+            diagnostics: vec![],
+        }
+    }
+
+    /// 指定されたディレクトリ配下(またはファイル)のymlファイルを検証する
+    pub fn validate_path(&mut self, path: &Path) {
+        for rule_file in get_rule_files(path) {
+            let filepath = rule_file.display().to_string();
+            match ParseYaml::read_file(rule_file) {
+                Ok(content) => self.validate_file(&filepath, &content),
+                Err(e) => {
+                    self.file_cnt += 1;
+                    self.push(&filepath, 0, Severity::Error, "read-error", e);
+                }
+            }
+        }
+    }
+
+    /// ルールファイルの内容を検証する
+    pub fn validate_file(&mut self, filepath: &str, content: &str) {
+        self.file_cnt += 1;
+        let docs = match YamlLoader::load_from_str(content) {
+            Ok(docs) => docs,
+            Err(e) => {
+                self.push(
+                    filepath,
+                    e.marker().line(),
+                    Severity::Error,
+                    "yaml-parse-error",
+                    format!("Failed to parse yml. {e}"),
+                );
+                return;
+            }
+        };
+        let lines: Vec<&str> = content.lines().collect();
+        for doc in docs.iter().filter(|doc| !doc.is_null()) {
+            self.validate_doc(filepath, &lines, doc);
+        }
+    }
+
+    fn validate_doc(&mut self, filepath: &str, lines: &[&str], doc: &Yaml) {
+        let err_cnt = self.get_error_cnt();
+        self.validate_metadata(filepath, lines, doc);
+        if rule::is_correlation_rule(doc) {
+            if let Some(timespan) = doc["correlation"]["timespan"].as_str() {
+                if !TIMEFRAME_REGEX.is_match(timespan) {
+                    let line = find_line(lines, 0, "timespan:");
+                    self.push(
+                        filepath,
+                        line,
+                        Severity::Error,
+                        "invalid-timeframe",
+                        format!("Timespan is invalid. Input value:{timespan}"),
+                    );
+                }
+            }
+            return;
+        }
+        // フィルタールールはfilter配下をdetectionと同じように検証する
+        let (detection_key, detection) = if !doc["detection"].is_badvalue() {
+            ("detection", &doc["detection"])
+        } else if !doc["filter"].is_badvalue() {
+            ("filter", &doc["filter"])
+        } else {
+            self.push(
+                filepath,
+                0,
+                Severity::Error,
+                "missing-detection",
+                "Detection node was not found.".to_string(),
+            );
+            return;
+        };
+        let detection_line = find_top_level_key(lines, detection_key);
+        self.validate_detection(filepath, lines, detection_line, detection);
+
+        // 個別の検証で問題が見つからなかった場合は、実際にルールを読み込んでエラーがないかを確認する
+        if detection_key == "detection" && self.get_error_cnt() == err_cnt {
+            let mut rule_node = rule::create_rule(filepath.to_string(), doc.clone());
+            if let Err(err_msgs) = rule_node.init(self.stored_static) {
+                for err_msg in err_msgs {
+                    self.push(
+                        filepath,
+                        detection_line,
+                        Severity::Error,
+                        "rule-parse-error",
+                        err_msg,
+                    );
+                }
+            }
+        }
+    }
+
+    /// id、level、statusを検証する
+    fn validate_metadata(&mut self, filepath: &str, lines: &[&str], doc: &Yaml) {
+        if let Some(id) = doc["id"].as_str() {
+            match self.rule_ids.get(id) {
+                Some(first_filepath) => {
+                    let msg = format!("The rule id '{id}' is already used in {first_filepath}.");
+                    let line = find_top_level_key(lines, "id");
+                    self.push(filepath, line, Severity::Error, "duplicate-id", msg);
+                }
+                None => {
+                    self.rule_ids.insert(id.to_string(), filepath.to_string());
+                }
+            }
+        }
+        if !doc["level"].is_badvalue() {
+            let level = doc["level"].as_str().unwrap_or_default();
+            if !LEVELS.contains(&level.to_lowercase().as_str()) {
+                let msg = format!("The level '{level}' is invalid. The level must be informational, low, medium, high or critical.");
+                let line = find_top_level_key(lines, "level");
+                self.push(filepath, line, Severity::Error, "invalid-level", msg);
+            }
+        }
+        if !doc["status"].is_badvalue() {
+            let status = doc["status"].as_str().unwrap_or_default();
+            if !STATUSES.contains(&status) {
+                let msg = format!("The status '{status}' is invalid. The status must be stable, test, experimental, deprecated or unsupported.");
+                let line = find_top_level_key(lines, "status");
+                self.push(filepath, line, Severity::Error, "invalid-status", msg);
+            }
+        }
+    }
+
+    fn validate_detection(
+        &mut self,
+        filepath: &str,
+        lines: &[&str],
+        detection_line: usize,
+        detection: &Yaml,
+    ) {
+        let detection_hash = match detection.as_hash() {
+            Some(hash) => hash,
+            None => {
+                self.push(
+                    filepath,
+                    detection_line,
+                    Severity::Error,
+                    "missing-detection",
+                    "Detection node was not found.".to_string(),
+                );
+                return;
+            }
+        };
+        let from = detection_line.saturating_sub(1);
+        let mut selection_names = vec![];
+        for (key, selection) in detection_hash.iter() {
+            let name = key.as_str().unwrap_or_default();
+            // condition等、特殊なキーワードはselectionではない
+            if name.is_empty() || ["condition", "timeframe", "rules"].contains(&name) {
+                continue;
+            }
+            selection_names.push(name);
+            let line = find_line(lines, from, &format!("{name}:"));
+            self.validate_selection(filepath, lines, line, selection, true);
+        }
+
+        if !detection["timeframe"].is_badvalue() {
+            let timeframe = detection["timeframe"].as_str().unwrap_or_default();
+            if !TIMEFRAME_REGEX.is_match(timeframe) {
+                let line = find_line(lines, from, "timeframe:");
+                self.push(
+                    filepath,
+                    line,
+                    Severity::Error,
+                    "invalid-timeframe",
+                    format!("Timeframe is invalid. Input value:{timeframe}"),
+                );
+            }
+        }
+
+        let condition_line = find_line(lines, from, "condition:");
+        let used_names = match &detection["condition"] {
+            Yaml::String(condition) => {
+                self.validate_condition(filepath, condition_line, condition, &selection_names)
+            }
+            // conditionが指定されていない場合、selectionが一つだけならそのselectionを採用する
+            Yaml::BadValue if selection_names.len() <= 1 => {
+                selection_names.iter().copied().collect()
+            }
+            Yaml::BadValue => {
+                self.push(
+                    filepath,
+                    detection_line,
+                    Severity::Error,
+                    "missing-condition",
+                    "There is no condition node under detection.".to_string(),
+                );
+                return;
+            }
+            _ => {
+                self.push(
+                    filepath,
+                    condition_line,
+                    Severity::Error,
+                    "invalid-condition",
+                    "The condition must be a string.".to_string(),
+                );
+                return;
+            }
+        };
+        for name in selection_names {
+            if !used_names.contains(name) {
+                let line = find_line(lines, from, &format!("{name}:"));
+                self.push(
+                    filepath,
+                    line,
+                    Severity::Warning,
+                    "unused-selection",
+                    format!("The selection '{name}' is not used in the condition."),
+                );
+            }
+        }
+    }
+
+    /// selection配下のフィールド名、modifier、正規表現を検証する
+    fn validate_selection(
+        &mut self,
+        filepath: &str,
+        lines: &[&str],
+        from_line: usize,
+        selection: &Yaml,
+        is_top: bool,
+    ) {
+        match selection {
+            Yaml::Array(children) => {
+                for child in children {
+                    self.validate_selection(filepath, lines, from_line, child, is_top);
+                }
+            }
+            Yaml::Hash(hash) => {
+                for (key, value) in hash.iter() {
+                    let key = match key.as_str() {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    let line = find_line(lines, from_line.saturating_sub(1), key);
+                    let is_nested = value.as_hash().is_some();
+                    let mut elements = key.split('|');
+                    let field = elements.next().unwrap_or_default();
+                    let modifiers: Vec<&str> = elements.collect();
+                    for modifier in modifiers.iter() {
+                        if !rule::is_known_modifier(modifier) {
+                            self.push(
+                                filepath,
+                                line,
+                                Severity::Error,
+                                "unknown-modifier",
+                                format!(
+                                    "An unknown modifier '{modifier}' was specified. key:{key}"
+                                ),
+                            );
+                        }
+                    }
+                    // ネストしたフィールドや、Event.System.EventIDのようなフィールドのパスはaliasで定義する必要がない
+                    if is_top
+                        && !field.is_empty()
+                        && !field.contains('.')
+                        && !is_nested
+                        && self
+                            .stored_static
+                            .eventkey_alias
+                            .get_event_key(field)
+                            .is_none()
+                    {
+                        self.push(
+                            filepath,
+                            line,
+                            Severity::Warning,
+                            "unknown-field",
+                            format!("The field '{field}' is not defined in eventkey_alias.txt."),
+                        );
+                    }
+                    if modifiers.contains(&"re") {
+                        let patterns = match value {
+                            Yaml::Array(values) => {
+                                values.iter().filter_map(|v| v.as_str()).collect()
+                            }
+                            _ => value.as_str().into_iter().collect::<Vec<_>>(),
+                        };
+                        for pattern in patterns {
+                            // ルールの読み込み時と同じサイズの上限で正規表現をコンパイルする
+                            if let Err(e) = rule::build_regex(pattern) {
+                                self.push(
+                                    filepath,
+                                    line,
+                                    Severity::Error,
+                                    "invalid-regex",
+                                    format!(
+                                        "Invalid regular expression '{pattern}'. {e} key:{key}"
+                                    ),
+                                );
+                            }
+                        }
+                    }
+                    if is_nested || value.is_array() {
+                        self.validate_selection(filepath, lines, line, value, is_top && !is_nested);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// conditionで参照しているselectionが定義されているかを検証し、参照されているselectionの一覧を返す
+    fn validate_condition<'b>(
+        &mut self,
+        filepath: &str,
+        condition_line: usize,
+        condition: &str,
+        selection_names: &[&'b str],
+    ) -> HashSet<&'b str> {
+        let mut used_names = HashSet::new();
+        // パイプ以降はaggregation conditionなので対象外
+        let condition = condition.split('|').next().unwrap_or_default();
+        for token in CONDITION_TOKEN_REGEX
+            .find_iter(condition)
+            .map(|m| m.as_str())
+        {
+            if token == "them" {
+                // themは_から始まるselectionを含まない
+                used_names.extend(selection_names.iter().filter(|name| !name.starts_with('_')));
+                continue;
+            }
+            if CONDITION_KEYWORDS.contains(&token) || token.parse::<usize>().is_ok() {
+                continue;
+            }
+            if token.contains('*') {
+                let matched: Vec<&str> = selection_names
+                    .iter()
+                    .copied()
+                    .filter(|name| is_wildcard_match(token, name))
+                    .collect();
+                if matched.is_empty() {
+                    self.push(
+                        filepath,
+                        condition_line,
+                        Severity::Error,
+                        "undefined-selection",
+                        format!("No selection matched to '{token}'."),
+                    );
+                }
+                used_names.extend(matched);
+            } else if let Some(name) = selection_names.iter().find(|name| **name == token) {
+                used_names.insert(*name);
+            } else {
+                self.push(
+                    filepath,
+                    condition_line,
+                    Severity::Error,
+                    "undefined-selection",
+                    format!("The selection '{token}' is not defined in detection."),
+                );
+            }
+        }
+        used_names
+    }
+
+    fn push(
+        &mut self,
+        filepath: &str,
+        line: usize,
+        severity: Severity,
+        code: &'static str,
+        message: String,
+    ) {
+        self.diagnostics.push(RuleDiagnostic {
+            filepath: filepath.to_string(),
+            line,
+            severity,
+            code,
+            message,
+        });
+    }
+
+    fn get_error_cnt(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count()
+    }
+
+    /// 1つでもエラーがあればtrueを返す
+    pub fn has_error(&self) -> bool {
+        self.get_error_cnt() > 0
+    }
+
+    /// 検証結果をJSON形式の文字列で返す
+    pub fn to_json(&self) -> String {
+        let diagnostics: Vec<_> = self
+            .diagnostics
+            .iter()
+            .map(|d| {
+                json!({
+                    "file": d.filepath,
+                    "line": d.line,
+                    "severity": d.severity.as_str(),
+                    "code": d.code,
+                    "message": d.message,
+                })
+            })
+            .collect();
+        let ret = json!({
+            "files": self.file_cnt,
+            "errors": self.get_error_cnt(),
+            "warnings": self.diagnostics.len() - self.get_error_cnt(),
+            "diagnostics": diagnostics,
+        });
+        serde_json::to_string_pretty(&ret).unwrap_or_default()
+    }
+
+    /// 検証結果を標準出力に出力する
+    pub fn output(&self, json_output: bool, no_color: bool) {
+        if json_output {
+            println!("{}", self.to_json());
+            return;
+        }
+        let stdout = BufferWriter::stdout(ColorChoice::Always);
+        for d in self.diagnostics.iter() {
+            let (label, color) = match d.severity {
+                Severity::Error => ("[ERROR]", Color::Red),
+                Severity::Warning => ("[WARN]", Color::Yellow),
+            };
+            write_color_buffer(
+                &stdout,
+                get_writable_color(Some(color), no_color),
+                label,
+                false,
+            )
+            .ok();
+            let msg = format!(" {}:{} {} ({})", d.filepath, d.line, d.message, d.code);
+            write_color_buffer(&stdout, None, &msg, true).ok();
+        }
+        if !self.diagnostics.is_empty() {
+            println!();
+        }
+        let err_cnt = self.get_error_cnt();
+        let summary = format!(
+            "Validated rule files: {} / Errors: {} / Warnings: {}",
+            self.file_cnt,
+            err_cnt,
+            self.diagnostics.len() - err_cnt
+        );
+        write_color_buffer(&stdout, None, &summary, true).ok();
+        if self.diagnostics.is_empty() {
+            write_color_buffer(
+                &stdout,
+                get_writable_color(Some(Color::Green), no_color),
+                "No problems were found.",
+                true,
+            )
+            .ok();
+        }
+        println!();
+    }
+}
+
+/// 指定されたディレクトリ配下(またはファイル)のymlファイルの一覧をパス順で返す
+pub fn get_rule_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        if path.extension().unwrap_or_default() == "yml" {
+            return vec![path.to_path_buf()];
+        }
+        return vec![];
+    }
+    let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return vec![],
+    };
+    entries.sort();
+    entries
+        .into_iter()
+        .filter(|entry| {
+            let entry_str = entry.to_str().unwrap_or_default();
+            // ignore .git folder and tool test yml files in hayabusa-rules.
+            entry.file_name() != Some(OsStr::new(".git"))
+                && !entry_str.contains("rules/tools/sigmac/test_files")
+                && !entry_str.contains("rules\\tools\\sigmac\\test_files")
+        })
+        .flat_map(|entry| get_rule_files(&entry))
+        .collect()
+}
+
+/// 行頭から`key:`で始まる行の行番号(1始まり)を返す。見つからない場合は0を返す
+fn find_top_level_key(lines: &[&str], key: &str) -> usize {
+    let prefix = format!("{key}:");
+    lines
+        .iter()
+        .position(|line| line.starts_with(&prefix))
+        .map(|idx| idx + 1)
+        .unwrap_or_default()
+}
+
+/// from(0始まり)以降で、textを含む最初の行の行番号(1始まり)を返す。見つからない場合は0を返す
+fn find_line(lines: &[&str], from: usize, text: &str) -> usize {
+    lines
+        .iter()
+        .enumerate()
+        .skip(from)
+        .find(|(_, line)| line.contains(text))
+        .map(|(idx, _)| idx + 1)
+        .unwrap_or_default()
+}
+
+/// *をワイルドカードとして、selection名がパターンに一致するかを返す
+fn is_wildcard_match(pattern: &str, name: &str) -> bool {
+    let regex_str = format!(
+        "^{}$",
+        pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*")
+    );
+    Regex::new(&regex_str)
+        .map(|re| re.is_match(name))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RuleValidator, Severity};
+    use crate::detections::configs::load_eventkey_alias;
+    use crate::detections::rule::tests::create_dummy_stored_static;
+
+    fn validate(rules: &[(&str, &str)]) -> Vec<(String, usize, Severity, &'static str)> {
+        let mut stored_static = create_dummy_stored_static();
+        stored_static.eventkey_alias =
+            load_eventkey_alias("test_files/config/validate_rules/eventkey_alias.txt");
+        let mut validator = RuleValidator::new(&stored_static);
+        for (filepath, content) in rules {
+            validator.validate_file(filepath, content);
+        }
+        validator
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.filepath, d.line, d.severity, d.code))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_valid_rule() {
+        let rule_str = r#"title: valid rule
+id: 4d2c8a5b-3e4f-4a6b-9c1d-0e2f3a4b5c6d
+status: test
+level: high
+detection:
+    selection:
+        Channel: Security
+        EventID: 4625
+    filter:
+        TargetUserName|endswith: '$'
+    condition: selection and not filter
+"#;
+        assert!(validate(&[("valid.yml", rule_str)]).is_empty());
+    }
+
+    #[test]
+    fn test_validate_invalid_rule() {
+        let rule_str = r#"title: invalid rule
+id: 4d2c8a5b-3e4f-4a6b-9c1d-0e2f3a4b5c6d
+status: testing
+level: severe
+detection:
+    selection:
+        Channel: Security
+        CommandLine|contanis: 'whoami'
+        ProcessName|re: 'cmd(\.exe'
+        UnknownFieldName: 'test'
+    unused:
+        EventID: 4688
+    condition: selection and notdefined
+    timeframe: 5x
+"#;
+        assert_eq!(
+            validate(&[("invalid.yml", rule_str)]),
+            vec![
+                (
+                    "invalid.yml".to_string(),
+                    4,
+                    Severity::Error,
+                    "invalid-level"
+                ),
+                (
+                    "invalid.yml".to_string(),
+                    3,
+                    Severity::Error,
+                    "invalid-status"
+                ),
+                (
+                    "invalid.yml".to_string(),
+                    8,
+                    Severity::Error,
+                    "unknown-modifier"
+                ),
+                (
+                    "invalid.yml".to_string(),
+                    9,
+                    Severity::Error,
+                    "invalid-regex"
+                ),
+                (
+                    "invalid.yml".to_string(),
+                    10,
+                    Severity::Warning,
+                    "unknown-field"
+                ),
+                (
+                    "invalid.yml".to_string(),
+                    14,
+                    Severity::Error,
+                    "invalid-timeframe"
+                ),
+                (
+                    "invalid.yml".to_string(),
+                    13,
+                    Severity::Error,
+                    "undefined-selection"
+                ),
+                (
+                    "invalid.yml".to_string(),
+                    11,
+                    Severity::Warning,
+                    "unused-selection"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_duplicate_id_and_of_condition() {
+        let rule_str = r#"title: of condition
+id: 4d2c8a5b-3e4f-4a6b-9c1d-0e2f3a4b5c6d
+detection:
+    selection_1:
+        Channel: Security
+    selection_2:
+        Channel: System
+    _helper:
+        EventID: 1
+    condition: 1 of selection_* or all of them or 1 of filter*
+"#;
+        assert_eq!(
+            validate(&[("first.yml", rule_str), ("second.yml", rule_str)]),
+            vec![
+                (
+                    "first.yml".to_string(),
+                    10,
+                    Severity::Error,
+                    "undefined-selection"
+                ),
+                (
+                    "first.yml".to_string(),
+                    8,
+                    Severity::Warning,
+                    "unused-selection"
+                ),
+                ("second.yml".to_string(), 2, Severity::Error, "duplicate-id"),
+                (
+                    "second.yml".to_string(),
+                    10,
+                    Severity::Error,
+                    "undefined-selection"
+                ),
+                (
+                    "second.yml".to_string(),
+                    8,
+                    Severity::Warning,
+                    "unused-selection"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_yaml_parse_error() {
+        let rule_str = "title: broken\ndetection:\n    selection: [\n";
+        let result = validate(&[("broken.yml", rule_str)]);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].2, Severity::Error);
+        assert_eq!(result[0].3, "yaml-parse-error");
+    }
+
+    #[test]
+    fn test_validate_rule_parse_error() {
+        // 個別の検証で見つからないエラーは、ルールの読み込み時のエラーとして出力する
+        let rule_str = r#"title: aggregation error
+detection:
+    selection:
+        Channel: Security
+    condition: selection | count() >
+"#;
+        assert_eq!(
+            validate(&[("agg.yml", rule_str)]),
+            vec![(
+                "agg.yml".to_string(),
+                2,
+                Severity::Error,
+                "rule-parse-error"
+            )]
+        );
+    }
+}
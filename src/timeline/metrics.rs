@@ -352,6 +352,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 common_options: CommonOptions {
                     no_color: false,
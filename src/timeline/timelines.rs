@@ -533,6 +533,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 common_options: CommonOptions {
                     no_color: false,
@@ -721,6 +722,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 common_options: CommonOptions {
                     no_color: false,
@@ -812,6 +814,7 @@ mod tests {
                     live_analysis: false,
                     recover_records: false,
                     timeline_offset: None,
+                    max_archive_member_size: None,
                 },
                 common_options: CommonOptions {
                     no_color: false,
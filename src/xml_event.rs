@@ -0,0 +1,375 @@
+use crate::archive;
+use crate::detections::utils::str_time_to_datetime;
+use encoding_rs_io::DecodeReaderBytes;
+use evtx::{RecordAllocation, SerializedEvtxRecord};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde_json::{Map, Value};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// XMLの要素。子要素とテキストの出現順は変換後のJSONに影響しないので、別々に保持する
+#[derive(Debug, Default)]
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, val)| val.as_str())
+    }
+}
+
+/// wevtutil qe /f:xmlやイベントビューアーの「XML形式で保存」で出力されたXMLからEvent要素を順番に読み込むイテレータ。
+/// Events要素で囲まれた形式と、Event要素が並んでいるだけの形式(wevtutil qe /f:xmlの出力)の両方に対応する。
+/// ファイル全体をメモリ上に読み込まないように、Event要素を1件ずつ読み込む
+pub struct XmlEventReader<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    stack: Vec<XmlElement>,
+    is_finished: bool,
+}
+
+impl<R: BufRead> XmlEventReader<R> {
+    pub fn new(reader: R) -> XmlEventReader<R> {
+        XmlEventReader {
+            reader: Reader::from_reader(reader),
+            buf: vec![],
+            stack: vec![],
+            is_finished: false,
+        }
+    }
+
+    /// 次のEvent要素を読み込む。Event要素以外のルート要素(Events要素を除く)の中身は読み飛ばす
+    fn next_event(&mut self) -> Result<Option<XmlElement>, String> {
+        loop {
+            self.buf.clear();
+            let event = self
+                .reader
+                .read_event_into(&mut self.buf)
+                .map_err(|e| e.to_string())?;
+            match event {
+                Event::Start(start) => {
+                    let element = to_element(&start)?;
+                    self.stack.push(element);
+                }
+                Event::Empty(start) => {
+                    let element = to_element(&start)?;
+                    if let Some(event) = self.close_element(element) {
+                        return Ok(Some(event));
+                    }
+                }
+                Event::End(_) => {
+                    // 開始タグと終了タグの名前が一致することはquick-xmlで確認済み
+                    let element = self.stack.pop().unwrap();
+                    if let Some(event) = self.close_element(element) {
+                        return Ok(Some(event));
+                    }
+                }
+                Event::Text(text) => {
+                    let text = text.unescape().map_err(|e| e.to_string())?;
+                    // Events要素直下のEvent要素間の改行等は不要なので保持しない
+                    match self.stack.last_mut() {
+                        Some(element) if element.name != "Events" => element.text.push_str(&text),
+                        _ => {}
+                    }
+                }
+                Event::CData(cdata) => {
+                    if let Some(element) = self.stack.last_mut() {
+                        element
+                            .text
+                            .push_str(&String::from_utf8_lossy(&cdata.into_inner()));
+                    }
+                }
+                Event::Eof => {
+                    if let Some(element) = self.stack.last() {
+                        return Err(format!("Element <{}> is not closed.", element.name));
+                    }
+                    return Ok(None);
+                }
+                // XML宣言、コメント、DOCTYPE宣言等は読み飛ばす
+                _ => {}
+            }
+        }
+    }
+
+    /// 閉じた要素を親要素に追加する。ルートまたはEvents要素直下のEvent要素の場合は、その要素を返す
+    fn close_element(&mut self, element: XmlElement) -> Option<XmlElement> {
+        let is_event =
+            element.name == "Event" && self.stack.iter().all(|ancestor| ancestor.name == "Events");
+        if is_event {
+            return Some(element);
+        }
+        // Events要素の子要素は1件ずつ返すので、Events要素には追加しない
+        match self.stack.last_mut() {
+            Some(parent) if parent.name != "Events" => parent.children.push(element),
+            _ => {}
+        }
+        None
+    }
+}
+
+impl<R: BufRead> Iterator for XmlEventReader<R> {
+    type Item = Result<Value, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+        match self.next_event() {
+            Ok(Some(event)) => {
+                let mut record = Map::new();
+                insert_element(&mut record, event);
+                Some(Ok(Value::Object(record)))
+            }
+            Ok(None) => {
+                self.is_finished = true;
+                None
+            }
+            Err(e) => {
+                // XMLが壊れている場合は以降のEvent要素を正しく読み込めないので、読み込みを終了する
+                self.is_finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// 開始タグから、要素名と属性のみを持つ要素を作成する
+fn to_element(start: &BytesStart) -> Result<XmlElement, String> {
+    let mut element = XmlElement {
+        name: String::from_utf8_lossy(start.name().as_ref()).to_string(),
+        ..Default::default()
+    };
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| e.to_string())?;
+        let value = attribute.unescape_value().map_err(|e| e.to_string())?;
+        element.attributes.push((
+            String::from_utf8_lossy(attribute.key.as_ref()).to_string(),
+            value.to_string(),
+        ));
+    }
+    Ok(element)
+}
+
+/// evtxクレートと同様に、10進数の整数として表せる値は数値に変換する
+fn to_typed_value(text: String) -> Value {
+    match text.parse::<i64>() {
+        Ok(num) if num.to_string() == text => Value::from(num),
+        _ => match text.parse::<u64>() {
+            Ok(num) if num.to_string() == text => Value::from(num),
+            _ => Value::String(text),
+        },
+    }
+}
+
+/// 同じ名前の要素が複数ある場合は配列にする(例: 名前のない複数のData要素)
+fn insert_value(map: &mut Map<String, Value>, key: String, value: Value) {
+    match map.get_mut(&key) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            map.insert(key, value);
+        }
+    }
+}
+
+/// 要素の内容をJSONの値に変換する。子要素がない場合はテキスト(空の場合はnull)を値とする
+fn element_content(element: XmlElement) -> Value {
+    if element.children.is_empty() {
+        if element.text.is_empty() {
+            return Value::Null;
+        }
+        return to_typed_value(element.text);
+    }
+    let mut map = Map::new();
+    for child in element.children {
+        insert_element(&mut map, child);
+    }
+    if !element.text.trim().is_empty() {
+        map.insert("#text".to_string(), Value::String(element.text));
+    }
+    Value::Object(map)
+}
+
+/// evtxクレートのseparate_json_attributes(true)の出力と同じ形式で、要素をmapに追加する。
+/// 属性は<要素名>_attributesとして要素と同じ階層に追加し、<Data Name="X">はXをキーとする
+fn insert_element(map: &mut Map<String, Value>, mut element: XmlElement) {
+    if element.name == "Data" {
+        if let Some(name) = element.get_attribute("Name").map(|s| s.to_string()) {
+            insert_value(map, name, element_content(element));
+            return;
+        }
+    }
+    let name = std::mem::take(&mut element.name);
+    let attributes = std::mem::take(&mut element.attributes);
+    let has_attributes = !attributes.is_empty();
+    if has_attributes {
+        let attributes = attributes
+            .into_iter()
+            .map(|(key, val)| (key, to_typed_value(val)))
+            .collect();
+        insert_value(map, format!("{name}_attributes"), Value::Object(attributes));
+    }
+    let content = element_content(element);
+    // <Provider Name="..."/>のように属性のみを持つ要素は、属性のみを出力する
+    if !(has_attributes && content.is_null()) {
+        insert_value(map, name, content);
+    }
+}
+
+/// 拡張子が.xmlのファイル(アーカイブのメンバーを含む)の場合にtrueを返す
+pub fn is_xml_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("xml"))
+}
+
+/// Event要素をevtxクレートで読み込んだレコードと同じ形式に変換する。タイムスタンプはTimeCreatedのSystemTime属性から取得する
+fn to_evtx_record(record: Value) -> Result<SerializedEvtxRecord<Value>, String> {
+    let system = &record["Event"]["System"];
+    let event_record_id = system["EventRecordID"].as_u64().unwrap_or_default();
+    let system_time = system["TimeCreated_attributes"]["SystemTime"]
+        .as_str()
+        .unwrap_or_default();
+    let timestamp = str_time_to_datetime(system_time).ok_or_else(|| {
+        format!("Cannot parse the TimeCreated of the event. [EventRecordID:{event_record_id}]")
+    })?;
+    Ok(SerializedEvtxRecord {
+        event_record_id,
+        timestamp,
+        data: record,
+        allocation: RecordAllocation::Normal,
+    })
+}
+
+/// XML形式でエクスポートされたイベントログのファイルを読み込み、evtxファイルと同じ形式のレコードのイテレータを返す
+pub fn read_xml_records(
+    path: &Path,
+) -> Result<impl Iterator<Item = Result<SerializedEvtxRecord<Value>, String>>, String> {
+    // zip等のアーカイブ内のファイル(archive.zip!/path/Security.xml)も読み込めるようにする
+    let file = archive::open_file(path)
+        .map_err(|e| format!("Cannot open file. [file:{}] {e}", path.display()))?;
+    Ok(read_xml_records_from_reader(file))
+}
+
+/// BOMを元にUTF-16(PowerShellのリダイレクトで出力した場合)のテキストをUTF-8に変換しながら、レコードを1件ずつ読み込む
+fn read_xml_records_from_reader<R: Read>(
+    reader: R,
+) -> impl Iterator<Item = Result<SerializedEvtxRecord<Value>, String>> {
+    let decoder = DecodeReaderBytes::new(reader);
+    XmlEventReader::new(BufReader::new(decoder)).map(|record| record.and_then(to_evtx_record))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::xml_event::{read_xml_records, read_xml_records_from_reader, XmlEventReader};
+    use std::path::Path;
+
+    #[test]
+    fn test_xml_event_reader() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Events>
+<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'><System><Provider Name='Microsoft-Windows-Security-Auditing' Guid='{54849625-5478-4994-a5ba-3e3b0328c30d}'/><EventID>4688</EventID><Level>0</Level><Keywords>0x8020000000000000</Keywords><TimeCreated SystemTime='2024-04-01T12:34:56.1234567Z'/><EventRecordID>1234</EventRecordID><Correlation/><Execution ProcessID='4' ThreadID='60'/><Channel>Security</Channel><Computer>WIN-HOST</Computer><Security/></System><EventData><Data Name='NewProcessName'>C:\Windows\System32\cmd.exe</Data><Data Name='CommandLine'>cmd.exe /c "echo &lt;a&gt; &amp; whoami"</Data><Data Name='ParentProcessName'></Data></EventData></Event>
+<!-- comment -->
+<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'><System><EventID Qualifiers='0'>400</EventID><Channel>Windows PowerShell</Channel></System><EventData><Data>Available</Data><Data><![CDATA[HostApplication=powershell.exe <script>]]></Data></EventData><RenderingInfo Culture='en-US'><Message>Engine state is changed.</Message></RenderingInfo></Event>
+</Events>"#;
+        let records: Vec<_> = XmlEventReader::new(xml.as_bytes()).collect();
+        assert_eq!(records.len(), 2);
+        let record = records[0].as_ref().unwrap();
+        assert_eq!(
+            record["Event_attributes"]["xmlns"],
+            "http://schemas.microsoft.com/win/2004/08/events/event"
+        );
+        let system = &record["Event"]["System"];
+        assert_eq!(system["EventID"], 4688);
+        assert_eq!(system["Keywords"], "0x8020000000000000");
+        assert_eq!(
+            system["Provider_attributes"]["Name"],
+            "Microsoft-Windows-Security-Auditing"
+        );
+        assert!(system.get("Provider").is_none());
+        assert_eq!(system["Execution_attributes"]["ProcessID"], 4);
+        assert!(system["Correlation"].is_null());
+        assert_eq!(system["Channel"], "Security");
+        let event_data = &record["Event"]["EventData"];
+        assert_eq!(
+            event_data["NewProcessName"],
+            "C:\\Windows\\System32\\cmd.exe"
+        );
+        assert_eq!(
+            event_data["CommandLine"],
+            "cmd.exe /c \"echo <a> & whoami\""
+        );
+        assert!(event_data["ParentProcessName"].is_null());
+
+        let record = records[1].as_ref().unwrap();
+        assert_eq!(record["Event"]["System"]["EventID"], 400);
+        assert_eq!(
+            record["Event"]["System"]["EventID_attributes"]["Qualifiers"],
+            0
+        );
+        assert_eq!(
+            record["Event"]["EventData"]["Data"],
+            serde_json::json!(["Available", "HostApplication=powershell.exe <script>"])
+        );
+        assert_eq!(
+            record["Event"]["RenderingInfo"]["Message"],
+            "Engine state is changed."
+        );
+    }
+
+    #[test]
+    fn test_xml_event_reader_invalid_xml() {
+        // wevtutil qe /f:xmlの出力のようにEvents要素がない場合も読み込めること
+        let xml = "<Event><System><EventID>1</EventID></System></Event><Event><System><EventID>2</System></Event>";
+        let records: Vec<_> = XmlEventReader::new(xml.as_bytes()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].as_ref().unwrap()["Event"]["System"]["EventID"],
+            1
+        );
+        assert_eq!(
+            records[1].as_ref().unwrap_err(),
+            "Expecting </EventID> found </System>"
+        );
+    }
+
+    #[test]
+    fn test_read_xml_records() {
+        assert!(read_xml_records(Path::new("test_files/evtx/notexists.xml")).is_err());
+        let records: Vec<_> = read_xml_records(Path::new("test_files/evtx/test.xml"))
+            .unwrap()
+            .collect();
+        assert_eq!(records.len(), 2);
+        let record = records[0].as_ref().unwrap();
+        assert_eq!(record.event_record_id, 1);
+        assert_eq!(
+            record.timestamp.to_rfc3339(),
+            "2021-12-23T00:00:00.123456700+00:00"
+        );
+        assert_eq!(record.data["Event"]["System"]["Channel"], "Security");
+    }
+
+    #[test]
+    fn test_read_xml_records_utf16() {
+        // PowerShellのリダイレクトで出力したBOM付きのUTF-16LEのファイルも読み込めること
+        let xml = "<Events><Event><System><EventRecordID>3</EventRecordID><TimeCreated SystemTime='2021-12-23T00:00:00.000Z'/><Channel>セキュリティ</Channel></System></Event></Events>";
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(xml.encode_utf16().flat_map(|c| c.to_le_bytes()));
+        let records: Vec<_> = read_xml_records_from_reader(bytes.as_slice()).collect();
+        assert_eq!(records.len(), 1);
+        let record = records[0].as_ref().unwrap();
+        assert_eq!(record.event_record_id, 3);
+        assert_eq!(record.data["Event"]["System"]["Channel"], "セキュリティ");
+    }
+}
@@ -4,6 +4,7 @@ extern crate yaml_rust;
 use crate::detections::configs::{self, StoredStatic};
 use crate::detections::message::AlertMessage;
 use crate::detections::message::ERROR_LOG_STACK;
+use crate::detections::rule_cache;
 use crate::detections::utils;
 use crate::filter::RuleExclude;
 use compact_str::CompactString;
@@ -24,6 +25,8 @@ pub struct ParseYaml {
     pub exclude_status: HashSet<String>,
     pub level_map: HashMap<String, u128>,
     pub loaded_rule_ids: HashSet<CompactString>,
+    /// レベルやステータス等の読み込み条件で除外されたルール。相関ルールから参照されているルールを探すために保持する
+    pub filtered_files: Vec<(String, yaml_rust::Yaml)>,
 }
 
 impl ParseYaml {
@@ -51,6 +54,7 @@ impl ParseYaml {
                 ("CRITICAL".to_owned(), 5),
             ]),
             loaded_rule_ids: HashSet::new(),
+            filtered_files: Vec::new(),
         }
     }
 
@@ -253,7 +257,8 @@ impl ParseYaml {
             })?;
         }
         let exist_output_opt = stored_static.output_option.is_some();
-        let files = yaml_docs.into_iter().filter_map(|(filepath, yaml_doc)| {
+        // 除外されたルールのうち、読み込み条件で除外されたルールは相関ルールから参照できるようにErr(Some)で返す
+        let results = yaml_docs.into_iter().map(|(filepath, yaml_doc)| {
             //除外されたルールは無視する
             let rule_id = &yaml_doc["id"].as_str();
             if rule_id.is_some() {
@@ -278,14 +283,14 @@ impl ParseYaml {
                     };
 
                     if entry_key == "excluded" || (entry_key == "noisy" && !enable_noisy_rules) {
-                        return Option::None;
+                        return Err(None);
                     }
                 }
                 if let Some(id) = rule_id {
                     if !stored_static.target_ruleids.is_target(id, true) {
                         let entry = self.rule_load_cnt.entry("excluded".into()).or_insert(0);
                         *entry += 1;
-                        return Option::None;
+                        return Err(None);
                     }
                 }
             }
@@ -312,7 +317,7 @@ impl ParseYaml {
                 || (target_level_num != &0_u128 && doc_level_num != target_level_num)
             {
                 up_rule_load_cnt("excluded");
-                return Option::None;
+                return Err(Some((filepath, yaml_doc)));
             }
 
             let status = yaml_doc["status"].as_str();
@@ -323,7 +328,7 @@ impl ParseYaml {
                         || stored_static.include_status.contains(s))
                 {
                     up_rule_load_cnt("excluded");
-                    return Option::None;
+                    return Err(Some((filepath, yaml_doc)));
                 }
                 if exist_output_opt
                     && ((s == "deprecated"
@@ -341,7 +346,7 @@ impl ParseYaml {
                 {
                     // deprecated or unsupported statusで対応するenable-xxx-rules optionが指定されていない場合はステータスのカウントのみ行ったうえで除外する
                     up_rule_status_cnt(s);
-                    return Option::None;
+                    return Err(Some((filepath, yaml_doc)));
                 }
             }
 
@@ -374,13 +379,13 @@ impl ParseYaml {
                     && !include_category.contains(&category_in_rule.to_string())
                 {
                     up_rule_load_cnt("excluded");
-                    return Option::None;
+                    return Err(Some((filepath, yaml_doc)));
                 }
                 if !exclude_category.is_empty()
                     && exclude_category.contains(&category_in_rule.to_string())
                 {
                     up_rule_load_cnt("excluded");
-                    return Option::None;
+                    return Err(Some((filepath, yaml_doc)));
                 }
             }
 
@@ -407,11 +412,11 @@ impl ParseYaml {
                     });
                     if !is_match {
                         up_rule_load_cnt("excluded");
-                        return Option::None;
+                        return Err(Some((filepath, yaml_doc)));
                     }
                 } else {
                     up_rule_load_cnt("excluded");
-                    return Option::None;
+                    return Err(Some((filepath, yaml_doc)));
                 }
             }
 
@@ -438,7 +443,7 @@ impl ParseYaml {
                     });
                     if is_match {
                         up_rule_load_cnt("excluded");
-                        return Option::None;
+                        return Err(Some((filepath, yaml_doc)));
                     }
                 }
             }
@@ -457,9 +462,59 @@ impl ParseYaml {
                 println!("Loaded rule: {filepath}");
             }
 
-            Option::Some((filepath, yaml_doc))
+            Ok((filepath, yaml_doc))
         });
-        self.files.extend(files);
+        for result in results {
+            match result {
+                Ok(file) => self.files.push(file),
+                Err(Some(file)) => self.filtered_files.push(file),
+                Err(None) => {}
+            }
+        }
+        io::Result::Ok(String::default())
+    }
+
+    /// ルールの読み込みとフィルタリングの結果をキャッシュファイルから読み込む。
+    /// キャッシュが存在しない場合やルールファイル、読み込み条件が変更されている場合はread_dirで読み込み、結果をキャッシュに保存する
+    pub fn read_dir_with_cache<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        min_level: &str,
+        target_level: &str,
+        exclude_ids: &RuleExclude,
+        stored_static: &StoredStatic,
+        cache_path: &Path,
+    ) -> io::Result<String> {
+        let cache_key = rule_cache::calc_cache_key(
+            path.as_ref(),
+            min_level,
+            target_level,
+            exclude_ids,
+            stored_static,
+        )
+        .ok();
+        if let Some(key) = &cache_key {
+            if rule_cache::load(cache_path, key, self) {
+                if stored_static.verbose_flag {
+                    self.files
+                        .iter()
+                        .for_each(|(filepath, _)| println!("Loaded rule: {filepath}"));
+                }
+                return io::Result::Ok(String::default());
+            }
+        }
+
+        self.read_dir(path, min_level, target_level, exclude_ids, stored_static)?;
+        // 読み込みに失敗したルールがある場合は、次回以降もエラーを出力するためにキャッシュしない
+        if let Some(key) = cache_key {
+            if self.errorrule_count == 0 {
+                if let Err(e) = rule_cache::save(cache_path, &key, self) {
+                    if stored_static.verbose_flag {
+                        AlertMessage::warn(&format!("Failed to save the rule cache. {e}"))?;
+                    }
+                }
+            }
+        }
         io::Result::Ok(String::default())
     }
 }
@@ -695,6 +750,7 @@ mod tests {
     use crate::detections::configs::InputOption;
     use crate::detections::configs::OutputOption;
     use crate::detections::configs::StoredStatic;
+    use crate::detections::rule_cache;
     use crate::filter;
     use crate::yaml;
     use crate::yaml::ParseYaml;
@@ -715,6 +771,7 @@ mod tests {
                         live_analysis: false,
                         recover_records: false,
                         timeline_offset: None,
+                        max_archive_member_size: None,
                     },
                     profile: None,
                     enable_deprecated_rules: false,
@@ -754,6 +811,7 @@ mod tests {
                     enable_unsupported_rules: false,
                     clobber: false,
                     proven_rules: false,
+                    filter_rules: None,
                     include_tag: None,
                     exclude_tag: None,
                     include_category: None,
@@ -767,6 +825,12 @@ mod tests {
                     no_wizard: true,
                     include_status: None,
                     low_memory_mode: false,
+                    rule_profiling: None,
+                    rule_profiling_output: None,
+                    regex_size_limit: None,
+                    regex_dfa_size_limit: None,
+                    follow: false,
+                    rule_cache: false,
                 },
                 geo_ip: None,
                 output: None,
@@ -1227,4 +1291,77 @@ mod tests {
         .unwrap();
         assert_eq!(yaml.files.len(), 5);
     }
+
+    #[test]
+    fn test_read_dir_with_cache() {
+        let path = Path::new("test_files/rules/level_yaml");
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache_path = &cache_dir.path().join("rules.json");
+        let dummy_stored_static = create_dummy_stored_static();
+        let exclude_ids = filter::exclude_ids(&dummy_stored_static);
+        let mut yaml = yaml::ParseYaml::new(&dummy_stored_static);
+        yaml.read_dir_with_cache(
+            path,
+            "MEDIUM",
+            "",
+            &exclude_ids,
+            &dummy_stored_static,
+            cache_path,
+        )
+        .unwrap();
+        assert_eq!(yaml.files.len(), 3);
+
+        // キャッシュから読み込んだ結果がルールファイルから読み込んだ結果と一致すること
+        let key =
+            rule_cache::calc_cache_key(path, "MEDIUM", "", &exclude_ids, &dummy_stored_static)
+                .unwrap();
+        let mut cached_yaml = yaml::ParseYaml::new(&dummy_stored_static);
+        assert!(rule_cache::load(cache_path, &key, &mut cached_yaml));
+        assert_eq!(cached_yaml.files, yaml.files);
+        assert_eq!(cached_yaml.rule_load_cnt, yaml.rule_load_cnt);
+        assert_eq!(cached_yaml.rule_status_cnt, yaml.rule_status_cnt);
+
+        // 読み込み条件が異なる場合はキャッシュを使わない
+        let key =
+            rule_cache::calc_cache_key(path, "", "", &exclude_ids, &dummy_stored_static).unwrap();
+        assert!(!rule_cache::load(
+            cache_path,
+            &key,
+            &mut yaml::ParseYaml::new(&dummy_stored_static)
+        ));
+        let mut yaml = yaml::ParseYaml::new(&dummy_stored_static);
+        yaml.read_dir_with_cache(path, "", "", &exclude_ids, &dummy_stored_static, cache_path)
+            .unwrap();
+        assert_eq!(yaml.files.len(), 5);
+    }
+
+    #[test]
+    fn test_calc_cache_key_with_updated_rule() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let rule_path = dir.join("rule.yml");
+        std::fs::write(&rule_path, "title: test\nlevel: low\n").unwrap();
+        let dummy_stored_static = create_dummy_stored_static();
+        let exclude_ids = filter::exclude_ids(&dummy_stored_static);
+        let calc_key =
+            || rule_cache::calc_cache_key(dir, "", "", &exclude_ids, &dummy_stored_static).unwrap();
+        let key = calc_key();
+        assert_eq!(key, calc_key());
+
+        // ルールファイルが更新された場合はキーが変わる
+        std::fs::write(&rule_path, "title: test\nlevel: high\n").unwrap();
+        let key_updated = calc_key();
+        assert_ne!(key, key_updated);
+
+        // サイズと更新日時が変わらない場合でも、内容が変わればキーが変わる
+        let modified = std::fs::metadata(&rule_path).unwrap().modified().unwrap();
+        std::fs::write(&rule_path, "title: test\nlevel: info\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&rule_path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_ne!(key_updated, calc_key());
+    }
 }